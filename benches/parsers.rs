@@ -0,0 +1,56 @@
+//! Benchmarks for the git output parsers, over the larger fixtures and a
+//! synthetic status of a very dirty work tree.
+//!
+//!     cargo bench --bench parsers
+//!
+//! confit is only a binary, so the parsers are compiled in here by path
+//! rather than linked.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+#[allow(unused)]
+#[path = "../src/git"]
+mod git {
+    pub mod parse;
+}
+
+use git::parse;
+
+const STATUS_LINES: usize = 10_000;
+
+// A status header, then ordinary changes, staged and unstaged, with
+// untracked files among them, each at its own path.
+fn synthetic_status(lines: usize) -> String {
+    let mut status = String::from(concat!(
+        "# branch.oid f6cfc1432035767a5dce1dc0d4126765ffd18289\n",
+        "# branch.head update-dev-go\n",
+        "# branch.upstream origin/update-dev-go\n",
+        "# branch.ab +3 -1\n",
+    ));
+    for n in 0..lines {
+        let line = match n % 3 {
+            0 => format!("1 M. N... 100644 100644 100644 c7f5a96ec3bf2b802ab371b73ded691f6c656331 b720f5091ae62a02ae67bfdf2582efdc35448552 src/module{}/lib.rs\n", n),
+            1 => format!("1 .M N... 100644 100644 100644 0149f74d8da209a2a13f224b553beb8114497813 0149f74d8da209a2a13f224b553beb8114497813 docs/page{}.md\n", n),
+            _ => format!("? scratch/notes{}.txt\n", n),
+        };
+        status.push_str(&line);
+    }
+    status
+}
+
+fn parsers(c: &mut Criterion) {
+    let status = synthetic_status(STATUS_LINES);
+    let for_each_ref = include_str!("../src/git/parse/testdata/mezzo-for-each-ref-formatted");
+    let ls_remote = include_str!("../src/git/parse/testdata/mezzo-ls-remote");
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(status.len() as u64));
+    group.bench_function("status", |b| b.iter(|| parse::status(&status).unwrap()));
+    group.throughput(Throughput::Bytes(for_each_ref.len() as u64));
+    group.bench_function("for_each_ref", |b| b.iter(|| parse::for_each_ref(for_each_ref).unwrap()));
+    group.throughput(Throughput::Bytes(ls_remote.len() as u64));
+    group.bench_function("ls_remote", |b| b.iter(|| parse::ls_remote(ls_remote).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, parsers);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::git::exec::{self, Runner};
+use crate::preserves::CheckDef;
+
+pub const FILENAME: &str = ".confit.toml";
+
+/// Settings read from a .confit.toml file.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+  #[serde(default)]
+  pub check: Vec<CheckDef>,
+  /// Glyphs to draw checks with, by tag, in place of their own.
+  #[serde(default)]
+  pub glyphs: HashMap<String, String>,
+}
+
+/// Loads the config at `path`; a missing file is the same as an empty one.
+pub fn load(path: &Path) -> Result<Config> {
+  match std::fs::read_to_string(path) {
+    Ok(body) => toml::from_str(&body).with_context(|| format!("couldn't parse {}", path.display())),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+    Err(e) => Err(e).with_context(|| format!("couldn't read {}", path.display())),
+  }
+}
+
+/// Defaults from the confit.* keys of git config, beneath the command line,
+/// CONFIT_OPTS and .confit.toml.
+#[derive(Debug, Default, PartialEq)]
+pub struct GitConfig {
+  /// confit.checks: comma-separated tags, from every value of the key.
+  pub checks: Vec<String>,
+  pub format: Option<String>,
+  /// confit.remote: whose refs to check, in place of the current branch's remote.
+  pub remote: Option<String>,
+  /// confit.threshold.<tag>, by tag. Git keys can't hold underscores, so
+  /// confit.threshold.stale-tag sets stale_tag's.
+  pub thresholds: HashMap<String, u16>,
+}
+
+/// Reads the confit.* keys from git config. Keys that aren't set are left
+/// at their defaults, as is everything if git can't be asked.
+pub fn from_git(runner: &impl Runner) -> Result<GitConfig> {
+  let listed = match runner.run(exec::CONFIG) {
+    Ok(ran) if ran.success => ran.stdout,
+    _ => return Ok(GitConfig::default()),
+  };
+  let mut config = GitConfig::default();
+  for entry in listed.split('\0').filter(|entry| !entry.is_empty()) {
+    let (key, value) = entry.split_once('\n').unwrap_or((entry, ""));
+    match key {
+      "confit.checks" => config.checks.extend(
+        value.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from)
+      ),
+      "confit.format" => config.format = Some(value.into()),
+      "confit.remote" => config.remote = Some(value.into()),
+      _ => if let Some(tag) = key.strip_prefix("confit.threshold.") {
+        let threshold = value.parse().with_context(|| format!("git config {}: not a threshold: {:?}", key, value))?;
+        config.thresholds.insert(tag.replace('-', "_"), threshold);
+      },
+    }
+  }
+  Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::git::exec::Ran;
+
+  struct Configured(&'static str, bool);
+
+  impl Runner for Configured {
+    fn run(&self, args: &[&str]) -> exec::Result<Ran> {
+      assert_eq!(args, exec::CONFIG);
+      Ok(Ran { success: self.1, stdout: self.0.into(), stderr: String::new() })
+    }
+  }
+
+  #[test]
+  fn custom_checks_from_toml() {
+    let config: Config = toml::from_str(r#"
+      [[check]]
+      label = "no more than five untracked files"
+      tags = ["few_untracked", "local"]
+      glyph = "?"
+      status_group = 1
+      required_data = ["status"]
+      fail_when = "untracked > 5"
+      severity = "warn"
+
+      [glyphs]
+      tag = "T"
+    "#).unwrap();
+
+    assert_eq!(config.check.len(), 1);
+    assert_eq!(config.check[0].fail_when, "untracked > 5");
+    assert_eq!(config.check[0].glyph, '?');
+    assert_eq!(config.glyphs["tag"], "T");
+  }
+
+  #[test]
+  fn missing_config() {
+    assert!(load(Path::new("no/such/.confit.toml")).unwrap().check.is_empty());
+  }
+
+  #[test]
+  fn defaults_from_git_config() {
+    let config = from_git(&Configured(
+      "confit.checks\npush, stage\0confit.checks\ntag\0confit.format\ncompact\0confit.remote\nupstream\0confit.threshold.stale-tag\n45\0confit.color\nauto\0",
+      true,
+    )).unwrap();
+    assert_eq!(config.checks, vec!["push", "stage", "tag"]);
+    assert_eq!(config.format.as_deref(), Some("compact"));
+    assert_eq!(config.remote.as_deref(), Some("upstream"));
+    assert_eq!(config.thresholds.get("stale_tag"), Some(&45));
+
+    // git config exits 1, saying nothing, when no key matches
+    assert_eq!(from_git(&Configured("", false)).unwrap(), GitConfig::default());
+
+    let e = from_git(&Configured("confit.threshold.stale-tag\na month\0", true)).unwrap_err();
+    assert_eq!(e.to_string(), "git config confit.threshold.stale-tag: not a threshold: \"a month\"");
+  }
+}
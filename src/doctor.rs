@@ -0,0 +1,245 @@
+//! Probes of whether confit can run meaningfully here, as opposed to the
+//! checks, which judge the workspace once it can.
+use std::fmt;
+
+use crate::git::exec::{self, Ran, Runner};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Verdict {
+  Pass,
+  Warn,
+  Fail,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Probe {
+  pub name: &'static str,
+  pub verdict: Verdict,
+  pub message: String,
+}
+
+impl fmt::Display for Verdict {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Verdict::Pass => "pass",
+      Verdict::Warn => "warn",
+      Verdict::Fail => "fail",
+    })
+  }
+}
+
+impl fmt::Display for Probe {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}  {}: {}", self.verdict, self.name, self.message)
+  }
+}
+
+/// Runs every probe, in order.
+pub fn diagnose(runner: &impl Runner) -> Vec<Probe> {
+  vec![
+    git_version(runner),
+    work_tree(runner),
+    remotes(runner),
+    upstream(runner),
+    remote_reachable(runner),
+    shallow(runner),
+    ref_listing(runner),
+  ]
+}
+
+/// Whether any probe found a prerequisite missing.
+pub fn failed(probes: &[Probe]) -> bool {
+  probes.iter().any(|p| p.verdict == Verdict::Fail)
+}
+
+// Runs git, and judges by what it printed on success, or by stderr otherwise.
+fn probe(
+  runner: &impl Runner,
+  name: &'static str,
+  args: &[&str],
+  missing: Verdict,
+  judge: impl FnOnce(&str) -> (Verdict, String),
+) -> Probe {
+  let (verdict, message) = match runner.run(args) {
+    Ok(Ran { success: true, stdout, .. }) => judge(stdout.trim()),
+    Ok(Ran { stderr, .. }) => (missing, stderr.trim().to_string()),
+    Err(_) => (missing, "couldn't run git".to_string()),
+  };
+  Probe { name, verdict, message }
+}
+
+pub fn git_version(runner: &impl Runner) -> Probe {
+  probe(runner, "git", exec::VERSION, Verdict::Fail, |out| (Verdict::Pass, out.to_string()))
+}
+
+pub fn work_tree(runner: &impl Runner) -> Probe {
+  probe(runner, "work tree", exec::INSIDE_WORK_TREE, Verdict::Fail, |out| {
+    if out == "true" {
+      (Verdict::Pass, "inside a git work tree".to_string())
+    } else {
+      (Verdict::Fail, "inside a git directory, but not a work tree".to_string())
+    }
+  })
+}
+
+pub fn remotes(runner: &impl Runner) -> Probe {
+  probe(runner, "remote", exec::REMOTES, Verdict::Warn, |out| {
+    if out.is_empty() {
+      (Verdict::Warn, "no remotes configured; remote checks will fail".to_string())
+    } else {
+      (Verdict::Pass, out.lines().collect::<Vec<_>>().join(", "))
+    }
+  })
+}
+
+pub fn upstream(runner: &impl Runner) -> Probe {
+  probe(runner, "upstream", exec::UPSTREAM, Verdict::Warn, |out| (Verdict::Pass, out.to_string()))
+}
+
+pub fn remote_reachable(runner: &impl Runner) -> Probe {
+  probe(runner, "ls-remote", exec::LS_REMOTE, Verdict::Warn, |out| {
+    (Verdict::Pass, format!("remote reachable, {} refs", out.lines().count()))
+  })
+}
+
+/// Whether git says the repository is a shallow clone. If it can't say, as
+/// before git 2.15, it's taken not to be.
+pub fn is_shallow(runner: &impl Runner) -> bool {
+  matches!(runner.run(exec::IS_SHALLOW), Ok(Ran { success: true, stdout, .. }) if stdout.trim() == "true")
+}
+
+pub fn shallow(runner: &impl Runner) -> Probe {
+  probe(runner, "history", exec::IS_SHALLOW, Verdict::Warn, |out| {
+    if out == "true" {
+      (Verdict::Warn, "shallow clone; commits ahead, behind and since tags may be miscounted (git fetch --unshallow)".to_string())
+    } else {
+      (Verdict::Pass, "complete".to_string())
+    }
+  })
+}
+
+/// Whether git lists local refs with the fields the checks need: quoted by
+/// --shell, or where git refuses --shell, NUL-separated, which confit falls
+/// back to.
+pub fn ref_listing(runner: &impl Runner) -> Probe {
+  let shell = probe(runner, "for-each-ref", exec::FOR_EACH_REF, Verdict::Warn, |_| (Verdict::Pass, "lists refs".to_string()));
+  if shell.verdict == Verdict::Pass || !exec::refuses_shell(&shell.message) {
+    return shell
+  }
+  let mut plain = probe(runner, "for-each-ref", exec::FOR_EACH_REF_PLAIN, Verdict::Warn, |_| {
+    (Verdict::Pass, "lists refs, without --shell".to_string())
+  });
+  if plain.verdict != Verdict::Pass {
+    plain.message = format!("git is too old for the checks that need local refs, tags among them: {}", plain.message)
+  }
+  plain
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Answers each git command with canned output; anything else can't run.
+  struct Canned(Vec<(&'static [&'static str], bool, &'static str)>);
+
+  impl Runner for Canned {
+    fn run(&self, args: &[&str]) -> exec::Result<Ran> {
+      self.0.iter()
+        .find(|(cmd, _, _)| *cmd == args)
+        .map(|&(_, success, out)| Ran {
+          success,
+          stdout: if success { out.into() } else { String::new() },
+          stderr: if success { String::new() } else { out.into() },
+        })
+        .ok_or(exec::Error::FailToExec)
+    }
+  }
+
+  #[test]
+  fn healthy_workspace() {
+    let runner = Canned(vec![
+      (exec::VERSION, true, "git version 2.31.1\n"),
+      (exec::INSIDE_WORK_TREE, true, "true\n"),
+      (exec::REMOTES, true, "origin\nupstream\n"),
+      (exec::UPSTREAM, true, "origin/main\n"),
+      (exec::LS_REMOTE, true, "d4ae7077d4ed711a10e89908ab91999ce326dfc0\trefs/heads/main\n"),
+      (exec::IS_SHALLOW, true, "false\n"),
+      (exec::FOR_EACH_REF, true, "'d4ae7077d4ed711a10e89908ab91999ce326dfc0' '' 'commit' 'refs/heads/main' '' '' '' 'Judson <nyarly@gmail.com> 1624060800 -0700' ''\n"),
+    ]);
+    let probes = diagnose(&runner);
+    assert!(probes.iter().all(|p| p.verdict == Verdict::Pass), "{:?}", probes);
+    assert!(!failed(&probes));
+    assert_eq!(probes[0].to_string(), "pass  git: git version 2.31.1");
+    assert_eq!(probes[2].message, "origin, upstream");
+  }
+
+  #[test]
+  fn missing_git() {
+    let runner = Canned(vec![]);
+    assert_eq!(git_version(&runner).verdict, Verdict::Fail);
+    assert_eq!(git_version(&runner).message, "couldn't run git");
+    assert!(failed(&diagnose(&runner)));
+  }
+
+  #[test]
+  fn outside_work_tree() {
+    let runner = Canned(vec![
+      (exec::INSIDE_WORK_TREE, false, "fatal: not a git repository (or any of the parent directories): .git\n"),
+    ]);
+    let probe = work_tree(&runner);
+    assert_eq!(probe.verdict, Verdict::Fail);
+    assert_eq!(probe.message, "fatal: not a git repository (or any of the parent directories): .git");
+
+    let bare = Canned(vec![(exec::INSIDE_WORK_TREE, true, "false\n")]);
+    assert_eq!(work_tree(&bare).verdict, Verdict::Fail);
+  }
+
+  #[test]
+  fn soft_failures_warn() {
+    let runner = Canned(vec![
+      (exec::REMOTES, true, ""),
+      (exec::UPSTREAM, false, "fatal: no upstream configured for branch 'main'\n"),
+      (exec::LS_REMOTE, false, "fatal: No remote configured to list refs from.\n"),
+    ]);
+    assert_eq!(remotes(&runner).verdict, Verdict::Warn);
+    assert_eq!(upstream(&runner).verdict, Verdict::Warn);
+    assert_eq!(remote_reachable(&runner).verdict, Verdict::Warn);
+  }
+
+  #[test]
+  fn shallow_clone_warns() {
+    let runner = Canned(vec![(exec::IS_SHALLOW, true, "true\n")]);
+    assert_eq!(shallow(&runner).verdict, Verdict::Warn);
+    assert!(is_shallow(&runner));
+
+    // git before 2.15 echoes the option it doesn't know
+    let old = Canned(vec![(exec::IS_SHALLOW, true, "--is-shallow-repository\n")]);
+    assert_eq!(shallow(&old).verdict, Verdict::Pass);
+    assert!(!is_shallow(&old));
+    assert!(!is_shallow(&Canned(vec![])));
+  }
+
+  #[test]
+  fn ref_listing_falls_back() {
+    let plain = Canned(vec![
+      (exec::FOR_EACH_REF, false, "error: unknown option `shell'\n"),
+      (exec::FOR_EACH_REF_PLAIN, true, ""),
+    ]);
+    assert_eq!(ref_listing(&plain), Probe { name: "for-each-ref", verdict: Verdict::Pass, message: "lists refs, without --shell".into() });
+
+    let ancient = Canned(vec![
+      (exec::FOR_EACH_REF, false, "error: unknown option `shell'\n"),
+      (exec::FOR_EACH_REF_PLAIN, false, "fatal: unknown field name: upstream:remotename\n"),
+    ]);
+    let probe = ref_listing(&ancient);
+    assert_eq!(probe.verdict, Verdict::Warn);
+    assert_eq!(probe.message, "git is too old for the checks that need local refs, tags among them: fatal: unknown field name: upstream:remotename");
+
+    // Failing for any other reason, the listing isn't tried again.
+    let broken = Canned(vec![
+      (exec::FOR_EACH_REF, false, "fatal: bad object refs/heads/main\n"),
+      (exec::FOR_EACH_REF_PLAIN, true, ""),
+    ]);
+    assert_eq!(ref_listing(&broken), Probe { name: "for-each-ref", verdict: Verdict::Warn, message: "fatal: bad object refs/heads/main".into() });
+  }
+}
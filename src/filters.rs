@@ -0,0 +1,161 @@
+//! Tera filters for confit's templates, registered on the built-in templates
+//! and on any given with --template.
+use std::collections::HashMap;
+use chrono::{DateTime, TimeZone, Utc};
+use tera::{Tera, Value, to_value, try_get_value};
+
+pub fn register(tera: &mut Tera) {
+  tera.register_filter("result_glyph", result_glyph);
+  tera.register_filter("label_value", label_value);
+  tera.register_filter("paint", paint);
+  tera.register_filter("reltime", reltime);
+}
+
+/// Renders an item's result as a symbol: `ok` (default ✓) if it passed,
+/// `warn` (default ⚠) if it warned, and `fail` (default ✗) if it failed,
+/// followed by the count of problems if there is one.
+///
+///     {{ item.result | result_glyph(ok="+", fail="-", warn="~") }}
+pub fn result_glyph(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+  let ok = match args.get("ok") {
+    Some(val) => try_get_value!("result_glyph", "ok", String, val),
+    None => "✓".to_string(),
+  };
+  let fail = match args.get("fail") {
+    Some(val) => try_get_value!("result_glyph", "fail", String, val),
+    None => "✗".to_string(),
+  };
+  let warn = match args.get("warn") {
+    Some(val) => try_get_value!("result_glyph", "warn", String, val),
+    None => "⚠".to_string(),
+  };
+
+  let glyph = match value {
+    Value::String(s) if s == "passed" => ok,
+    Value::String(s) if s == "failed" => fail,
+    Value::Object(o) if o.contains_key("bad") => format!("{}{}", fail, o["bad"]),
+    Value::Object(o) if o.contains_key("warn") => match &o["warn"] {
+      Value::Null => warn,
+      n => format!("{}{}", warn, n),
+    },
+    _ => return Err(format!("result_glyph expects a check result, got {}", value).into()),
+  };
+  Ok(to_value(glyph)?)
+}
+
+/// Escapes a string for use as a Prometheus label value, between double
+/// quotes: backslashes, double quotes and newlines are backslash escaped.
+///
+///     confit_check{tag="{{ tag | label_value }}"}
+pub fn label_value(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+  let s = try_get_value!("label_value", "value", String, value);
+  Ok(to_value(s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))?)
+}
+
+/// Wraps a string in the ANSI escapes for a terminal color: one of `red`,
+/// `green` or `yellow`.
+///
+///     {% if color %}{{ "⚠" | paint(color="yellow") }}{% endif %}
+pub fn paint(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+  let s = try_get_value!("paint", "value", String, value);
+  let code = match args.get("color") {
+    Some(val) => match try_get_value!("paint", "color", String, val).as_str() {
+      "red" => 31,
+      "green" => 32,
+      "yellow" => 33,
+      other => return Err(format!("paint doesn't know the color {}", other).into()),
+    },
+    None => return Err("paint needs a color".into()),
+  };
+  Ok(to_value(format!("\x1b[{}m{}\x1b[0m", code, s))?)
+}
+
+/// Renders a timestamp (RFC 3339, or seconds since the epoch) relative to
+/// now, or to `now` if given, in the largest whole unit: "just now",
+/// "5 minutes ago", "2 days ago", or "in 3 hours" for times to come.
+///
+///     committed {{ newest_unpushed | reltime }}
+pub fn reltime(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+  let when = timestamp("reltime", value)?;
+  let now = match args.get("now") {
+    Some(now) => timestamp("reltime", now)?,
+    None => Utc::now(),
+  };
+  let secs = (now - when).num_seconds();
+  let (n, unit) = [(365 * 86400, "year"), (30 * 86400, "month"), (7 * 86400, "week"), (86400, "day"), (3600, "hour"), (60, "minute")]
+    .iter()
+    .map(|&(size, unit)| (secs.abs() / size, unit))
+    .find(|&(n, _)| n > 0)
+    .unwrap_or((0, "second"));
+  let span = format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+  Ok(to_value(match n {
+    0 => "just now".to_string(),
+    _ if secs < 0 => format!("in {}", span),
+    _ => format!("{} ago", span),
+  })?)
+}
+
+fn timestamp(filter: &str, value: &Value) -> tera::Result<DateTime<Utc>> {
+  let parsed = match value {
+    Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc)),
+    Value::Number(n) => n.as_i64().and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+    _ => None,
+  };
+  parsed.ok_or_else(|| format!("{} expects a timestamp, got {}", filter, value).into())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tera::Context;
+
+  #[test]
+  fn result_glyphs() {
+    let mut tera = Tera::default();
+    register(&mut tera);
+    tera.add_raw_template("glyphs", "{% for r in results %}{{ r | result_glyph }} {% endfor %}{{ results.2 | result_glyph(ok=\"ok\", fail=\"bad\") }}").unwrap();
+    tera.add_raw_template("plural", "{{ n }} file{{ n | pluralize }}").unwrap();
+
+    let mut context = Context::new();
+    context.insert("results", &serde_json::json!(["passed", "failed", {"bad": 3}, {"warn": null}, {"warn": 2}]));
+    assert_eq!(tera.render("glyphs", &context).unwrap(), "✓ ✗ ✗3 ⚠ ⚠2 bad3");
+
+    context.insert("n", &1);
+    assert_eq!(tera.render("plural", &context).unwrap(), "1 file");
+    context.insert("n", &3);
+    assert_eq!(tera.render("plural", &context).unwrap(), "3 files");
+
+    context.insert("results", &serde_json::json!([7]));
+    assert!(tera.render("glyphs", &context).is_err());
+  }
+
+  #[test]
+  fn relative_times() {
+    let mut tera = Tera::default();
+    register(&mut tera);
+    tera.add_raw_template("rel", "{% for t in times %}{{ t | reltime(now=\"2021-07-02T12:00:00Z\") }}; {% endfor %}").unwrap();
+
+    let mut context = Context::new();
+    context.insert("times", &serde_json::json!([
+      "2021-06-30T11:00:00Z", "2021-07-02T11:59:30Z", "2021-07-02T10:45:00+00:00",
+      "2021-05-01T12:00:00Z", "2021-07-02T15:00:00Z", 1625140800,
+    ]));
+    assert_eq!(tera.render("rel", &context).unwrap(), "2 days ago; just now; 1 hour ago; 2 months ago; in 3 hours; 1 day ago; ");
+
+    context.insert("times", &serde_json::json!(["yesterday"]));
+    assert!(tera.render("rel", &context).is_err());
+  }
+
+  #[test]
+  fn label_values() {
+    let mut tera = Tera::default();
+    register(&mut tera);
+    tera.add_raw_template("label", "{{ s | label_value }}").unwrap();
+
+    let mut context = Context::new();
+    context.insert("s", "push");
+    assert_eq!(tera.render("label", &context).unwrap(), "push");
+    context.insert("s", "a \"b\"\\c\nd");
+    assert_eq!(tera.render("label", &context).unwrap(), r#"a \"b\"\\c\nd"#);
+  }
+}
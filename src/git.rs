@@ -4,18 +4,32 @@ pub mod parse;
 pub use parse::ls_remote::RefPair;
 pub use parse::status::Status;
 pub use parse::for_each_ref::RefLine;
+pub use parse::describe::Description;
+pub use parse::numstat::FileStat;
 
 use fake::{Fake, Faker};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use parse::WorkPath;
 use crate::preserves::datasource::{self,Group};
 
 #[derive(Debug)]
 pub enum Error {
   Exec,
   Utf8,
+  Read(String),
   LsRemote(String),
+  NoRemote,
   Status(String),
   ForEachRef(String),
+  Unpushed(String),
+  Describe(String),
+  LsFiles(String),
+  Diff(String),
+  Revision(String),
   Parse(String),
+  NotAWorkTree(String),
 }
 
 impl From<parse::Err<&str>> for Error {
@@ -30,6 +44,12 @@ impl From<std::string::FromUtf8Error> for Error {
   }
 }
 
+impl From<std::io::Error> for Error {
+  fn from(e: std::io::Error) -> Self {
+    Error::Read(format!("{}", e))
+  }
+}
+
 impl From<exec::Error> for Error {
   fn from(_: exec::Error) -> Self {
     Error::Exec
@@ -42,28 +62,94 @@ impl std::fmt::Display for Error {
     match self {
       Exec => write!(f, "problem executing git"),
       Utf8 => write!(f, "utf8 translation error"),
-      LsRemote(s) => write!(f, "ls-remote parse error: {}", s),
-      Status(s) => write!(f, "status parse error: {}", s),
-      ForEachRef(s) => write!(f, "for-each-ref parse error: {}", s),
+      Read(s) => write!(f, "couldn't read captured git output: {}", s),
+      LsRemote(s) => failed(f, "ls-remote", s),
+      NoRemote => write!(f, "no remote configured"),
+      Status(s) => failed(f, "status", s),
+      ForEachRef(s) => failed(f, "for-each-ref", s),
+      Unpushed(s) => failed(f, "log (of unpushed commits)", s),
+      Describe(s) => failed(f, "describe", s),
+      LsFiles(s) => failed(f, "ls-files", s),
+      Diff(s) => failed(f, "diff", s),
+      Revision(s) => write!(f, "'{}' doesn't name a commit", s),
       Parse(s) => write!(f, "parse error: {}", s),
+      NotAWorkTree(s) => write!(f, "not inside a git work tree: {}", s),
     }
   }
 }
 
 impl std::error::Error for Error {}
 
+// The variants for a git command that failed carry everything it wrote to
+// stderr, which is where the explanation (an authentication failure, say) is.
+fn failed(f: &mut std::fmt::Formatter<'_>, command: &str, stderr: &str) -> std::fmt::Result {
+  write!(f, "git {} failed: {}", command, stderr.trim_end())
+}
+
+// git's complaints about credentials or a remote that isn't there: these
+// will fail just the same next time.
+const FATAL: &[&str] = &[
+  "Authentication failed",
+  "Permission denied",
+  "could not read Username",
+  "returned error: 40",
+  "does not appear to be a git repository",
+  "Repository not found",
+];
+
+// ...and about the network, which might do better.
+const TRANSIENT: &[&str] = &[
+  "Could not resolve host",
+  "Temporary failure in name resolution",
+  "Connection timed out",
+  "Operation timed out",
+  "Connection refused",
+  "Connection reset",
+  "early EOF",
+  "remote end hung up unexpectedly",
+];
+
+impl Error {
+  /// Whether contacting the remote failed in a way that trying again might
+  /// fix. Failures git doesn't explain as network trouble are taken as fatal.
+  pub fn is_transient(&self) -> bool {
+    match self {
+      Error::LsRemote(stderr) => {
+        !FATAL.iter().any(|m| stderr.contains(m)) && TRANSIENT.iter().any(|m| stderr.contains(m))
+      }
+      _ => false,
+    }
+  }
+}
+
 type Result<O> = std::result::Result<O, Error>;
 
 pub trait Provider {
   type Data;
   const PROVIDES: Group;
+  const COMMAND: &'static [&'static str];
 
   fn get(&self) -> Result<Self::Data>;
 
+  fn parse(&self, input: &str) -> Result<Self::Data>;
+
   fn empty(&self) -> Self::Data;
 
+  /// Parses output captured from an earlier run of the git command,
+  /// read from a file, or from stdin if the path is "-".
+  fn read_from(&self, path: &str) -> Result<Self::Data> {
+    use std::io::Read;
+    let mut input = String::new();
+    if path == "-" {
+      std::io::stdin().read_to_string(&mut input)?;
+    } else {
+      std::fs::File::open(path)?.read_to_string(&mut input)?;
+    }
+    self.parse(&input)
+  }
+
   fn collect(&self, reqs: Group) -> Result<Self::Data> {
-    if reqs.includes(self.provides()) {
+    if reqs.intersects(self.provides()) {
       self.get()
     } else {
       Ok(self.empty())
@@ -74,19 +160,52 @@ pub trait Provider {
     Self::PROVIDES
   }
 
+  /// The git command `collect` would run for these requirements, if any.
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) {
+      Some(exec::command_line(Self::COMMAND))
+    } else {
+      None
+    }
+  }
+
   fn example(&self) -> Self::Data {
     self.empty()
   }
 }
 
-pub struct LsRemote;
+/// The refs on the remote: the current branch's, or origin, unless another
+/// is named.
+#[derive(Default)]
+pub struct LsRemote {
+  remote: Option<String>,
+}
+
+impl LsRemote {
+  pub fn of(remote: Option<String>) -> Self {
+    LsRemote { remote }
+  }
+}
 
 impl Provider for LsRemote {
   type Data = Vec<RefPair>;
   const PROVIDES: Group = datasource::REMOTE;
+  const COMMAND: &'static [&'static str] = exec::LS_REMOTE;
 
   fn get(&self) -> Result<Self::Data> {
-    exec_and_parse(exec::ls_remote, parse::ls_remote, Error::LsRemote)
+    exec_and_parse(|| exec::ls_remote(self.remote.as_deref()), |out| self.parse(out), ls_remote_error)
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) {
+      Some(exec::command_line(&exec::ls_remote_args(self.remote.as_deref())))
+    } else {
+      None
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::ls_remote(input)?)
   }
 
   fn empty(&self) -> Self::Data {
@@ -94,14 +213,139 @@ impl Provider for LsRemote {
   }
 }
 
-pub struct GetStatus;
+/// Gets data from another provider, trying again after transient failures
+/// up to `retries` times: first after `delay`, then twice as long each time.
+pub struct Retry<P> {
+  provider: P,
+  retries: u32,
+  delay: Duration,
+}
+
+impl<P> Retry<P> {
+  pub fn new(provider: P, retries: u32, delay: Duration) -> Self {
+    Retry { provider, retries, delay }
+  }
+}
+
+impl<P: Provider> Provider for Retry<P> {
+  type Data = P::Data;
+  const PROVIDES: Group = P::PROVIDES;
+  const COMMAND: &'static [&'static str] = P::COMMAND;
+
+  fn get(&self) -> Result<Self::Data> {
+    let mut delay = self.delay;
+    for _ in 0..self.retries {
+      match self.provider.get() {
+        Err(e) if e.is_transient() => {
+          log::debug!("retrying in {:?} after: {}", delay, e);
+          std::thread::sleep(delay);
+          delay *= 2;
+        }
+        result => return result,
+      }
+    }
+    self.provider.get()
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    self.provider.command_line(reqs)
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    self.provider.parse(input)
+  }
+
+  fn empty(&self) -> Self::Data {
+    self.provider.empty()
+  }
+}
+
+// A workspace without any remote isn't broken, just unpublished; any other
+// failure is.
+fn ls_remote_error(stderr: String) -> Error {
+  if stderr.contains("No remote configured") {
+    Error::NoRemote
+  } else {
+    Error::LsRemote(stderr)
+  }
+}
+
+/// The workspace's status; with pathspecs, only files matching them are
+/// reported, though the branch and its tracking are unaffected.
+///
+/// At a revision other than the checkout, there is no work tree to compare,
+/// so the status is only of the revision's branch: its commit, the branch
+/// (or a detached HEAD for tags and commits), and that branch's upstream,
+/// compared as of the last fetch. Every check over files passes.
+#[derive(Default)]
+pub struct GetStatus {
+  pathspecs: Vec<String>,
+  dirty_submodules: bool,
+  ignored: bool,
+  at: Option<String>,
+}
+
+impl GetStatus {
+  pub fn within(pathspecs: Vec<String>) -> Self {
+    GetStatus { pathspecs, ..Default::default() }
+  }
+
+  /// Reports submodules with changed content as modified, even where git is
+  /// configured to ignore them. Otherwise, git's configuration decides.
+  pub fn count_dirty_submodules(&mut self) {
+    self.dirty_submodules = true
+  }
+
+  /// Lists ignored files too, for the ignored check.
+  pub fn list_ignored(&mut self) {
+    self.ignored = true
+  }
+
+  /// Reports on a revision instead of the work tree.
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+
+  fn options(&self) -> Vec<&'static str> {
+    let mut options = vec![];
+    if self.dirty_submodules {
+      options.extend_from_slice(exec::NO_IGNORED_SUBMODULES)
+    }
+    if self.ignored {
+      options.extend_from_slice(exec::IGNORED)
+    }
+    options
+  }
+}
 
 impl Provider for GetStatus {
   type Data = Status;
   const PROVIDES: Group = datasource::STATUS;
+  const COMMAND: &'static [&'static str] = exec::STATUS;
 
   fn get(&self) -> Result<Self::Data> {
-    exec_and_parse(exec::status, parse::status, Error::Status)
+    match &self.at {
+      Some(rev) => status_at(rev),
+      None => exec_and_parse(|| exec::status(&self.options(), &self.pathspecs), |out| self.parse(out), Error::Status),
+    }
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if !reqs.intersects(self.provides()) {
+      return None
+    }
+    match &self.at {
+      Some(rev) => Some([
+        exec::command_line(&exec::with_arg(exec::VERIFY, &format!("{}^{{commit}}", rev))),
+        exec::command_line(&exec::with_arg(exec::SYMBOLIC_NAME, rev)),
+        exec::command_line(&exec::with_arg(exec::TRACKING, "<branch>")),
+      ].join("\n")),
+      None => Some(exec::command_line(&exec::status_args(&self.options(), &self.pathspecs))),
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::status(input)?)
   }
 
   fn empty(&self) -> Self::Data {
@@ -113,14 +357,83 @@ impl Provider for GetStatus {
   }
 }
 
-pub struct ForEachRef;
+fn status_at(rev: &str) -> Result<Status> {
+  let commit = commit_of(rev)?;
+  let refname = exec_and_parse(|| exec::symbolic_name(rev), |out| Ok(out.trim().to_string()), Error::Status)?;
+  let tracking = if refname.starts_with("refs/heads/") {
+    exec_and_parse(|| exec::tracking(&refname), |out| Ok(out.to_string()), Error::Status)?
+  } else {
+    String::new()
+  };
+  Ok(parse::status(&porcelain_at(&commit, &refname, &tracking))?)
+}
+
+// The branch headers `git status --porcelain=v2 --branch` would print, were
+// the revision checked out. `tracking` is the branch's line of exec::TRACKING:
+// its upstream, a tab, then "ahead 1, behind 2", "gone", or nothing when in step.
+fn porcelain_at(commit: &str, refname: &str, tracking: &str) -> String {
+  let mut out = format!("# branch.oid {}\n", commit);
+  match refname.strip_prefix("refs/heads/") {
+    None => out.push_str("# branch.head (detached)\n"),
+    Some(branch) => {
+      out.push_str(&format!("# branch.head {}\n", branch));
+      let mut fields = tracking.trim_end_matches('\n').splitn(2, '\t');
+      if let Some(upstream) = fields.next().filter(|u| !u.is_empty()) {
+        out.push_str(&format!("# branch.upstream {}\n", upstream));
+        let track = fields.next().unwrap_or("");
+        if track != "gone" {
+          let count = |word| track.split(", ").find_map(|part: &str| part.strip_prefix(word)).unwrap_or("0");
+          out.push_str(&format!("# branch.ab +{} -{}\n", count("ahead "), count("behind ")));
+        }
+      }
+    }
+  }
+  out
+}
+
+/// The local refs, or only those under a pattern like `refs/tags/` when the
+/// checks being run don't need the rest.
+#[derive(Default)]
+pub struct ForEachRef {
+  pattern: Option<&'static str>,
+}
+
+impl ForEachRef {
+  pub fn within(pattern: Option<&'static str>) -> Self {
+    ForEachRef { pattern }
+  }
+}
 
 impl Provider for ForEachRef {
   type Data = Vec<RefLine>;
   const PROVIDES: Group = datasource::REFS;
+  const COMMAND: &'static [&'static str] = exec::FOR_EACH_REF;
 
+  // Should git refuse --shell itself, the plain listing is tried instead.
+  // Any other failure is git's own, and reported as it is.
   fn get(&self) -> Result<Self::Data> {
-    exec_and_parse(exec::for_each_ref, parse::for_each_ref, Error::ForEachRef)
+    match exec_and_parse(|| exec::for_each_ref(self.pattern), |out| self.parse(out), Error::ForEachRef) {
+      Err(Error::ForEachRef(stderr)) if exec::refuses_shell(&stderr) => {
+        exec_and_parse(|| exec::for_each_ref_plain(self.pattern), |out| self.parse(out), too_old_for_refs)
+      },
+      result => result,
+    }
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) {
+      Some(format!(
+        "{}\n# where git refuses --shell: {}",
+        exec::command_line(&exec::for_each_ref_args(self.pattern)),
+        exec::command_line(&exec::for_each_ref_plain_args(self.pattern)),
+      ))
+    } else {
+      None
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::for_each_ref(input)?)
   }
 
   fn empty(&self) -> Self::Data {
@@ -128,19 +441,374 @@ impl Provider for ForEachRef {
   }
 }
 
+// A git too old for --shell can also predate some of the fields asked for,
+// when the plain listing fails as well.
+fn too_old_for_refs(stderr: String) -> Error {
+  Error::ForEachRef(format!("{}(your git may be too old for the checks that need local refs, tags among them; see `confit doctor`)\n", stderr))
+}
+
+/// The committer date of the newest commit not yet pushed upstream, or made
+/// since some other revision.
+#[derive(Default)]
+pub struct Unpushed {
+  since: Option<String>,
+  at: Option<String>,
+}
+
+impl Unpushed {
+  pub fn since(rev: String) -> Self {
+    Unpushed { since: Some(rev), ..Default::default() }
+  }
+
+  /// Logs the commits up to a revision instead of HEAD, and by default since
+  /// that revision's upstream.
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+
+  /// Whether the log is of commits since the upstream, so that there's only
+  /// a log to ask for when there's a fetched upstream to be ahead of.
+  pub fn logs_from_upstream(&self) -> bool {
+    self.since.is_none()
+  }
+
+  fn range(&self) -> String {
+    let head = self.at.as_deref().unwrap_or("HEAD");
+    match &self.since {
+      Some(since) => format!("{}..{}", since, head),
+      None => format!("{}@{{upstream}}..{}", self.at.as_deref().unwrap_or(""), head),
+    }
+  }
+}
+
+impl Provider for Unpushed {
+  type Data = Option<DateTime<Utc>>;
+  const PROVIDES: Group = datasource::LOG;
+  const COMMAND: &'static [&'static str] = exec::UNPUSHED;
+
+  fn get(&self) -> Result<Self::Data> {
+    exec_and_parse(|| exec::unpushed(&self.range()), |out| self.parse(out), Error::Unpushed)
+  }
+
+  // As collect_by_cost would, the log from the upstream waits on the status.
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if !reqs.intersects(self.provides()) {
+      None
+    } else if self.logs_from_upstream() {
+      Some(format!(
+        "{}\n# only with commits ahead of the upstream, as git status reports",
+        exec::command_line(&exec::unpushed_args(&self.range()))
+      ))
+    } else {
+      Some(exec::command_line(&exec::unpushed_args(&self.range())))
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::unpushed(input)?)
+  }
+
+  fn empty(&self) -> Self::Data {
+    None
+  }
+}
+
+/// The latest tag reachable from HEAD (or another revision), and how many
+/// commits there have been since. Nothing, rather than an error, when there
+/// are no tags to describe it by.
+#[derive(Default)]
+pub struct Describe {
+  at: Option<String>,
+}
+
+impl Describe {
+  /// Describes a revision instead of HEAD.
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+}
+
+// What git describe says when no tag is an ancestor.
+const NO_TAGS: &[&str] = &["No names found", "No tags can describe"];
+
+impl Provider for Describe {
+  type Data = Option<Description>;
+  const PROVIDES: Group = datasource::DESCRIBE;
+  const COMMAND: &'static [&'static str] = exec::DESCRIBE;
+
+  // A detached HEAD may have no ref to date it by, so the described commit's
+  // date is asked for separately.
+  fn get(&self) -> Result<Self::Data> {
+    let at = self.at.as_deref();
+    let described = match exec_and_parse(|| exec::describe(at), |out| self.parse(out), Error::Describe) {
+      Err(Error::Describe(stderr)) if NO_TAGS.iter().any(|msg| stderr.contains(msg)) => return Ok(None),
+      described => described?,
+    };
+    described.map(|d| {
+      let committed = exec_and_parse(|| exec::commit_date(at), |out| Ok(parse::unpushed(out)?), Error::Describe)?;
+      Ok(Description { committed, ..d })
+    }).transpose()
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) {
+      Some(format!(
+        "{}\n{}",
+        exec::command_line(&exec::describe_args(self.at.as_deref())),
+        exec::command_line(&exec::commit_date_args(self.at.as_deref()))
+      ))
+    } else {
+      None
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::describe(input)?)
+  }
+
+  fn empty(&self) -> Self::Data {
+    None
+  }
+}
+
+/// Tracked files that the ignore rules match. Like the status, there's no
+/// work tree to ask about at another revision, so there are none there.
+#[derive(Default)]
+pub struct TrackedIgnored {
+  at: Option<String>,
+}
+
+impl TrackedIgnored {
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+}
+
+impl Provider for TrackedIgnored {
+  type Data = Vec<WorkPath>;
+  const PROVIDES: Group = datasource::LS_FILES;
+  const COMMAND: &'static [&'static str] = exec::TRACKED_IGNORED;
+
+  fn get(&self) -> Result<Self::Data> {
+    match self.at {
+      Some(_) => Ok(self.empty()),
+      None => exec_and_parse(exec::tracked_ignored, |out| self.parse(out), Error::LsFiles),
+    }
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) && self.at.is_none() {
+      Some(exec::command_line(Self::COMMAND))
+    } else {
+      None
+    }
+  }
+
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(parse::ls_files(input)?)
+  }
+
+  fn empty(&self) -> Self::Data {
+    vec![]
+  }
+}
+
+/// Checks that a revision names a commit, as `git rev-parse --verify` sees it.
+pub fn verify_rev(rev: &str) -> Result<()> {
+  commit_of(rev).map(|_| ())
+}
+
+fn commit_of(rev: &str) -> Result<String> {
+  let commit = format!("{}^{{commit}}", rev);
+  exec_and_parse(|| exec::verify(&commit), |out| Ok(out.trim().to_string()), |_| Error::Revision(rev.into()))
+}
+
 // collect(LsRemote, reqs).unwrap_or_exit(128)
 
+/// Where git keeps a workspace's data. Anything that stores files alongside
+/// git's (hooks, caches) should use these rather than assume `.git` is a
+/// directory: in a linked worktree (`git worktree add`) `.git` is a file,
+/// `git_dir` is private to the worktree, and `common_dir` is shared with the
+/// main checkout.
+#[derive(Debug, PartialEq)]
+pub struct GitDirs {
+  pub git_dir: PathBuf,
+  pub common_dir: PathBuf,
+}
+
+pub fn git_dirs() -> Result<GitDirs> {
+  let cwd = std::env::current_dir()?;
+  exec_and_parse(exec::git_dirs, |out| parse_git_dirs(out, &cwd), Error::NotAWorkTree)
+}
+
+// rev-parse reports each directory on its own line, relative to the
+// current directory unless it's outside it.
+fn parse_git_dirs(output: &str, cwd: &Path) -> Result<GitDirs> {
+  let mut lines = output.lines();
+  match (lines.next(), lines.next()) {
+    (Some(git_dir), Some(common_dir)) => Ok(GitDirs {
+      git_dir: cwd.join(git_dir),
+      common_dir: cwd.join(common_dir),
+    }),
+    _ => Err(Error::NotAWorkTree(format!("unexpected rev-parse output: {:?}", output))),
+  }
+}
+
+/// Operations git can leave half done, waiting on the user to continue or abort.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operation {
+  Merge,
+  Rebase,
+  /// `git am`, applying patches from a mailbox.
+  Am,
+  CherryPick,
+  Revert,
+  Bisect,
+}
+
+impl std::fmt::Display for Operation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      Operation::Merge => "merge",
+      Operation::Rebase => "rebase",
+      Operation::Am => "am",
+      Operation::CherryPick => "cherry-pick",
+      Operation::Revert => "revert",
+      Operation::Bisect => "bisect",
+    })
+  }
+}
+
+// The files git keeps in the (per-worktree) git dir while each is under way.
+// rebase-apply/ serves both rebase and am; am also leaves rebase-apply/applying.
+const IN_PROGRESS_MARKERS: &[(&str, Operation)] = &[
+  ("MERGE_HEAD", Operation::Merge),
+  ("rebase-merge", Operation::Rebase),
+  ("rebase-apply", Operation::Rebase),
+  ("CHERRY_PICK_HEAD", Operation::CherryPick),
+  ("REVERT_HEAD", Operation::Revert),
+  ("BISECT_LOG", Operation::Bisect),
+];
+
+/// The operations in progress, by the files they've left in the git dir.
+pub fn in_progress(git_dir: &Path) -> Vec<Operation> {
+  IN_PROGRESS_MARKERS.iter()
+    .filter(|(marker, _)| git_dir.join(marker).exists())
+    .map(|&(marker, op)| match op {
+      Operation::Rebase if marker == "rebase-apply" && git_dir.join(marker).join("applying").exists() => Operation::Am,
+      op => op,
+    })
+    .collect()
+}
+
+/// Merges, rebases and the like left in progress in the work tree. There's
+/// nothing in progress at another revision.
+#[derive(Default)]
+pub struct InProgress {
+  at: Option<String>,
+}
+
+impl InProgress {
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+}
+
+impl Provider for InProgress {
+  type Data = Vec<Operation>;
+  const PROVIDES: Group = datasource::GIT_DIR;
+  const COMMAND: &'static [&'static str] = exec::GIT_DIRS;
+
+  fn get(&self) -> Result<Self::Data> {
+    match self.at {
+      Some(_) => Ok(self.empty()),
+      None => Ok(in_progress(&git_dirs()?.git_dir)),
+    }
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) && self.at.is_none() {
+      Some(exec::command_line(Self::COMMAND))
+    } else {
+      None
+    }
+  }
+
+  // From rev-parse's report of the git dirs, which are then looked in.
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(in_progress(&parse_git_dirs(input, &std::env::current_dir()?)?.git_dir))
+  }
+
+  fn empty(&self) -> Self::Data {
+    vec![]
+  }
+}
+
+/// Lines added and deleted in each uncommitted file.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DiffStat {
+  pub staged: Vec<FileStat>,
+  pub unstaged: Vec<FileStat>,
+}
+
+/// Counts the lines of uncommitted work, staged and unstaged. There's no
+/// work tree at another revision, so nothing uncommitted there.
+#[derive(Default)]
+pub struct Uncommitted {
+  at: Option<String>,
+}
+
+impl Uncommitted {
+  pub fn at(&mut self, rev: String) {
+    self.at = Some(rev)
+  }
+}
+
+impl Provider for Uncommitted {
+  type Data = DiffStat;
+  const PROVIDES: Group = datasource::DIFF;
+  const COMMAND: &'static [&'static str] = exec::DIFF_STAT;
+
+  fn get(&self) -> Result<Self::Data> {
+    if self.at.is_some() {
+      return Ok(self.empty())
+    }
+    let listing = |cached| exec_and_parse(|| exec::diff_stat(cached), |out| Ok(parse::numstat(out)?), Error::Diff);
+    Ok(DiffStat { staged: listing(true)?, unstaged: listing(false)? })
+  }
+
+  fn command_line(&self, reqs: Group) -> Option<String> {
+    if reqs.intersects(self.provides()) && self.at.is_none() {
+      Some([
+        exec::command_line(&exec::diff_stat_args(true)),
+        exec::command_line(&exec::diff_stat_args(false)),
+      ].join("\n"))
+    } else {
+      None
+    }
+  }
+
+  // A single listing, as captured, is taken for the unstaged changes.
+  fn parse(&self, input: &str) -> Result<Self::Data> {
+    Ok(DiffStat { staged: vec![], unstaged: parse::numstat(input)? })
+  }
+
+  fn empty(&self) -> Self::Data {
+    DiffStat::default()
+  }
+}
+
 fn exec_and_parse<O, E, X, P>(exec: X, parse: P, e: E) -> Result<O>
 where
     X: FnOnce() -> exec::Result<std::process::Output>,
-    P: FnOnce(&str) -> parse::Result<&str, O>,
+    P: FnOnce(&str) -> Result<O>,
     E: FnOnce(String) -> Error,
 {
   let out = exec()?;
 
   if out.status.success() {
     //println!("{}", String::from_utf8_lossy(&out.stdout));
-    Ok(parse(&String::from_utf8(out.stdout)?)?)
+    parse(&String::from_utf8(out.stdout)?)
   } else {
     Err(e(String::from_utf8_lossy(&out.stderr).into_owned()))
   }
@@ -149,6 +817,233 @@ where
 #[cfg(test)]
 mod tests {
   use super::*;
+  // To reproduce by hand: `git worktree add ../feature` in any repository,
+  // then `git rev-parse --git-dir --git-common-dir` from inside ../feature.
+  #[test]
+  fn linked_worktree_dirs() {
+    let cwd = Path::new("/src/feature");
+    assert_eq!(
+      parse_git_dirs("/src/main/.git/worktrees/feature\n/src/main/.git\n", cwd).unwrap(),
+      GitDirs {
+        git_dir: "/src/main/.git/worktrees/feature".into(),
+        common_dir: "/src/main/.git".into(),
+      }
+    );
+    assert_eq!(
+      parse_git_dirs(".git\n.git\n", Path::new("/src/main")).unwrap(),
+      GitDirs { git_dir: "/src/main/.git".into(), common_dir: "/src/main/.git".into() }
+    );
+    assert!(matches!(parse_git_dirs("", cwd), Err(Error::NotAWorkTree(_))));
+  }
+
+  #[test]
+  fn missing_remote() {
+    assert!(matches!(ls_remote_error("fatal: No remote configured to list refs from.\n".into()), Error::NoRemote));
+    assert!(matches!(
+      ls_remote_error("fatal: 'origin' does not appear to be a git repository\n".into()),
+      Error::LsRemote(_)
+    ));
+  }
+
+  // Fails with the given stderr until it has failed `failures` times.
+  struct Flaky {
+    stderr: &'static str,
+    failures: u32,
+    attempts: std::cell::Cell<u32>,
+  }
+
+  impl Flaky {
+    fn new(stderr: &'static str, failures: u32) -> Self {
+      Flaky { stderr, failures, attempts: Default::default() }
+    }
+  }
+
+  impl Provider for Flaky {
+    type Data = Vec<RefPair>;
+    const PROVIDES: Group = datasource::REMOTE;
+    const COMMAND: &'static [&'static str] = exec::LS_REMOTE;
+
+    fn get(&self) -> Result<Self::Data> {
+      self.attempts.set(self.attempts.get() + 1);
+      if self.attempts.get() <= self.failures {
+        Err(ls_remote_error(self.stderr.into()))
+      } else {
+        self.parse("d4ae7077d4ed711a10e89908ab91999ce326dfc0\trefs/heads/main\n")
+      }
+    }
+
+    fn parse(&self, input: &str) -> Result<Self::Data> {
+      LsRemote::default().parse(input)
+    }
+
+    fn empty(&self) -> Self::Data {
+      vec![]
+    }
+  }
+
+  const UNRESOLVED: &str = "fatal: unable to access 'https://example.com/repo.git/': Could not resolve host: example.com\n";
+
+  #[test]
+  fn retries_transient_failures() {
+    let flaky = Retry::new(Flaky::new(UNRESOLVED, 2), 3, Duration::from_millis(0));
+    assert_eq!(flaky.get().unwrap().len(), 1);
+    assert_eq!(flaky.provider.attempts.get(), 3);
+
+    let flaky = Retry::new(Flaky::new(UNRESOLVED, 2), 1, Duration::from_millis(0));
+    assert!(matches!(flaky.get(), Err(Error::LsRemote(_))));
+    assert_eq!(flaky.provider.attempts.get(), 2);
+
+    // by default, there's no second try
+    let flaky = Retry::new(Flaky::new(UNRESOLVED, 2), 0, Duration::from_millis(0));
+    assert!(flaky.get().is_err());
+    assert_eq!(flaky.provider.attempts.get(), 1);
+  }
+
+  #[test]
+  fn fatal_failures_arent_retried() {
+    for stderr in &[
+      "fatal: Authentication failed for 'https://example.com/repo.git/'\n",
+      "fatal: unable to access 'https://example.com/repo.git/': The requested URL returned error: 403\n",
+      "git@example.com: Permission denied (publickey).\nfatal: Could not read from remote repository.\n",
+      "fatal: 'upstream' does not appear to be a git repository\n",
+      "fatal: No remote configured to list refs from.\n",
+      "fatal: something nobody has seen before\n",
+    ] {
+      let flaky = Retry::new(Flaky::new(stderr, 1), 3, Duration::from_millis(0));
+      assert!(flaky.get().is_err(), "{}", stderr);
+      assert_eq!(flaky.provider.attempts.get(), 1, "{}", stderr);
+    }
+  }
+
+  #[test]
+  fn status_within_pathspecs() {
+    assert_eq!(GetStatus::default().command_line(datasource::STATUS).unwrap(), "git status --branch --porcelain=v2");
+    assert_eq!(
+      GetStatus::within(vec!["src/".into()]).command_line(datasource::STATUS).unwrap(),
+      "git status --branch --porcelain=v2 -- src/"
+    );
+    assert_eq!(GetStatus::within(vec!["src/".into()]).command_line(datasource::REMOTE), None);
+
+    let mut submodules = GetStatus::default();
+    submodules.count_dirty_submodules();
+    assert_eq!(
+      submodules.command_line(datasource::STATUS).unwrap(),
+      "git status --branch --porcelain=v2 --ignore-submodules=none"
+    );
+  }
+
+  #[test]
+  fn operations_in_progress() {
+    let git_dir = std::env::temp_dir().join(format!("confit-in-progress-{}", std::process::id()));
+    std::fs::create_dir_all(&git_dir).unwrap();
+    let found = |files: &[&str]| {
+      for file in files {
+        let path = git_dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+      }
+      let ops = in_progress(&git_dir);
+      std::fs::remove_dir_all(&git_dir).unwrap();
+      std::fs::create_dir_all(&git_dir).unwrap();
+      ops
+    };
+
+    assert_eq!(found(&[]), vec![]);
+    assert_eq!(found(&["HEAD", "ORIG_HEAD"]), vec![]);
+    assert_eq!(found(&["MERGE_HEAD", "MERGE_MSG"]), vec![Operation::Merge]);
+    assert_eq!(found(&["rebase-merge/head-name"]), vec![Operation::Rebase]);
+    assert_eq!(found(&["rebase-apply/next"]), vec![Operation::Rebase]);
+    assert_eq!(found(&["rebase-apply/applying"]), vec![Operation::Am]);
+    assert_eq!(found(&["CHERRY_PICK_HEAD"]), vec![Operation::CherryPick]);
+    assert_eq!(found(&["REVERT_HEAD"]), vec![Operation::Revert]);
+    assert_eq!(found(&["BISECT_LOG", "MERGE_HEAD"]), vec![Operation::Merge, Operation::Bisect]);
+    std::fs::remove_dir_all(&git_dir).unwrap();
+
+    assert_eq!(Operation::CherryPick.to_string(), "cherry-pick");
+  }
+
+  #[test]
+  fn failures_carry_stderr() {
+    let stderr = "remote: Invalid username or password.\nfatal: Authentication failed for 'https://example.com/repo.git/'\n";
+    let variants: Vec<fn(String) -> Error> = vec![Error::LsRemote, Error::Status, Error::ForEachRef, Error::Unpushed, Error::Describe, Error::LsFiles];
+    for variant in variants {
+      let message = variant(stderr.into()).to_string();
+      assert!(message.starts_with("git "), "{}", message);
+      assert!(message.ends_with(stderr.trim_end()), "{}", message);
+    }
+    assert_eq!(
+      Error::LsRemote(stderr.into()).to_string(),
+      "git ls-remote failed: remote: Invalid username or password.\nfatal: Authentication failed for 'https://example.com/repo.git/'"
+    );
+  }
+
+  #[test]
+  fn for_each_ref_within() {
+    let listing = ForEachRef::default().command_line(datasource::REFS).unwrap();
+    let lines: Vec<_> = listing.lines().collect();
+    assert!(lines[0].starts_with("git for-each-ref --shell") && lines[0].ends_with("%(taggerdate:raw)'"), "{}", listing);
+    assert!(lines[1].starts_with("# where git refuses --shell: git for-each-ref --format") && lines[1].ends_with("%(taggerdate:raw)"), "{}", listing);
+    let tags = ForEachRef::within(Some("refs/tags/")).command_line(datasource::REFS).unwrap();
+    assert!(tags.lines().all(|line| line.ends_with("%(taggerdate:raw)' refs/tags/") || line.ends_with("%(taggerdate:raw) refs/tags/")), "{}", tags);
+  }
+
+  #[test]
+  fn unpushed_since() {
+    assert_eq!(
+      Unpushed::default().command_line(datasource::LOG).unwrap(),
+      "git log @{upstream}..HEAD -1 --format=%ct\n# only with commits ahead of the upstream, as git status reports"
+    );
+    assert_eq!(Unpushed::default().command_line(datasource::STATUS), None);
+    assert_eq!(
+      Unpushed::since("main".into()).command_line(datasource::LOG).unwrap(),
+      "git log main..HEAD -1 --format=%ct"
+    );
+    assert!(!Unpushed::since("v1.0".into()).logs_from_upstream());
+  }
+
+  #[test]
+  fn status_at_revision() {
+    let commit = "2dff3255ab554f314fc26e7632a0dfdb588d8b76";
+    let branch = porcelain_at(commit, "refs/heads/release", "origin/release\tahead 2, behind 1\n");
+    assert_eq!(branch, include_str!("git/parse/testdata/status-at-branch"));
+    let status = parse::status(&branch).unwrap();
+    let head = status.branch.unwrap();
+    assert_eq!(head.head, parse::status::Head::Branch("release".into()));
+    assert_eq!(head.upstream, Some("origin/release".into()));
+    assert_eq!(head.commits, Some(parse::TrackingCounts(2, 1)));
+    assert!(status.lines.is_empty());
+
+    let tag = parse::status(&porcelain_at(commit, "refs/tags/v1.2", "")).unwrap().branch.unwrap();
+    assert_eq!(tag.head, parse::status::Head::Detached);
+    assert_eq!(tag.upstream, None);
+    let sha = parse::status(&porcelain_at(commit, "", "")).unwrap().branch.unwrap();
+    assert_eq!(sha.head, parse::status::Head::Detached);
+
+    assert!(porcelain_at(commit, "refs/heads/main", "origin/main\t\n").ends_with("# branch.ab +0 -0\n"));
+    assert!(porcelain_at(commit, "refs/heads/main", "origin/main\tbehind 3\n").ends_with("# branch.ab +0 -3\n"));
+    assert!(porcelain_at(commit, "refs/heads/main", "origin/main\tgone\n").ends_with("# branch.upstream origin/main\n"));
+    assert!(porcelain_at(commit, "refs/heads/main", "\t\n").ends_with("# branch.head main\n"));
+
+    let mut at = GetStatus::default();
+    at.at("v1.2".into());
+    assert_eq!(
+      at.command_line(datasource::STATUS).unwrap(),
+      "git rev-parse --verify --quiet v1.2^{commit}\n\
+       git rev-parse --symbolic-full-name v1.2\n\
+       git for-each-ref --format=%(upstream:short)%09%(upstream:track,nobracket) <branch>"
+    );
+  }
+
+  #[test]
+  fn unpushed_at() {
+    let mut at = Unpushed::default();
+    at.at("release".into());
+    assert!(at.command_line(datasource::LOG).unwrap().starts_with("git log release@{upstream}..release -1 --format=%ct\n"));
+    let mut since = Unpushed::since("v1.0".into());
+    since.at("release".into());
+    assert_eq!(since.command_line(datasource::LOG).unwrap(), "git log v1.0..release -1 --format=%ct");
+  }
+
   #[test]
   fn parse_accepts_process_output() {
     let stdout: Vec<u8> = include_str!("git/parse/testdata/mezzo-ls-remote").into();
@@ -12,24 +12,298 @@ impl From<std::io::Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn ls_remote() -> Result<Output> {
-    Ok(Command::new("git").arg("ls-remote").output()?)
-}
-
-pub fn status() -> Result<Output> {
-    Ok(Command::new("git")
-        .arg("status")
-        .arg("--branch")
-        .arg("--porcelain=v2")
-        .output()?)
-}
-
-pub fn for_each_ref() -> Result<Output> {
-    Ok(Command::new("git")
-       .arg("for-each-ref")
-       .arg("--shell") // escapes fields
-       .arg("--format")
-       .arg("%(objectname) %(*objectname) %(objecttype) %(refname) %(upstream) %(upstream:remotename) %(upstream:track) %(creator)")
-       .output()?
-       )
+pub const LS_REMOTE: &[&str] = &["ls-remote"];
+
+pub const STATUS: &[&str] = &["status", "--branch", "--porcelain=v2"];
+
+pub const FOR_EACH_REF: &[&str] = &[
+    "for-each-ref",
+    "--shell", // escapes fields
+    "--format",
+    "%(objectname) %(*objectname) %(objecttype) %(refname) %(upstream) %(upstream:remotename) %(upstream:track) %(creator) %(taggerdate:raw)",
+];
+
+/// The same fields as FOR_EACH_REF, for a git that won't take --shell with
+/// them: separated by NULs, which no field can hold.
+pub const FOR_EACH_REF_PLAIN: &[&str] = &[
+    "for-each-ref",
+    "--format",
+    "%(objectname)%00%(*objectname)%00%(objecttype)%00%(refname)%00%(upstream)%00%(upstream:remotename)%00%(upstream:track)%00%(creator)%00%(taggerdate:raw)",
+];
+
+pub const UNPUSHED: &[&str] = &["log", "@{upstream}..HEAD", "-1", "--format=%ct"];
+
+pub const DESCRIBE: &[&str] = &["describe", "--tags", "--long"];
+
+pub const COMMIT_DATE: &[&str] = &["log", "-1", "--format=%ct"];
+
+/// NUL-terminated, so that paths aren't quoted.
+pub const TRACKED_IGNORED: &[&str] = &["ls-files", "-z", "--ignored", "--cached", "--exclude-standard"];
+
+pub const VERIFY: &[&str] = &["rev-parse", "--verify", "--quiet"];
+
+pub const SYMBOLIC_NAME: &[&str] = &["rev-parse", "--symbolic-full-name"];
+
+pub const TRACKING: &[&str] = &["for-each-ref", "--format=%(upstream:short)%09%(upstream:track,nobracket)"];
+
+/// Paths are NUL-terminated and left unquoted, and renames give both paths
+/// whole, rather than as src/{old.rs => new.rs}.
+pub const DIFF_STAT: &[&str] = &["diff", "--numstat", "-z"];
+
+/// Compares the index with HEAD, for what's staged, rather than the work
+/// tree with the index.
+pub const CACHED: &[&str] = &["--cached"];
+
+pub const GIT_DIRS: &[&str] = &["rev-parse", "--git-dir", "--git-common-dir"];
+
+pub const VERSION: &[&str] = &["--version"];
+
+pub const INSIDE_WORK_TREE: &[&str] = &["rev-parse", "--is-inside-work-tree"];
+
+pub const REMOTES: &[&str] = &["remote"];
+
+pub const IS_SHALLOW: &[&str] = &["rev-parse", "--is-shallow-repository"];
+
+pub const UPSTREAM: &[&str] = &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{upstream}"];
+
+/// Every confit.* setting, each as its key, a newline and its value,
+/// terminated by a NUL. git exits 1 if there are none.
+pub const CONFIG: &[&str] = &["config", "--null", "--get-regexp", "^confit\\."];
+
+pub fn ls_remote(remote: Option<&str>) -> Result<Output> {
+    git(&ls_remote_args(remote))
+}
+
+/// Lists the refs of the named remote, instead of the current branch's (or
+/// origin), if one is given.
+pub fn ls_remote_args(remote: Option<&str>) -> Vec<&str> {
+    match remote {
+        Some(remote) => with_arg(LS_REMOTE, remote),
+        None => LS_REMOTE.to_vec(),
+    }
+}
+
+/// Reports submodules with modified or untracked content even where git is
+/// configured to ignore them (`submodule.<name>.ignore` or `diff.ignoreSubmodules`).
+pub const NO_IGNORED_SUBMODULES: &[&str] = &["--ignore-submodules=none"];
+
+/// Lists ignored files as well, on `!` lines. A directory that's ignored
+/// as a whole is listed once, unless untracked files are listed one by one.
+pub const IGNORED: &[&str] = &["--ignored"];
+
+pub fn status(options: &[&str], pathspecs: &[String]) -> Result<Output> {
+    git(&status_args(options, pathspecs))
+}
+
+/// The status command with extra options, limited to the given pathspecs if
+/// there are any.
+pub fn status_args<'a>(options: &[&'a str], pathspecs: &'a [String]) -> Vec<&'a str> {
+    let mut args = STATUS.to_vec();
+    args.extend_from_slice(options);
+    if !pathspecs.is_empty() {
+        args.push("--");
+        args.extend(pathspecs.iter().map(String::as_str));
+    }
+    args
+}
+
+pub fn for_each_ref(pattern: Option<&str>) -> Result<Output> {
+    git(&for_each_ref_args(pattern))
+}
+
+/// The refs listing, limited to refs under a pattern like `refs/tags/` if
+/// one is given.
+pub fn for_each_ref_args(pattern: Option<&str>) -> Vec<&str> {
+    match pattern {
+        Some(pattern) => with_arg(FOR_EACH_REF, pattern),
+        None => FOR_EACH_REF.to_vec(),
+    }
+}
+
+pub fn for_each_ref_plain(pattern: Option<&str>) -> Result<Output> {
+    git(&for_each_ref_plain_args(pattern))
+}
+
+/// The plain refs listing, limited as for_each_ref_args limits it.
+pub fn for_each_ref_plain_args(pattern: Option<&str>) -> Vec<&str> {
+    match pattern {
+        Some(pattern) => with_arg(FOR_EACH_REF_PLAIN, pattern),
+        None => FOR_EACH_REF_PLAIN.to_vec(),
+    }
+}
+
+/// Whether git failed the refs listing because it doesn't know --shell, as
+/// in "error: unknown option `shell'", rather than for any other reason.
+pub fn refuses_shell(stderr: &str) -> bool {
+    stderr.lines().any(|line| line.contains("unknown option") && line.contains("shell"))
+}
+
+pub fn unpushed(range: &str) -> Result<Output> {
+    git(&unpushed_args(range))
+}
+
+/// The log of unpushed commits, over the given range instead of from the
+/// upstream.
+pub fn unpushed_args(range: &str) -> Vec<&str> {
+    let mut args = UNPUSHED.to_vec();
+    args[1] = range;
+    args
+}
+
+pub fn describe(rev: Option<&str>) -> Result<Output> {
+    git(&describe_args(rev))
+}
+
+/// Describes a revision instead of HEAD, if one is given.
+pub fn describe_args(rev: Option<&str>) -> Vec<&str> {
+    match rev {
+        Some(rev) => with_arg(DESCRIBE, rev),
+        None => DESCRIBE.to_vec(),
+    }
+}
+
+pub fn commit_date(rev: Option<&str>) -> Result<Output> {
+    git(&commit_date_args(rev))
+}
+
+/// The committer date of a revision instead of HEAD, if one is given.
+pub fn commit_date_args(rev: Option<&str>) -> Vec<&str> {
+    match rev {
+        Some(rev) => with_arg(COMMIT_DATE, rev),
+        None => COMMIT_DATE.to_vec(),
+    }
+}
+
+pub fn tracked_ignored() -> Result<Output> {
+    git(TRACKED_IGNORED)
+}
+
+pub fn diff_stat(cached: bool) -> Result<Output> {
+    git(&diff_stat_args(cached))
+}
+
+pub fn diff_stat_args(cached: bool) -> Vec<&'static str> {
+    let mut args = DIFF_STAT.to_vec();
+    if cached {
+        args.extend_from_slice(CACHED);
+    }
+    args
+}
+
+pub fn verify(rev: &str) -> Result<Output> {
+    git(&with_arg(VERIFY, rev))
+}
+
+pub fn symbolic_name(rev: &str) -> Result<Output> {
+    git(&with_arg(SYMBOLIC_NAME, rev))
+}
+
+pub fn tracking(refname: &str) -> Result<Output> {
+    git(&with_arg(TRACKING, refname))
+}
+
+pub fn with_arg<'a>(args: &[&'a str], arg: &'a str) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push(arg);
+    args
+}
+
+pub fn git_dirs() -> Result<Output> {
+    git(GIT_DIRS)
+}
+
+// Failures are told apart by what git says on stderr (no remote, no tags,
+// a flaky network), so it has to say it in English, whatever the locale.
+fn git(args: &[&str]) -> Result<Output> {
+    Ok(Command::new("git").args(args).env("LC_ALL", "C").output()?)
+}
+
+/// What a finished git command reported.
+pub struct Ran {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs git commands: System really does, tests substitute canned output.
+pub trait Runner {
+    fn run(&self, args: &[&str]) -> Result<Ran>;
+}
+
+pub struct System;
+
+impl Runner for System {
+    fn run(&self, args: &[&str]) -> Result<Ran> {
+        let out = git(args)?;
+        Ok(Ran {
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        })
+    }
+}
+
+/// Renders a git invocation as it could be typed into a shell.
+pub fn command_line(args: &[&str]) -> String {
+    std::iter::once("git")
+        .chain(args.iter().copied())
+        .map(|arg| {
+            if arg.contains(|c: char| c.is_whitespace() || c == '\'') {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_quoting() {
+        assert_eq!(command_line(STATUS), "git status --branch --porcelain=v2");
+        assert_eq!(
+            command_line(&["log", "--format", "it's %ct"]),
+            "git log --format 'it'\\''s %ct'"
+        );
+    }
+
+    #[test]
+    fn status_pathspecs() {
+        assert_eq!(status_args(&[], &[]), STATUS);
+        assert_eq!(
+            command_line(&status_args(&[], &["src/".into(), "my docs/".into()])),
+            "git status --branch --porcelain=v2 -- src/ 'my docs/'"
+        );
+        assert_eq!(
+            command_line(&status_args(NO_IGNORED_SUBMODULES, &["src/".into()])),
+            "git status --branch --porcelain=v2 --ignore-submodules=none -- src/"
+        );
+    }
+
+    #[test]
+    fn for_each_ref_pattern() {
+        assert_eq!(for_each_ref_args(None), FOR_EACH_REF);
+        let args = for_each_ref_args(Some("refs/tags/"));
+        assert_eq!(args.last(), Some(&"refs/tags/"));
+        assert_eq!(&args[..FOR_EACH_REF.len()], FOR_EACH_REF);
+        assert_eq!(for_each_ref_plain_args(Some("refs/tags/")).last(), Some(&"refs/tags/"));
+    }
+
+    #[test]
+    fn shell_refusal() {
+        assert!(refuses_shell("error: unknown option `shell'\nusage: git for-each-ref [<options>] [<pattern>]\n"));
+        assert!(refuses_shell("error: unknown option 'shell'\n"));
+        assert!(!refuses_shell("fatal: unknown field name: upstream:remotename\n"));
+        assert!(!refuses_shell("fatal: not a git repository (or any of the parent directories): .git\n"));
+    }
+
+    #[test]
+    fn unpushed_range() {
+        assert_eq!(unpushed_args("@{upstream}..HEAD"), UNPUSHED);
+        assert_eq!(command_line(&unpushed_args("v1.0..HEAD")), "git log v1.0..HEAD -1 --format=%ct");
+    }
 }
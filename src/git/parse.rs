@@ -3,28 +3,39 @@ extern crate nom;
 
 use nom::{
   bytes::complete::{take_till1, take_while_m_n},
+  character::complete::{char, line_ending},
   combinator::map,
+  sequence::terminated,
   IResult,
 };
 use std::{
   error::Error,
   ffi::OsString,
   fmt::{self, Debug, Display},
-  path::PathBuf
+  path::{Path, PathBuf}
 };
 use serde::Serialize;
+use schemars::{JsonSchema, gen::SchemaGenerator, schema::Schema};
 use fake::{Dummy,Fake,Faker,PathFaker,faker::company::en::{BsVerb,BsNoun}};
 use rand::Rng;
 
+pub mod describe;
 pub mod for_each_ref;
+pub mod ls_files;
 pub mod ls_remote;
+pub mod numstat;
 pub mod status;
+pub mod unpushed;
 
+pub use describe::parse as describe;
 pub use for_each_ref::parse as for_each_ref;
+pub use ls_files::parse as ls_files;
 pub use ls_remote::parse as ls_remote;
+pub use numstat::parse as numstat;
 pub use status::parse as status;
+pub use unpushed::parse as unpushed;
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, JsonSchema, Dummy)]
 pub struct ObjectName(String);
 
 impl From<&str> for ObjectName {
@@ -33,7 +44,34 @@ impl From<&str> for ObjectName {
   }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+/// The shortest abbreviation git itself uses; anything shorter is too likely
+/// to name more than one object.
+const MIN_ABBREV: usize = 7;
+
+/// The length of a full SHA-1 name, as git prints it.
+const FULL_LENGTH: usize = 40;
+
+impl ObjectName {
+  /// Whether the names are of the same object, either of them perhaps
+  /// abbreviated: a shorter name matches a longer one it begins, so long as
+  /// it's at least MIN_ABBREV characters.
+  pub fn matches(&self, other: &ObjectName) -> bool {
+    let (short, long) = if self.0.len() <= other.0.len() { (&self.0, &other.0) } else { (&other.0, &self.0) };
+    if short.len() == long.len() {
+      short == long
+    } else {
+      short.len() >= MIN_ABBREV && long.starts_with(short.as_str())
+    }
+  }
+
+  /// Whether the name is shorter than a full one, and so could stand for
+  /// any object it begins.
+  pub fn is_abbreviated(&self) -> bool {
+    self.0.len() < FULL_LENGTH
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, JsonSchema)]
 pub struct RefName(String);
 
 impl Dummy<Faker> for RefName {
@@ -55,7 +93,34 @@ impl AsRef<str> for RefName {
   }
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Clone)]
+// Git names the same ref differently by context: in full
+// ("refs/heads/feature"), short ("feature"), or by remote ("origin/feature").
+impl RefName {
+  /// The name within its namespace: without refs/heads/ or refs/tags/, or
+  /// without refs/remotes/ and the remote. Short names are left as they are.
+  pub fn short(&self) -> &str {
+    let name = self.0.as_str();
+    if let Some(remote_ref) = name.strip_prefix("refs/remotes/") {
+      return without_remote(remote_ref)
+    }
+    ["refs/heads/", "refs/tags/"]
+      .iter()
+      .find_map(|namespace| name.strip_prefix(namespace))
+      .unwrap_or(name)
+  }
+
+  /// The branch an upstream names, whether in full
+  /// ("refs/remotes/origin/feature") or as git status has it ("origin/feature").
+  pub fn upstream_branch(&self) -> &str {
+    without_remote(self.0.strip_prefix("refs/remotes/").unwrap_or(&self.0))
+  }
+}
+
+fn without_remote(name: &str) -> &str {
+  name.split_once('/').map_or(name, |(_, rest)| rest)
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Clone)]
 #[serde(into="String")]
 pub struct WorkPath(OsString);
 
@@ -65,12 +130,36 @@ impl From<&str> for WorkPath {
   }
 }
 
+impl WorkPath {
+  /// The top-level directory the path is in, with a trailing slash; None
+  /// for a file at the top of the work tree.
+  pub fn top_dir(&self) -> Option<WorkPath> {
+    let mut components = Path::new(&self.0).components();
+    let top = components.next()?;
+    components.next()?;
+    let mut dir = top.as_os_str().to_os_string();
+    dir.push("/");
+    Some(WorkPath(dir))
+  }
+}
+
 impl Into<String> for WorkPath {
   fn into(self) -> String {
     self.0.to_string_lossy().into_owned()
   }
 }
 
+// Serialized through String, so described as one.
+impl JsonSchema for WorkPath {
+  fn schema_name() -> String {
+    String::schema_name()
+  }
+
+  fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    String::json_schema(gen)
+  }
+}
+
 impl Dummy<Faker> for WorkPath {
   fn dummy_with_rng<R: Rng + ?Sized>(_: &Faker, rng: &mut R) -> Self {
     WorkPath(OsString::from(PathFaker::new(
@@ -84,7 +173,7 @@ impl Dummy<Faker> for WorkPath {
   }
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, JsonSchema)]
 pub struct TrackingCounts(pub u64, pub u64);
 
 impl Dummy<Faker> for TrackingCounts {
@@ -99,6 +188,7 @@ pub enum Err<I> {
   Failed(nom::Err<(I, nom::error::ErrorKind)>),
   Incomplete(nom::Err<(I, nom::error::ErrorKind)>),
   ParseInt(std::num::ParseIntError),
+  Timestamp(I),
 }
 
 impl<I: Display + Debug> Display for Err<I> {
@@ -117,6 +207,7 @@ impl<I: Display + Debug> Display for Err<I> {
       Incomplete(nom::Err::Incomplete(nom::Needed::Unknown)) => {
         write!(f, "Incomplete, but don't know what's needed")
       }
+      Timestamp(s) => write!(f, "invalid timestamp or zone offset: {}", s),
       otherwise => write!(f, "Unexpected error: {:?}", otherwise),
     }
   }
@@ -166,12 +257,23 @@ fn sha(input: &str) -> IResult<&str, ObjectName> {
   map(take_while_m_n(40, 40, is_hex_digit), |s: &str| ObjectName(s.into()))(input)
 }
 
+// Git on Windows (say, with core.autocrlf) can end lines with "\r\n".
+fn eol(input: &str) -> IResult<&str, &str> {
+  line_ending(input)
+}
+
 fn filepath(input: &str) -> IResult<&str, WorkPath> {
   map(take_till1(end_of_path), WorkPath::from)(input)
 }
 
+/// A path as git gives it with -z: as it is, unquoted, up to the NUL that
+/// ends it.
+fn nul_terminated_path(input: &str) -> IResult<&str, WorkPath> {
+  terminated(map(take_till1(|c| c == '\0'), WorkPath::from), char('\0'))(input)
+}
+
 fn end_of_path(input: char) -> bool {
-  matches!(input,  '\t' | '\n')
+  matches!(input,  '\t' | '\r' | '\n')
 }
 
 /*
@@ -184,3 +286,71 @@ fn end_of_path(input: char) -> bool {
    Err(nom::Err::Incomplete(nom::Needed::Unknown)) =>  Err(format!("Incomplete, but don't know what's needed"))
    }
    */
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use proptest::prelude::*;
+
+  /// Inputs for checking that a parser never panics: anything at all, or
+  /// (more often, so that the search gets past the first few characters) one
+  /// of its fixtures with characters replaced or deleted, then cut short.
+  pub(super) fn garbled(fixtures: &[&'static str]) -> impl Strategy<Value = String> {
+    let damaged = prop::sample::select(fixtures.to_vec()).prop_flat_map(|fixture| {
+      let len = fixture.chars().count();
+      let edit = (0..len.max(1), prop::option::of(prop_oneof![
+        prop::sample::select(vec![' ', '\t', '\n', '\r', '\'', '<', '>', '.', '^', '0', '9', '#', '-', '+']),
+        any::<char>(),
+      ]));
+      (Just(fixture), prop::collection::vec(edit, 0..6), 0..=len)
+    }).prop_map(|(fixture, edits, cut)| {
+      let mut chars: Vec<Option<char>> = fixture.chars().map(Some).collect();
+      for (at, replacement) in edits {
+        if let Some(c) = chars.get_mut(at) {
+          *c = replacement
+        }
+      }
+      chars.into_iter().take(cut).flatten().collect()
+    });
+    prop_oneof![1 => any::<String>(), 4 => damaged]
+  }
+
+  #[test]
+  fn object_names_match() {
+    let full = ObjectName::from("0a03ba3cfde6472cb7431958dd78ca2c0d65de74");
+    assert!(full.matches(&full.clone()));
+    assert!(!full.matches(&ObjectName::from("0a03ba3cfde6472cb7431958dd78ca2c0d65de75")));
+    // prefixes, either way round
+    assert!(full.matches(&ObjectName::from("0a03ba3")));
+    assert!(ObjectName::from("0a03ba3cfde6").matches(&full));
+    assert!(!full.matches(&ObjectName::from("0a03ba4")));
+    // too short to trust
+    assert!(!full.matches(&ObjectName::from("0a03ba")));
+    assert!(!full.matches(&ObjectName::from("")));
+    // equal, however short
+    assert!(ObjectName::from("0a03").matches(&ObjectName::from("0a03")));
+
+    assert!(!full.is_abbreviated());
+    assert!(ObjectName::from("0a03ba3").is_abbreviated());
+  }
+
+  #[test]
+  fn short_ref_names() {
+    let short = |name: &str| RefName::from(name).short().to_string();
+    assert_eq!(short("refs/heads/feature"), "feature");
+    assert_eq!(short("refs/heads/team/feature"), "team/feature");
+    assert_eq!(short("refs/tags/v1.0"), "v1.0");
+    assert_eq!(short("refs/remotes/origin/team/feature"), "team/feature");
+    assert_eq!(short("feature"), "feature");
+    assert_eq!(short("refs/pull/12/head"), "refs/pull/12/head");
+  }
+
+  #[test]
+  fn upstream_branches() {
+    let branch = |name: &str| RefName::from(name).upstream_branch().to_string();
+    assert_eq!(branch("origin/feature"), "feature");
+    assert_eq!(branch("refs/remotes/origin/feature"), "feature");
+    assert_eq!(branch("origin/team/feature"), "team/feature");
+    assert_eq!(branch("main"), "main");
+  }
+}
@@ -0,0 +1,85 @@
+use nom::{
+    character::complete::not_line_ending,
+    combinator::{map_opt, opt},
+    sequence::terminated,
+    IResult,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use schemars::JsonSchema;
+
+use super::{eol, is_digit, is_hex_digit, settle_parse_result, ObjectName};
+
+/*
+ * git describe --tags --long
+ * v1.0-5-gabc123
+ *
+ * The most recent tag reachable from HEAD, how many commits HEAD is past it,
+ * and HEAD's abbreviated name. Tags can contain dashes themselves, so the
+ * line is read from the right. Nothing at all if there are no tags.
+ *
+ * git log -1 --format=%ct
+ * 1633024800
+ *
+ * When there's a tag, the described commit's date too, since no ref need
+ * point at it. That's read as the unpushed log is, so it's left unset here.
+ */
+
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema)]
+pub struct Description {
+    pub tag: String,
+    pub commits: u64,
+    pub object_name: ObjectName,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub committed: Option<DateTime<Utc>>,
+}
+
+pub fn parse(input: &str) -> super::Result<&str, Option<Description>> {
+    settle_parse_result(opt(terminated(describe, eol))(input))
+}
+
+fn describe(input: &str) -> IResult<&str, Description> {
+    map_opt(not_line_ending, |line: &str| {
+        let mut parts = line.rsplitn(3, '-');
+        let (object_name, commits) = (parts.next()?, parts.next()?);
+        let tag = parts.next().filter(|tag| !tag.is_empty())?;
+        let (commits, object_name) = suffix(commits, object_name)?;
+        Some(Description { tag: tag.into(), commits, object_name, committed: None })
+    })(input)
+}
+
+fn suffix(commits: &str, object_name: &str) -> Option<(u64, ObjectName)> {
+    let abbrev = object_name.strip_prefix('g')?;
+    let all = |s: &str, pred: fn(char) -> bool| !s.is_empty() && s.chars().all(pred);
+    if all(commits, is_digit) && all(abbrev, is_hex_digit) {
+        Some((commits.parse().ok()?, abbrev.into()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_parse() {
+        assert_eq!(
+            parse("v1.0-5-gabc123\n").unwrap(),
+            Some(Description { tag: "v1.0".into(), commits: 5, object_name: "abc123".into(), committed: None })
+        );
+        assert_eq!(
+            parse("release-2021-06-0-g0a03ba3\n").unwrap(),
+            Some(Description { tag: "release-2021-06".into(), commits: 0, object_name: "0a03ba3".into(), committed: None })
+        );
+    }
+
+    #[test]
+    fn no_tags_parse() {
+        assert_eq!(parse("").unwrap(), None);
+        assert!(parse("v1.0\n").is_err());
+        assert!(parse("v1.0-five-gabc123\n").is_err());
+        assert!(parse("-5-gabc123\n").is_err());
+    }
+}
@@ -2,6 +2,7 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take_until, take_while},
     combinator::{opt, map, map_res},
+    error::ErrorKind,
     multi::{many0, separated_nonempty_list},
     sequence::{delimited, terminated, tuple},
     //multi::many0,
@@ -9,13 +10,20 @@ use nom::{
 };
 
 use fake::{Dummy,Fake};
-use super::{is_digit, settle_parse_result, sha, ObjectName, RefName, TrackingCounts};
+use super::{eol, is_digit, settle_parse_result, sha, Err, ObjectName, RefName, TrackingCounts};
 use chrono::{DateTime, Utc};
 
 /*
  *
- * git for-each-ref --shell --format "%(objectname) %(*objectname) %(objecttype) %(refname) %(upstream) %(upstream:remotename) %(upstream:track) %(creator)"
- * '8558b6934276f1b9966c01f7b3e5aeea2902742d' 'commit' 'refs/heads/multiple_provisioning' 'refs/remotes/origin/multiple_provisioning' 'origin' '[ahead 1]' 'Judson <nyarly@gmail.com> 1572973200 -0800'
+ * git for-each-ref --shell --format "%(objectname) %(*objectname) %(objecttype) %(refname) %(upstream) %(upstream:remotename) %(upstream:track) %(creator) %(taggerdate:raw)"
+ * '8558b6934276f1b9966c01f7b3e5aeea2902742d' '' 'commit' 'refs/heads/multiple_provisioning' 'refs/remotes/origin/multiple_provisioning' 'origin' '[ahead 1]' 'Judson <nyarly@gmail.com> 1572973200 -0800' ''
+ *
+ * For annotated tags, %(creator) is the tagger, and %(taggerdate:raw) repeats its date;
+ * for every other ref the tagger date is empty.
+ *
+ * Where --shell doesn't work, the same fields are separated by NULs instead (%00 in the
+ * format), without quotes:
+ * 8558b6934276f1b9966c01f7b3e5aeea2902742d\0\0commit\0refs/heads/multiple_provisioning\0...
  */
 
 #[derive(Debug, PartialEq, Eq, Dummy)]
@@ -28,6 +36,7 @@ pub struct RefLine {
     pub creator_name: String,
     pub creator_email: String,
     pub creation_date: DateTime<Utc>,
+    pub tagger_date: Option<DateTime<Utc>>,
 }
 
 // XXX review pulling this up to RefLine
@@ -77,12 +86,43 @@ impl From<(String, RefName, Option<(u64, u64)>)> for TrackSync {
     }
 }
 
+/// Parses either form of the listing: quoted by --shell, or NUL-separated.
 pub fn parse(input: &str) -> super::Result<&str, Vec<RefLine>> {
-    settle_parse_result(many0(terminated(line, tag("\n")))(input))
+    let line: fn(&str) -> IResult<&str, RefLine> = if input.contains('\0') { plain_line } else { line };
+    settle_parse_result(many0(terminated(line, eol))(input)).map_err(|e| match e {
+        Err::Failed(nom::Err::Failure((rest, ErrorKind::Verify))) => {
+            Err::Timestamp(&rest[..rest.find(['\'', '\0', '\r', '\n']).unwrap_or(rest.len())])
+        }
+        e => e,
+    })
+}
+
+// How the fields of a line are set off from each other.
+struct Quoting {
+    open: &'static str,
+    sep: &'static str,
+    close: &'static str,
+    // what ends a field's value
+    end: &'static str,
 }
 
-// '8558b6934276f1b9966c01f7b3e5aeea2902742d' 'commit' 'refs/heads/multiple_provisioning' 'refs/remotes/origin/multiple_provisioning' 'origin' '[ahead 1]' 'Judson <nyarly@gmail.com> 1572973200 -0800'
+const SHELL: Quoting = Quoting { open: "'", sep: "' '", close: "'", end: "'" };
+
+const PLAIN: Quoting = Quoting { open: "", sep: "\0", close: "", end: "\0" };
+
+// '8558b6934276f1b9966c01f7b3e5aeea2902742d' '' 'commit' 'refs/heads/multiple_provisioning' 'refs/remotes/origin/multiple_provisioning' 'origin' '[ahead 1]' 'Judson <nyarly@gmail.com> 1572973200 -0800' ''
 fn line(input: &str) -> IResult<&str, RefLine> {
+    fields(&SHELL, input)
+}
+
+// 8558b6934276f1b9966c01f7b3e5aeea2902742d\0\0commit\0refs/heads/multiple_provisioning\0refs/remotes/origin/multiple_provisioning\0origin\0[ahead 1]\0Judson <nyarly@gmail.com> 1572973200 -0800\0
+fn plain_line(input: &str) -> IResult<&str, RefLine> {
+    fields(&PLAIN, input)
+}
+
+fn fields<'a>(q: &Quoting, input: &'a str) -> IResult<&'a str, RefLine> {
+    let qref = |input: &'a str| map(take_until(q.end), RefName::from)(input);
+    let qstring = |input: &'a str| map(take_until(q.end), String::from)(input);
     let (
         rest,
         (
@@ -103,25 +143,29 @@ fn line(input: &str) -> IResult<&str, RefLine> {
             _,
             (creator_name, creator_email, creation_date),
             _,
+            tagger_date,
+            _,
         ),
     ) = tuple((
-        tag("'"),       // '
+        tag(q.open),    // '
         sha,            // 8558b6934276f1b9966c01f7b3e5aeea2902742d
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         opt(sha),            // 8558b6934276f1b9966c01f7b3e5aeea2902742d
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         object_type,    // commit
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         qref,        // refs/heads/multiple_provisioning
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         qref,        // refs/remotes/origin/multiple_provisioning
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         qstring,        // origin
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         tracking_state, // [ahead 1]
-        tag("' '"),     // ' '
+        tag(q.sep),     // ' '
         creator::parse, // Judson <nyarly@gmail.com> 1572973200 -0800
-        tag("'"),       // '
+        tag(q.sep),     // ' '
+        opt(creator::date), // 1572973200 -0800
+        tag(q.close),   // '
     ))(input)?;
 
     Ok((
@@ -135,18 +179,11 @@ fn line(input: &str) -> IResult<&str, RefLine> {
             creator_name,
             creator_email,
             creation_date,
+            tagger_date,
         },
     ))
 }
 
-fn qref(input: &str) -> IResult<&str, RefName> {
-    map(take_until("'"), RefName::from)(input)
-}
-
-fn qstring(input: &str) -> IResult<&str, String> {
-    map(take_until("'"), String::from)(input)
-}
-
 fn object_type(input: &str) -> IResult<&str, ObjectType> {
     alt((
         map(tag("commit"), |_| ObjectType::Commit),
@@ -192,7 +229,7 @@ mod creator {
     use chrono::{DateTime, FixedOffset, TimeZone, Utc};
     use nom::{
         branch::alt,
-        bytes::complete::{tag, take_until, take_while, take_while_m_n},
+        bytes::complete::{tag, take_till, take_while, take_while_m_n},
         combinator::map_res,
         error::ErrorKind,
         IResult,
@@ -224,13 +261,22 @@ mod creator {
         }
     }
 
+    // Names can hold angle brackets of their own, so the email is the last
+    // <...> on the line, just before the date.
     pub fn parse(input: &str) -> IResult<&str, (String, String, DateTime<Utc>)> {
-        let (rest, name) = take_until(" <")(input)?;
-        let (rest, _) = tag(" <")(rest)?;
-        let (rest, email) = take_until("> ")(rest)?;
-        let (rest, _) = tag("> ")(rest)?;
+        let (_, line) = take_till(|c| c == '\r' || c == '\n')(input)?;
+        let missing = || nom::Err::Error((input, ErrorKind::TakeUntil));
+        let close = line.rfind("> ").ok_or_else(missing)?;
+        let open = line[..close].rfind('<').ok_or_else(missing)?;
+        let (rest, ts) = date(&input[close + 2..])?;
+
+        Ok((rest, (line[..open].trim_end().into(), line[open + 1..close].into(), ts)))
+    }
+
+    // A raw git date: seconds since the epoch, and the zone offset, e.g. 1572973200 -0800
+    pub fn date(input: &str) -> IResult<&str, DateTime<Utc>> {
         let (rest, secs_epoch): (_, i64) =
-            map_res(take_while(is_digit), |s: &str| s.parse())(rest)?;
+            map_res(take_while(is_digit), |s: &str| s.parse())(input)?;
         let (rest, _) = tag(" ")(rest)?;
         let (rest, sign) = map_res(alt((tag("+"), tag("-"))), |s: &str| s.parse())(rest)?;
         let (rest, hours): (_, i32) =
@@ -238,20 +284,25 @@ mod creator {
         let (rest, minutes): (_, i32) =
             map_res(take_while_m_n(2, 2, is_digit), |s: &str| s.parse())(rest)?;
 
+        // A well-formed date that can't be represented is a hard failure, flagged as Verify,
+        // which super::parse reports as an invalid timestamp rather than a syntax error.
         let ts = build_timestamp((sign, hours, minutes), secs_epoch)
-            .ok_or(nom::Err::Error((rest, ErrorKind::TakeWhileMN)))?;
+            .ok_or(nom::Err::Failure((input, ErrorKind::Verify)))?;
 
-        Ok((rest, (name.into(), email.into(), ts)))
+        Ok((rest, ts))
     }
 
     fn build_timestamp(offset: (Sign, i32, i32), secs_epoch: i64) -> Option<DateTime<Utc>> {
         let (sign, hours, minutes) = offset;
+        if minutes >= 60 {
+            return None;
+        }
         let offset_secs = (hours * 60 + minutes) * 60;
 
         let tz = match sign {
-            Sign::Pos => FixedOffset::east(offset_secs),
-            Sign::Neg => FixedOffset::west(offset_secs),
-        };
+            Sign::Pos => FixedOffset::east_opt(offset_secs),
+            Sign::Neg => FixedOffset::west_opt(offset_secs),
+        }?;
 
         tz.timestamp_opt(secs_epoch, 0).earliest().map(|t| t.into())
     }
@@ -280,12 +331,78 @@ mod creator {
                 ))
             )
         }
+
+        #[test]
+        fn creator_names() {
+            fn name_and_email(creator: &str) -> IResult<&str, (String, String)> {
+                parse(creator).map(|(rest, (name, email, _))| (rest, (name, email)))
+            }
+            assert_eq!(
+                name_and_email("Foo <bar> Baz <real@example.com> 1570644797 -0700' ''\n"),
+                Ok(("' ''\n", ("Foo <bar> Baz".into(), "real@example.com".into())))
+            );
+            assert_eq!(
+                name_and_email("Zoë Ñúñez 山田 <zoe@example.com> 1570644797 +0900"),
+                Ok(("", ("Zoë Ñúñez 山田".into(), "zoe@example.com".into())))
+            );
+            assert_eq!(
+                name_and_email("Judson<nyarly@gmail.com> 1570644797 -0700"),
+                Ok(("", ("Judson".into(), "nyarly@gmail.com".into())))
+            );
+            // the next line's creator isn't this one's
+            assert!(parse("Judson 1570644797 -0700\nJudson <nyarly@gmail.com> 1570644797 -0700").is_err());
+            assert_eq!(
+                parse("Foo <bar> Baz <real@example.com> 1570644797 -0700").unwrap().1 .2,
+                utc_time("Wed, 9 Oct 2019 18:13:17")
+            );
+        }
+
+        #[test]
+        fn partial_hour_offsets() {
+            assert_eq!(
+                date("1570644797 +0530"),
+                Ok(("", utc_time("Wed, 9 Oct 2019 18:13:17")))
+            );
+            assert_eq!(
+                date("1570644797 +0545"),
+                Ok(("", utc_time("Wed, 9 Oct 2019 18:13:17")))
+            );
+            assert_eq!(
+                date("1570644797 -0000"),
+                Ok(("", utc_time("Wed, 9 Oct 2019 18:13:17")))
+            );
+        }
+
+        #[test]
+        fn unrepresentable_offsets() {
+            assert_eq!(
+                date("1570644797 +2500"),
+                Err(nom::Err::Failure(("1570644797 +2500", ErrorKind::Verify)))
+            );
+            assert_eq!(
+                date("1570644797 -0075"),
+                Err(nom::Err::Failure(("1570644797 -0075", ErrorKind::Verify)))
+            );
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn never_panics(input in super::super::tests::garbled(&[
+            include_str!("testdata/mezzo-for-each-ref-formatted"),
+            include_str!("testdata/for-each-ref-branches"),
+            include_str!("testdata/for-each-ref-tags"),
+            include_str!("testdata/for-each-ref-plain"),
+        ])) {
+            let _ = parse(&input);
+        }
+    }
 
     fn utc_time(from: &str) -> DateTime<Utc> {
         DateTime::<Utc>::from(
@@ -301,14 +418,14 @@ mod tests {
     #[test]
     fn first_line() {
         line(
-            "'c3327de22fc2bee6f25988f727700a9932b520dc' '' 'commit' 'refs/heads/ad_objects' 'refs/remotes/origin/ad_objects' 'origin' '' 'Paula Burke <pburke@opentable.com> 1534292967 -0700'"
+            "'c3327de22fc2bee6f25988f727700a9932b520dc' '' 'commit' 'refs/heads/ad_objects' 'refs/remotes/origin/ad_objects' 'origin' '' 'Paula Burke <pburke@opentable.com> 1534292967 -0700' ''"
         ).unwrap();
     }
 
     #[test]
     fn line_parse() {
         assert_eq!(
-            line("'f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2' '' 'commit' 'refs/heads/along' 'refs/remotes/along/mezzo' 'along' '' 'Judson <nyarly@gmail.com> 1570644797 -0700'"),
+            line("'f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2' '' 'commit' 'refs/heads/along' 'refs/remotes/along/mezzo' 'along' '' 'Judson <nyarly@gmail.com> 1570644797 -0700' ''"),
             Ok(("", RefLine{
                 local_ref: "refs/heads/along".into(),
                 object_name: "f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2".into(),
@@ -324,11 +441,89 @@ mod tests {
                 creator_name: "Judson".into(),
                 creator_email: "nyarly@gmail.com".into(),
                 creation_date: utc_time("Wed, 9 Oct 2019 18:13:17"),
+                tagger_date: None,
+            }))
+        )
+    }
+
+    #[test]
+    fn plain_parse() {
+        let shell = [include_str!("testdata/for-each-ref-branches"), include_str!("testdata/for-each-ref-tags")].concat();
+        let plain = include_str!("testdata/for-each-ref-plain");
+        assert_eq!(parse(plain).unwrap(), parse(&shell).unwrap());
+        assert_eq!(parse(&plain.replace('\n', "\r\n")).unwrap(), parse(&shell).unwrap());
+        assert!(matches!(
+            parse("d98f5dc243faaf545c3fcf08c3b02f44c58981d4\0\0commit\0refs/heads/main\0\0\0\0Judson <nyarly@gmail.com> 1624060800 -0075\0\n"),
+            Err(Err::Timestamp("1624060800 -0075"))
+        ));
+    }
+
+    #[test]
+    fn empty_parse() {
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn crlf_parse() {
+        let fixture = include_str!("testdata/mezzo-for-each-ref-formatted");
+        assert_eq!(parse(&fixture.replace('\n', "\r\n")).unwrap(), parse(fixture).unwrap());
+    }
+
+    #[test]
+    fn untracked_parse() {
+        let lines = parse(include_str!("testdata/for-each-ref-untracked")).unwrap();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            assert_eq!(line.referred_object, None);
+            assert_eq!(line.upstream, TrackSync::Untracked);
+            assert_eq!(line.tagger_date, None);
+        }
+    }
 
+    #[test]
+    fn tag_kinds_parse() {
+        let lines = parse(include_str!("testdata/for-each-ref-tags")).unwrap();
+        let annotated = &lines[1];
+        assert_eq!(annotated.object_type, ObjectType::Tag);
+        assert_eq!(annotated.referred_object, Some("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2".into()));
+        assert!(annotated.tagger_date.is_some());
+        // a lightweight tag is just a ref to the commit, like a branch
+        let lightweight = &lines[2];
+        assert_eq!(lightweight.local_ref, "refs/tags/v1.1".into());
+        assert_eq!(lightweight.object_type, ObjectType::Commit);
+        assert_eq!(lightweight.object_name, lines[0].object_name);
+        assert_eq!(lightweight.referred_object, None);
+        assert_eq!(lightweight.tagger_date, None);
+    }
+
+    #[test]
+    fn annotated_tag_line_parse() {
+        assert_eq!(
+            line("'de4280e681a2a5989e17f223054d13858aef2861' '06e3cae8ecf583464b4d7288a99a58421986b553' 'tag' 'refs/tags/v2.0' '' '' '' 'Judson <nyarly@gmail.com> 1572973897 -0800' '1572973897 -0800'"),
+            Ok(("", RefLine{
+                local_ref: "refs/tags/v2.0".into(),
+                object_name: "de4280e681a2a5989e17f223054d13858aef2861".into(),
+                referred_object: Some("06e3cae8ecf583464b4d7288a99a58421986b553".into()),
+                object_type: ObjectType::Tag,
+                upstream: TrackSync::Untracked,
+                creator_name: "Judson".into(),
+                creator_email: "nyarly@gmail.com".into(),
+                creation_date: utc_time("Tue, 5 Nov 2019 17:11:37"),
+                tagger_date: Some(utc_time("Tue, 5 Nov 2019 17:11:37")),
             }))
         )
     }
 
+    #[test]
+    fn bad_timestamp_parse() {
+        let parsed = parse("'f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2' '' 'commit' 'refs/heads/along' 'refs/remotes/along/mezzo' 'along' '' 'Judson <nyarly@gmail.com> 1570644797 +2500' ''\n");
+        assert!(matches!(parsed, Err(Err::Timestamp("1570644797 +2500"))));
+        assert_eq!(
+            format!("{}", parsed.unwrap_err()),
+            "invalid timestamp or zone offset: 1570644797 +2500"
+        );
+    }
+
     #[test]
     fn object_type_parse() {
         use super::ObjectType::*;
@@ -0,0 +1,33 @@
+use nom::multi::many0;
+
+use super::{nul_terminated_path, settle_parse_result, WorkPath};
+
+/*
+ * git ls-files -z --ignored --cached --exclude-standard
+ * vendor/cache/build.log\0config/local.yml\0
+ *
+ * Tracked files that the ignore rules (.gitignore, .git/info/exclude and
+ * core.excludesFile) would otherwise ignore, each path as it is, unquoted,
+ * ending in a NUL.
+ */
+
+pub fn parse(input: &str) -> super::Result<&str, Vec<WorkPath>> {
+    settle_parse_result(many0(nul_terminated_path)(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignored_paths_parse() {
+        assert_eq!(
+            parse("vendor/cache/build.log\0config/local.yml\0").unwrap(),
+            vec![WorkPath::from("vendor/cache/build.log"), WorkPath::from("config/local.yml")]
+        );
+        assert_eq!(parse("my\tnotes.md\0").unwrap(), vec![WorkPath::from("my\tnotes.md")]);
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert!(parse("unterminated").is_err());
+        assert!(parse("vendor/cache/build.log\n").is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use nom::{
-    bytes::complete::tag,
+    bytes::complete::{is_not, tag},
     combinator::map,
     multi::many0,
     sequence::{terminated, tuple},
@@ -7,28 +7,62 @@ use nom::{
 };
 
 use fake::{Dummy,Fake};
-use super::{filepath, settle_parse_result, sha, ObjectName, WorkPath};
+use super::{eol, settle_parse_result, sha, ObjectName, RefName};
 
+// git ls-remote prints "<sha>\t<refname>"
+//
+// An annotated tag gets a second line, for the commit it refers to, with
+// "^{}" after its name:
+// 5b0e2e8a6e4f3c1d9a7b2c4e6f8a0b1c3d5e7f90\trefs/tags/v1.0
+// f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2\trefs/tags/v1.0^{}
+// That commit is kept with the tag, as `peeled`.
 #[derive(Debug, PartialEq, Eq, Dummy)]
 pub struct RefPair {
-    pub refname: ObjectName,
-    pub path: WorkPath,
+    pub object_name: ObjectName,
+    pub refname: RefName,
+    pub peeled: Option<ObjectName>,
 }
 
-impl From<(ObjectName, WorkPath)> for RefPair {
-    fn from(pair: (ObjectName, WorkPath)) -> Self {
-        let (refname, path) = pair;
-        RefPair { refname, path }
+impl From<(ObjectName, RefName)> for RefPair {
+    fn from(pair: (ObjectName, RefName)) -> Self {
+        let (object_name, refname) = pair;
+        RefPair { object_name, refname, peeled: None }
     }
 }
 
+const PEELED: &str = "^{}";
+
 pub fn parse(input: &str) -> super::Result<&str, Vec<RefPair>> {
-    settle_parse_result(many0(terminated(ref_pair, tag("\n")))(input))
+    settle_parse_result(many0(terminated(ref_pair, eol))(input)).map(peel)
+}
+
+// Folds each peeled line into its tag's, which git lists just before it. A
+// peeled line on its own stands for the tag.
+fn peel(pairs: Vec<RefPair>) -> Vec<RefPair> {
+    let mut peeled: Vec<RefPair> = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        let base = match pair.refname.as_ref().strip_suffix(PEELED) {
+            Some(base) => RefName::from(base),
+            None => {
+                peeled.push(pair);
+                continue;
+            }
+        };
+        match peeled.last_mut() {
+            Some(tag) if tag.refname == base => tag.peeled = Some(pair.object_name),
+            _ => peeled.push(RefPair {
+                object_name: pair.object_name.clone(),
+                refname: base,
+                peeled: Some(pair.object_name),
+            }),
+        }
+    }
+    peeled
 }
 
 fn ref_pair(input: &str) -> IResult<&str, RefPair> {
     map(
-        tuple((terminated(sha, tag("\t")), filepath)),
+        tuple((terminated(sha, tag("\t")), map(is_not("\r\n"), RefName::from))),
         RefPair::from,
     )(input)
 }
@@ -36,6 +70,17 @@ fn ref_pair(input: &str) -> IResult<&str, RefPair> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn never_panics(input in super::super::tests::garbled(&[
+            include_str!("testdata/mezzo-ls-remote"),
+            include_str!("testdata/ls-remote-peeled"),
+        ])) {
+            let _ = parse(&input);
+        }
+    }
 
     #[test]
     fn ref_pairs_parse() {
@@ -43,6 +88,32 @@ mod tests {
         assert_eq!(lines.len(), 730)
     }
 
+    #[test]
+    fn peeled_tags_parse() {
+        let pairs = parse(include_str!("testdata/ls-remote-peeled")).unwrap();
+        assert_eq!(pairs.len(), 4);
+        assert!(pairs.iter().all(|pair| !pair.refname.as_ref().ends_with(PEELED)));
+        assert_eq!(
+            pairs[2],
+            RefPair {
+                object_name: "5b0e2e8a6e4f3c1d9a7b2c4e6f8a0b1c3d5e7f90".into(),
+                refname: "refs/tags/v1.0".into(),
+                peeled: Some("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2".into()),
+            }
+        );
+        assert_eq!(pairs[3].peeled, None);
+
+        let orphan = parse("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2\trefs/tags/v2.0^{}\n").unwrap();
+        assert_eq!(orphan[0].refname, RefName::from("refs/tags/v2.0"));
+        assert_eq!(orphan[0].peeled, Some("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2".into()));
+    }
+
+    #[test]
+    fn crlf_ref_pairs_parse() {
+        let fixture = include_str!("testdata/mezzo-ls-remote");
+        assert_eq!(parse(&fixture.replace('\n', "\r\n")).unwrap(), parse(fixture).unwrap());
+    }
+
     #[test]
     fn ref_pair_parse() {
         assert_eq!(
@@ -50,8 +121,9 @@ mod tests {
             Ok((
                 "",
                 RefPair {
-                    refname: "d4ae7077d4ed711a10e89908ab91999ce326dfc0".into(),
-                    path: "refs/heads/approvals_template".into(),
+                    object_name: "d4ae7077d4ed711a10e89908ab91999ce326dfc0".into(),
+                    refname: "refs/heads/approvals_template".into(),
+                    peeled: None,
                 }
             ))
         )
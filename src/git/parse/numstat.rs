@@ -0,0 +1,79 @@
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::digit1,
+    combinator::{map, map_res},
+    multi::many0,
+    sequence::{pair, preceded, terminated, tuple},
+    IResult,
+};
+use serde::Serialize;
+use schemars::JsonSchema;
+
+use super::{nul_terminated_path, settle_parse_result, WorkPath};
+
+/*
+ * git diff --numstat -z
+ * 12	3	src/main.rs\0
+ * -	-	jar.svg\0
+ * 0	5	\0src/old.rs\0src/new.rs\0
+ *
+ * Lines added and deleted in each changed file, tab-separated, then the
+ * path as it is, unquoted, ending in a NUL. A binary file has no lines to
+ * count, so git gives a dash for each. A rename has an empty path, followed
+ * by the old and new paths, each ending in a NUL; its new path is kept.
+ */
+
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema)]
+pub struct FileStat {
+    pub path: WorkPath,
+    /// None for a binary file, as is `deleted`.
+    pub added: Option<u64>,
+    pub deleted: Option<u64>,
+}
+
+pub fn parse(input: &str) -> super::Result<&str, Vec<FileStat>> {
+    settle_parse_result(many0(file_stat)(input))
+}
+
+fn file_stat(input: &str) -> IResult<&str, FileStat> {
+    map(
+        tuple((terminated(lines, tag("\t")), terminated(lines, tag("\t")), alt((renamed, nul_terminated_path)))),
+        |(added, deleted, path)| FileStat { path, added, deleted },
+    )(input)
+}
+
+fn renamed(input: &str) -> IResult<&str, WorkPath> {
+    map(preceded(tag("\0"), pair(nul_terminated_path, nul_terminated_path)), |(_, new)| new)(input)
+}
+
+fn lines(input: &str) -> IResult<&str, Option<u64>> {
+    alt((
+        map(tag("-"), |_| None),
+        map(map_res(digit1, |n: &str| n.parse()), Some),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numstat_parse() {
+        let stat = |added, deleted, path: &str| FileStat { path: path.into(), added, deleted };
+        assert_eq!(
+            parse(include_str!("testdata/diff-numstat")).unwrap(),
+            vec![
+                stat(Some(12), Some(3), "src/main.rs"),
+                stat(None, None, "jar.svg"),
+                stat(Some(0), Some(5), "src/new.rs"),
+                stat(Some(1), Some(0), "my notes.md"),
+            ]
+        );
+        assert_eq!(parse("1\t0\ttab\tname.txt\0").unwrap(), vec![stat(Some(1), Some(0), "tab\tname.txt")]);
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert!(parse("twelve\t3\tsrc/main.rs\0").is_err());
+        assert!(parse("12\t3\tsrc/main.rs\n").is_err());
+        assert!(parse("12\t3\0").is_err());
+    }
+}
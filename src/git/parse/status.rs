@@ -1,7 +1,7 @@
 extern crate nom;
 use nom::{
   branch::alt,
-  bytes::complete::{tag, take, take_until, take_while},
+  bytes::complete::{is_not, tag, take, take_while},
   character::complete::one_of,
   combinator::{map, map_res, opt},
   multi::{count, many0},
@@ -10,13 +10,16 @@ use nom::{
 };
 use fake::{Dummy,Fake,Faker};
 use rand::Rng;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use schemars::{JsonSchema, gen::SchemaGenerator, schema::Schema};
+use std::fmt;
 use std::array::TryFromSliceError;
 use std::convert::TryFrom;
+use std::str::FromStr;
 
-use super::{filepath, settle_parse_result, sha, ObjectName, RefName, TrackingCounts, WorkPath};
+use super::{eol, filepath, settle_parse_result, sha, ObjectName, RefName, TrackingCounts, WorkPath};
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
 pub struct Status {
   pub branch: Option<Branch>,
   pub lines: Vec<StatusLine>,
@@ -40,7 +43,7 @@ impl Dummy<Faker> for Status {
   }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema, Dummy)]
 pub struct Branch {
   pub oid: Oid,
   pub head: Head,
@@ -48,7 +51,7 @@ pub struct Branch {
   pub commits: Option<TrackingCounts>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Serialize, JsonSchema, Dummy)]
 #[serde(rename_all = "lowercase")]
 pub enum StatusLine {
   One {
@@ -69,7 +72,9 @@ pub enum StatusLine {
     worktree_mode: Mode,
     head_obj: ObjectName,
     index_obj: ObjectName,
-    change_score: ChangeScore,
+    /// Git always reports this, but it's the one field of the line a
+    /// different porcelain could plausibly drop.
+    change_score: Option<ChangeScore>,
     path: WorkPath,
     orig_path: WorkPath,
   },
@@ -93,44 +98,123 @@ pub enum StatusLine {
   },
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema, Dummy)]
 #[serde(rename_all = "lowercase")]
 pub enum Oid {
   Initial,
   Commit(ObjectName),
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema, Dummy)]
 #[serde(rename_all = "lowercase")]
 pub enum Head {
   Detached,
   Branch(RefName),
 }
 
-#[derive(Debug, PartialEq, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Dummy)]
 pub struct Mode([u8; 6]);
 
-#[derive(Debug, PartialEq, Serialize, Dummy)]
-#[serde(rename_all = "lowercase")]
+impl fmt::Display for Mode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.iter().try_for_each(|d| write!(f, "{}", d))
+  }
+}
+
+// As git writes it: "100644", rather than an array of digits.
+impl Serialize for Mode {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl JsonSchema for Mode {
+  fn schema_name() -> String {
+    String::schema_name()
+  }
+
+  fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    String::json_schema(gen)
+  }
+}
+
+/// Which sides of a merge still have a conflicted path, as `git status` describes it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+  BothModified,
+  BothAdded,
+  BothDeleted,
+  AddedByUs,
+  AddedByThem,
+  DeletedByUs,
+  DeletedByThem,
+}
+
+/// Whether a path is a submodule, and if so, whether its commit changed, and
+/// whether it has modifications or untracked files of its own, in that order.
+#[derive(Debug, PartialEq, Dummy)]
 pub enum SubmoduleStatus {
   Not,
   Is(bool, bool, bool),
 }
 
-#[derive(Debug, PartialEq, Serialize, Dummy)]
+// The flags by name, which the tuple doesn't give them in JSON.
+#[derive(Serialize, JsonSchema)]
+struct SubmoduleFlags {
+  submodule: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  commit_changed: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  has_modifications: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  has_untracked: Option<bool>,
+}
+
+impl Serialize for SubmoduleStatus {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let flags = match *self {
+      SubmoduleStatus::Not => SubmoduleFlags {
+        submodule: false,
+        commit_changed: None,
+        has_modifications: None,
+        has_untracked: None,
+      },
+      SubmoduleStatus::Is(commit, modified, untracked) => SubmoduleFlags {
+        submodule: true,
+        commit_changed: Some(commit),
+        has_modifications: Some(modified),
+        has_untracked: Some(untracked),
+      },
+    };
+    flags.serialize(serializer)
+  }
+}
+
+impl JsonSchema for SubmoduleStatus {
+  fn schema_name() -> String {
+    "SubmoduleStatus".to_string()
+  }
+
+  fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    SubmoduleFlags::json_schema(gen)
+  }
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema, Dummy)]
 #[serde(rename_all = "lowercase")]
 pub enum ChangeScore {
   Rename(u8),
   Copy(u8),
 }
 
-#[derive(Debug, PartialEq, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Serialize, JsonSchema, Dummy)]
 pub struct StatusPair {
   pub staged: LineStatus,
   pub unstaged: LineStatus,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Dummy)]
+#[derive(Debug, PartialEq, Eq, Serialize, JsonSchema, Dummy)]
 #[serde(rename_all = "lowercase")]
 pub enum LineStatus {
   Unmodified,
@@ -144,6 +228,145 @@ pub enum LineStatus {
   Ignored,
 }
 
+impl StatusLine {
+  fn status_pair(&self) -> Option<&StatusPair> {
+    match self {
+      StatusLine::One { status, .. }
+      | StatusLine::Two { status, .. }
+      | StatusLine::Unmerged { status, .. } => Some(status),
+      StatusLine::Untracked { .. } | StatusLine::Ignored { .. } => None,
+    }
+  }
+
+  /// The index status of a tracked path; None for untracked and ignored paths.
+  pub fn staged_status(&self) -> Option<&LineStatus> {
+    self.status_pair().map(|p| &p.staged)
+  }
+
+  /// The worktree status of a tracked path; None for untracked and ignored paths.
+  pub fn unstaged_status(&self) -> Option<&LineStatus> {
+    self.status_pair().map(|p| &p.unstaged)
+  }
+
+  pub fn path(&self) -> &WorkPath {
+    match self {
+      StatusLine::One { path, .. }
+      | StatusLine::Two { path, .. }
+      | StatusLine::Unmerged { path, .. }
+      | StatusLine::Untracked { path }
+      | StatusLine::Ignored { path } => path,
+    }
+  }
+
+  pub fn is_conflict(&self) -> bool {
+    matches!(self, StatusLine::Unmerged { .. })
+  }
+
+  /// Which side of the merge is missing the path, from which of the base (1),
+  /// ours (2) and theirs (3) stages are present; None if not in conflict.
+  pub fn conflict_kind(&self) -> Option<ConflictKind> {
+    use ConflictKind::*;
+    match self {
+      StatusLine::Unmerged { stage1_mode, stage2_mode, stage3_mode, .. } => {
+        Some(match (stage1_mode.is_absent(), stage2_mode.is_absent(), stage3_mode.is_absent()) {
+          (false, false, false) => BothModified,
+          (true, false, false) => BothAdded,
+          (false, true, true) => BothDeleted,
+          (true, false, true) => AddedByUs,
+          (true, true, false) => AddedByThem,
+          (false, true, false) => DeletedByUs,
+          (false, false, true) => DeletedByThem,
+          // git never reports a path absent from every stage
+          (true, true, true) => BothDeleted,
+        })
+      }
+      _ => None,
+    }
+  }
+}
+
+impl Mode {
+  /// Whether the mode is 000000, as for a stage the path is missing from.
+  pub fn is_absent(&self) -> bool {
+    self.0.iter().all(|&d| d == 0)
+  }
+}
+
+impl LineStatus {
+  pub fn is_clean(&self) -> bool {
+    *self == LineStatus::Unmodified
+  }
+
+  /// The character git uses for this status in porcelain output.
+  pub fn code(&self) -> char {
+    use LineStatus::*;
+    match self {
+      Unmodified => '.',
+      Modified => 'M',
+      Added => 'A',
+      Deleted => 'D',
+      Renamed => 'R',
+      Copied => 'C',
+      Unmerged => 'U',
+      Untracked => '?',
+      Ignored => '!',
+    }
+  }
+}
+
+// Each of these displays in git's own notation, and parses back from it.
+
+impl fmt::Display for LineStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.code())
+  }
+}
+
+impl FromStr for LineStatus {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    whole(s, line_status, "line status")
+  }
+}
+
+// Staged, then unstaged: "M.", ".D".
+impl fmt::Display for StatusPair {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}{}", self.staged, self.unstaged)
+  }
+}
+
+impl FromStr for StatusPair {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    whole(s, status_pair, "status pair")
+  }
+}
+
+// "R75", "C100"
+impl fmt::Display for ChangeScore {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ChangeScore::Rename(score) => write!(f, "R{}", score),
+      ChangeScore::Copy(score) => write!(f, "C{}", score),
+    }
+  }
+}
+
+impl FromStr for ChangeScore {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    whole(s, change_score, "change score")
+  }
+}
+
+fn whole<'a, O>(s: &'a str, parser: impl Fn(&'a str) -> IResult<&'a str, O>, what: &str) -> Result<O, String> {
+  settle_parse_result(parser(s)).map_err(|_| format!("not a {}: {:?}", what, s))
+}
+
 impl TryFrom<Vec<u8>> for Mode {
   type Error = TryFromSliceError;
   fn try_from(v: Vec<u8>) -> Result<Mode, TryFromSliceError> {
@@ -189,9 +412,9 @@ fn branch_oid(input: &str) -> IResult<&str, Oid> {
     tag("# branch.oid "),
     alt((
         map(tag("(initial)"), |_| Oid::Initial),
-        map(take_until("\n"), |s: &str| Oid::Commit(s.into())),
+        map(is_not("\r\n"), |s: &str| Oid::Commit(s.into())),
     )),
-    tag("\n"),
+    eol,
   )(input)
 }
 
@@ -200,17 +423,17 @@ fn branch_head(input: &str) -> IResult<&str, Head> {
     tag("# branch.head "),
     alt((
         map(tag("(detached)"), |_| Head::Detached),
-        map(take_until("\n"), |s: &str| Head::Branch(s.into())),
+        map(is_not("\r\n"), |s: &str| Head::Branch(s.into())),
     )),
-    tag("\n"),
+    eol,
   )(input)
 }
 
 fn branch_upstream(input: &str) -> IResult<&str, RefName> {
   delimited(
     tag("# branch.upstream "),
-    map(take_until("\n"), |s: &str| s.into()),
-    tag("\n"),
+    map(is_not("\r\n"), |s: &str| s.into()),
+    eol,
   )(input)
 }
 
@@ -219,7 +442,7 @@ fn branch_commits(input: &str) -> IResult<&str, TrackingCounts> {
     delimited(
       tag("# branch.ab "),
       separated_pair(tagged_commits("+"), tag(" "), tagged_commits("-")),
-      tag("\n"),
+      eol,
     ),
     |(a, b)| TrackingCounts(a, b),
   )(input)
@@ -233,7 +456,7 @@ fn tagged_commits<'a>(pattern: &'static str) -> impl Fn(&'a str) -> IResult<&'a
 }
 
 pub fn status_lines(input: &str) -> IResult<&str, Vec<StatusLine>> {
-  many0(terminated(status_line, tag("\n")))(input)
+  many0(terminated(status_line, eol))(input)
 }
 
 fn status_line(input: &str) -> IResult<&str, StatusLine> {
@@ -288,7 +511,7 @@ fn two_file_line(input: &str) -> IResult<&str, StatusLine> {
   let (i, worktree_mode) = terminated(mode, tag(" "))(i)?;
   let (i, head_obj) = terminated(sha, tag(" "))(i)?;
   let (i, index_obj) = terminated(sha, tag(" "))(i)?;
-  let (i, change_score) = terminated(change_score, tag(" "))(i)?;
+  let (i, change_score) = opt(terminated(change_score, tag(" ")))(i)?;
   let (i, path) = terminated(filepath, tag("\t"))(i)?;
   let (i, orig_path) = filepath(i)?;
   Ok((
@@ -377,19 +600,17 @@ where
 }
 
 fn submodule_status(input: &str) -> IResult<&str, SubmoduleStatus> {
-  let (i, s) = one_of("NS")(input)?;
-  match s {
-    'N' => map(count(one_of("."), 3), |_| SubmoduleStatus::Not)(i),
-    'S' => map(
-      tuple((
-          submodule_status_flag("C."),
-          submodule_status_flag("M."),
-          submodule_status_flag("U."),
-      )),
-      |(c, m, u)| SubmoduleStatus::Is(c, m, u),
-    )(i),
-    _ => panic!("one_of violated contract"),
-  }
+  alt((
+      map(preceded(tag("N"), count(one_of("."), 3)), |_| SubmoduleStatus::Not),
+      map(
+        preceded(tag("S"), tuple((
+              submodule_status_flag("C."),
+              submodule_status_flag("M."),
+              submodule_status_flag("U."),
+        ))),
+        |(c, m, u)| SubmoduleStatus::Is(c, m, u),
+      ),
+  ))(input)
 }
 
 fn tagged_score<'a>(pattern: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, u8> {
@@ -410,6 +631,29 @@ fn change_score(input: &str) -> IResult<&str, ChangeScore> {
 mod tests {
   use super::*;
   use nom;
+  use proptest::prelude::*;
+
+  #[test]
+  fn malformed_fields_fail() {
+    assert!(submodule_status("X...").is_err());
+    assert!(submodule_status("N.M.").is_err());
+    assert!(submodule_status("S").is_err());
+    assert!(Mode::try_from(vec![1, 0, 0]).is_err());
+    assert!(mode("1006").is_err());
+    assert!(mode("10064a").is_err());
+  }
+
+  proptest! {
+    #[test]
+    fn never_panics(input in super::super::tests::garbled(&[
+      include_str!("testdata/mezzo-status-1"),
+      include_str!("testdata/status-conflict-kinds"),
+      include_str!("testdata/status-dirty-submodules"),
+      include_str!("testdata/status-triple-u"),
+    ])) {
+      let _ = parse(&input);
+    }
+  }
 
   #[test]
   fn full_parse() {
@@ -458,6 +702,48 @@ mod tests {
     )
   }
 
+  #[test]
+  fn full_parse_upstream_without_ab() {
+    assert_eq!(
+      parse(include_str!("testdata/status-upstream-no-ab")).unwrap(),
+      Status {
+        branch: Some(Branch {
+          oid: Oid::Commit("5f1c2e0a9b8d7c6e5f4a3b2c1d0e9f8a7b6c5d4e".into()),
+          head: Head::Branch("release".into()),
+          upstream: Some("origin/release".into()),
+          commits: None
+        }),
+        lines: vec![]
+      }
+    );
+  }
+
+  #[test]
+  fn crlf_parse() {
+    for fixture in &[
+      include_str!("testdata/mezzo-status-2"),
+      include_str!("testdata/self-status-unknownfile"),
+      include_str!("testdata/status-triple-u"),
+    ] {
+      assert_eq!(parse(&fixture.replace('\n', "\r\n")).unwrap(), parse(fixture).unwrap());
+    }
+  }
+
+  #[test]
+  fn conflict_kinds() {
+    let status = parse(include_str!("testdata/status-conflict-kinds")).unwrap();
+    let kinds: Vec<_> = status.lines.iter().map(|l| l.conflict_kind()).collect();
+    assert_eq!(kinds, vec![
+      Some(ConflictKind::DeletedByThem),
+      Some(ConflictKind::DeletedByUs),
+      Some(ConflictKind::BothAdded),
+      Some(ConflictKind::BothModified),
+    ]);
+
+    let status = parse(include_str!("testdata/status-triple-u")).unwrap();
+    assert_eq!(status.lines[0].conflict_kind(), None);
+  }
+
   #[test]
   fn full_parse_triple_u() {
     assert_eq!(
@@ -562,6 +848,22 @@ mod tests {
     )
   }
 
+  #[test]
+  fn line_helpers() {
+    let status = parse(include_str!("testdata/status-triple-u")).unwrap();
+    let conflicted: Vec<_> = status.lines.iter().filter(|l| l.is_conflict()).collect();
+    assert_eq!(conflicted.len(), 1);
+    assert_eq!(conflicted[0].staged_status(), Some(&LineStatus::Unmerged));
+    assert_eq!(conflicted[0].unstaged_status(), Some(&LineStatus::Unmerged));
+
+    assert_eq!(status.lines[0].staged_status(), Some(&LineStatus::Modified));
+    assert!(status.lines[0].unstaged_status().unwrap().is_clean());
+
+    let untracked = StatusLine::Untracked { path: "pinned.nix".into() };
+    assert_eq!(untracked.staged_status(), None);
+    assert!(!untracked.is_conflict());
+  }
+
   #[test]
   fn parse_unknown_line() {
     assert_eq!(
@@ -621,6 +923,12 @@ mod tests {
     );
   }
 
+  #[test]
+  fn mode_serialize() {
+    assert_eq!(serde_json::to_string(&Mode([1, 0, 0, 6, 4, 4])).unwrap(), "\"100644\"");
+    assert_eq!(Mode([0, 0, 0, 0, 0, 0]).to_string(), "000000");
+  }
+
   #[test]
   fn sha_parse() {
     assert_eq!(
@@ -682,6 +990,31 @@ mod tests {
     );
   }
 
+  #[test]
+  fn notation_round_trips() {
+    use LineStatus::*;
+    for status in vec![Unmodified, Modified, Added, Deleted, Renamed, Copied, Unmerged, Untracked, Ignored] {
+      let code = status.to_string();
+      assert_eq!(code.len(), 1);
+      assert_eq!(code.parse::<LineStatus>(), Ok(status));
+    }
+    assert!("MM".parse::<LineStatus>().is_err());
+    assert_eq!("x".parse::<LineStatus>(), Err("not a line status: \"x\"".to_string()));
+
+    for code in &["M.", ".D", "UU", "??", "RM"] {
+      assert_eq!(code.parse::<StatusPair>().unwrap().to_string(), *code);
+    }
+    assert_eq!(StatusPair { staged: Added, unstaged: Modified }.to_string(), "AM");
+    assert!("M".parse::<StatusPair>().is_err());
+
+    for code in &["R75", "C100", "R0"] {
+      assert_eq!(code.parse::<ChangeScore>().unwrap().to_string(), *code);
+    }
+    assert_eq!("C90".parse::<ChangeScore>(), Ok(ChangeScore::Copy(90)));
+    assert!("R".parse::<ChangeScore>().is_err());
+    assert!("X75".parse::<ChangeScore>().is_err());
+  }
+
   #[test]
   fn test_path() {
     assert_eq!(
@@ -700,6 +1033,25 @@ mod tests {
     assert_eq!(change_score("C90"), Ok(("", ChangeScore::Copy(90))))
   }
 
+  #[test]
+  fn two_file_line_score() {
+    let scored = "R. N... 100644 100644 100644 c68d13474cd3f99964c052e5acc771f4df1e668e c68d13474cd3f99964c052e5acc771f4df1e668e R100 new.txt\told.txt";
+    let (_, line) = two_file_line(scored).unwrap();
+    assert!(matches!(line, StatusLine::Two { change_score: Some(ChangeScore::Rename(100)), .. }));
+
+    let unscored = "R. N... 100644 100644 100644 c68d13474cd3f99964c052e5acc771f4df1e668e c68d13474cd3f99964c052e5acc771f4df1e668e new.txt\told.txt";
+    let (rest, line) = two_file_line(unscored).unwrap();
+    assert_eq!(rest, "");
+    match line {
+      StatusLine::Two { change_score, path, orig_path, .. } => {
+        assert_eq!(change_score, None);
+        assert_eq!(path, WorkPath::from("new.txt"));
+        assert_eq!(orig_path, WorkPath::from("old.txt"));
+      }
+      other => panic!("not a two-file line: {:?}", other),
+    }
+  }
+
   #[test]
   fn status_lines_parse() {
     let (_rest, lines) = status_lines(include_str!("testdata/mezzo-status-1")).unwrap();
@@ -758,4 +1110,28 @@ mod tests {
       }
     );
   }
+
+  #[test]
+  fn submodule_flags_parse() {
+    let status = parse(include_str!("testdata/status-dirty-submodules")).unwrap();
+    let subs: Vec<_> = status.lines.iter().map(|line| match line {
+      StatusLine::One { sub, .. } => sub,
+      other => panic!("unexpected line {:?}", other),
+    }).collect();
+    assert_eq!(subs, vec![
+      &SubmoduleStatus::Is(false, true, false),
+      &SubmoduleStatus::Is(false, false, true),
+      &SubmoduleStatus::Is(true, false, false),
+    ]);
+
+    let json = serde_json::to_value(&status).unwrap();
+    assert_eq!(json["lines"][0]["one"]["sub"], serde_json::json!({
+      "submodule": true,
+      "commit_changed": false,
+      "has_modifications": true,
+      "has_untracked": false,
+    }));
+    assert_eq!(json["lines"][2]["one"]["sub"]["commit_changed"], true);
+    assert_eq!(serde_json::to_value(&SubmoduleStatus::Not).unwrap(), serde_json::json!({"submodule": false}));
+  }
 }
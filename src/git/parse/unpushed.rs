@@ -0,0 +1,46 @@
+use nom::{
+    character::complete::digit1,
+    combinator::{map_opt, opt},
+    sequence::terminated,
+    IResult,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use super::{eol, settle_parse_result};
+
+/*
+ * git log @{upstream}..HEAD -1 --format=%ct
+ * 1633024800
+ *
+ * The committer date of the newest unpushed commit, or nothing at all if
+ * every commit has been pushed.
+ */
+
+pub fn parse(input: &str) -> super::Result<&str, Option<DateTime<Utc>>> {
+    settle_parse_result(opt(terminated(epoch, eol))(input))
+}
+
+fn epoch(input: &str) -> IResult<&str, DateTime<Utc>> {
+    map_opt(digit1, |secs: &str| {
+        secs.parse().ok().and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+    })(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch_line_parse() {
+        assert_eq!(
+            parse("1633024800\n").unwrap(),
+            Some(DateTime::parse_from_rfc3339("2021-09-30T18:00:00Z").unwrap().into())
+        );
+    }
+
+    #[test]
+    fn nothing_unpushed_parse() {
+        assert_eq!(parse("").unwrap(), None);
+        assert!(parse("yesterday\n").is_err());
+    }
+}
@@ -1,18 +1,26 @@
+mod config;
+mod doctor;
+mod filters;
 mod git;
 mod preserves;
+mod progress;
 mod subcommands;
 
-use clap::{App, AppSettings, Arg, crate_authors, crate_version};
-use preserves::{Check, Summary, CheckList, datasource::Group};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, crate_authors, crate_version};
+use preserves::{Check, Meta, Report, Summary, CheckList, ExitMode, Gate, Glyphs, PathOrder, datasource::{self, Group}};
 use tera::{Tera, Context};
 use lazy_static::lazy_static;
 use include_dir::{include_dir,Dir,DirEntry};
-use std::path::Path;
-use git::{LsRemote, GetStatus, ForEachRef};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use git::{LsRemote, GetStatus, ForEachRef, Unpushed, Describe, TrackedIgnored, InProgress, Uncommitted, Provider};
 use fake::{Fake, Faker};
 use rand::{Rng,rngs::StdRng};
 use rand::SeedableRng;
 use getrandom::getrandom;
+use log::{debug, trace};
 
 lazy_static! {
   pub static ref TEMPLATE_DIR: Dir<'static> = include_dir!("src/templates");
@@ -28,44 +36,647 @@ lazy_static! {
   };
   pub static ref TMPL: Tera = {
     let mut tera = Tera::default();
+    filters::register(&mut tera);
     tera.add_raw_templates((*TEMPLATES).clone()).expect(&*format!("templates to parse"));
     tera
   };
+  static ref FORMAT_HELP: String = format!("choose formats for output, comma-separated [included: {}, json, or none to print nothing]",
+    template_formats(&TMPL).join(", "));
+  static ref FORMAT_LONG_HELP: String = format!("{}. compact prints just the failing checks' glyphs and counts, like \"↑2 +1 ?3\", for tmux and other status bars, and nothing when everything passes. markdown prints a table of checks for pasting into pull requests. prometheus prints gauges for node_exporter's textfile collector: confit_check per check (1 when failing, 0 for warnings), confit_check_count for counting checks, and confit_repo_clean (1 unless a check failed). none (or null) prints nothing, like -qq, for wrappers that always pass a format. json is the --json document. Several formats, as CI might want a log and an artifact, need --output for all but one.", *FORMAT_HELP);
+  // Custom checks have to be registered before this is first used.
+  static ref CHECKS_HELP: String = format!("checks to run, by tag or glob like 'track_*' [possible values: {}]", TAGS.join(", "));
+  static ref CHECKS_LONG_HELP: String = format!("{}. 'all' selects every check, which is the default. In a glob, * matches any run of characters and ? any single one. Tags are comma-separated (or repeated, as in -c push -c stage), so paths can follow them: confit -c push,stage src/.", *CHECKS_HELP);
+  static ref TAGS: Vec<&'static str> = Check::all_tags();
+  static ref FORMATS: Vec<&'static str> = format_names();
+}
+
+/// The templates that are formats, by name, in order: all but the ones
+/// another template imports or extends, which are libraries for the others.
+fn template_formats(tera: &Tera) -> Vec<&str> {
+  let libraries: HashSet<&str> = tera.get_template_names()
+    .filter_map(|name| tera.get_template(name).ok())
+    .flat_map(|t| t.imported_macro_files.iter().map(|(file, _)| file.as_str()).chain(t.parents.iter().map(String::as_str)))
+    .collect();
+  let mut names: Vec<_> = tera.get_template_names()
+    .filter(|name| !libraries.contains(name))
+    .collect();
+  names.sort_unstable();
+  names
+}
+
+/// Every format --format takes: the built-in templates', then json and the
+/// silent formats.
+fn format_names() -> Vec<&'static str> {
+  template_formats(&TMPL).into_iter()
+    .chain(std::iter::once(JSON_FORMAT))
+    .chain(SILENT_FORMATS.iter().copied())
+    .collect()
 }
 
 fn main() -> ! {
-  let opt = App::new("Confit")
+  let config = config::load(Path::new(config::FILENAME)).unwrap_or_else(&error_status(136));
+  for def in config.check {
+    Check::register(Check::from_def(def).unwrap_or_else(&error_status(136)))
+  }
+
+  let args = with_default_opts(std::env::args_os().collect(), std::env::var("CONFIT_OPTS").ok().as_deref())
+    .unwrap_or_else(|e| {
+      eprintln!("error: couldn't split CONFIT_OPTS: {}", e);
+      std::process::exit(1)
+    });
+  let opt = app().get_matches_from(args.clone());
+
+  let mut logger = env_logger::Builder::from_default_env();
+  if opt.is_present("debug") {
+    logger.filter_level(log::LevelFilter::Trace);
+  }
+  logger.init();
+
+  if let (name, Some(sub_opt)) = opt.subcommand() {
+    match name {
+      "write-templates" => subcommands::write_templates::run(sub_opt),
+      "schema" => subcommands::schema::run(sub_opt),
+      "doctor" => subcommands::doctor::run(sub_opt),
+      "check-templates" => subcommands::check_templates::run(sub_opt),
+      "completions" => subcommands::completions::run(sub_opt),
+      _ => {
+        println!("Unknown subcommand: {}", name);
+      } //?
+    }
+
+    std::process::exit(0)
+  }
+
+  if let Err(e) = format_targets(&opt) {
+    clap::Error::with_description(&e, clap::ErrorKind::ArgumentConflict).exit()
+  }
+
+  if opt.is_present("dry-run") {
+    let git_config = read_git_config().unwrap_or_else(Failure::exit);
+    let opt = configured(&args, &git_config).unwrap_or_else(Failure::exit);
+    let (checks, _, reqs) = plan(&opt, &git_config);
+    let (status, unpushed, describe, tracked_ignored, in_progress, uncommitted) = providers(&opt, &checks);
+    let commands = vec![
+      LsRemote::of(git_config.remote.clone()).command_line(reqs),
+      status.command_line(reqs),
+      ForEachRef::within(checks.ref_pattern()).command_line(reqs),
+      unpushed.command_line(reqs),
+      describe.command_line(reqs),
+      tracked_ignored.command_line(reqs),
+      in_progress.command_line(reqs),
+      uncommitted.command_line(reqs),
+    ];
+    for command in commands.into_iter().flatten() {
+      println!("{}", command)
+    }
+    std::process::exit(0)
+  }
+
+  if let Some(repos) = opt.values_of("repo") {
+    let base = std::env::current_dir().unwrap_or_else(&error_status(129));
+    let glyphs = &config.glyphs;
+    let json = opt.is_present("json");
+    // Each repository's own git config applies to it.
+    let summaries = check_repos(repo_paths(repos), |repo| {
+      std::env::set_current_dir(base.join(repo)).map_err(|e| Failure { status: 129, message: format!("error: {}\n", e) })?;
+      if let Some(message) = outside_work_tree(&git::exec::System) {
+        return Err(Failure { status: 140, message })
+      }
+      let git_config = read_git_config()?;
+      let opt = configured(&args, &git_config)?;
+      let (checks, offline, reqs) = plan(&opt, &git_config);
+      let summary = analyze(&opt, checks, glyphs, &git_config, offline, reqs)?;
+      let shown = report_at(&summary, Verbosity::from_opts(&opt), opt.is_present("only-failing")).is_some();
+      if !json && shown {
+        println!("{}:", repo);
+        print_report(&opt, &args, &summary);
+      }
+      Ok(summary)
+    });
+
+    if json {
+      println!("{}", repos_json(&summaries, &args, opt.is_present("only-failing")));
+    }
+    if summaries.iter().any(|(_, summary)| summary.is_err()) {
+      std::process::exit(140)
+    }
+    std::process::exit(exit_code(&opt, combined_exit(&summaries)))
+  }
+
+  let git_config = read_git_config().unwrap_or_else(Failure::exit);
+  let opt = configured(&args, &git_config).unwrap_or_else(Failure::exit);
+  let (checks, offline, reqs) = plan(&opt, &git_config);
+  // Otherwise every provider would fail in turn, each with git's own complaint.
+  if !offline && !opt.is_present("example") {
+    if let Some(message) = outside_work_tree(&git::exec::System) {
+      Failure { status: 140, message }.exit()
+    }
+  }
+  let summary = analyze(&opt, checks, &config.glyphs, &git_config, offline, reqs).unwrap_or_else(Failure::exit);
+  print_report(&opt, &args, &summary);
+  std::process::exit(exit_code(&opt, summary.exit_status()))
+}
+
+/// The confit.* settings from the git config of the repository in the
+/// current directory, failing with 136 if they're malformed.
+fn read_git_config() -> Result<config::GitConfig, Failure> {
+  let malformed = |message| Failure { status: 136, message };
+  let git_config = config::from_git(&git::exec::System).map_err(|e| malformed(format!("{:?}\n", e)))?;
+  git_config_tags(&git_config).map_err(|e| malformed(format!("error: {}\n", e)))?;
+  Ok(git_config)
+}
+
+/// The options parsed again with the configured format, if there is one,
+/// as --format's default.
+fn configured<'a>(args: &[OsString], git_config: &'a config::GitConfig) -> Result<ArgMatches<'a>, Failure> {
+  let opt = app_formatting(git_config.format.as_deref().unwrap_or("summary")).get_matches_from(args.to_vec());
+  format_targets(&opt).map_err(|e| Failure { status: 1, message: format!("error: {}\n", e) })?;
+  Ok(opt)
+}
+
+/// The checks to run, whether they're judged from data captured with
+/// --from-* options, and the data they need collected.
+fn plan(opt: &ArgMatches, git_config: &config::GitConfig) -> (Vec<&'static Check>, bool, Group) {
+  let mut checks = selected_checks(opt, &git_config.checks);
+
+  let offline = ["from-ls-remote", "from-status", "from-for-each-ref"]
+    .iter()
+    .any(|&source| opt.is_present(source));
+
+  // Under --strict-data, checks needing what wasn't supplied are kept, to fail.
+  if offline && !opt.is_present("strict-data") {
+    checks.restrict_to(supplied(opt))
+  }
+
+  // No check needs the diff; it's only counted for the summary.
+  let reqs = if opt.is_present("diff-stat") && !offline {
+    checks.required_sources() | datasource::DIFF
+  } else {
+    checks.required_sources()
+  };
+
+  debug!("required sources: {:?}", reqs);
+  (checks, offline, reqs)
+}
+
+/// The status to exit with: the checks' own, unless --no-fail overrides it.
+fn exit_code(opt: &ArgMatches, status: i32) -> i32 {
+  if opt.is_present("no-fail") {
+    debug!("exiting 0 instead of {} (--no-fail)", status);
+    0
+  } else {
+    status
+  }
+}
+
+/// The checks named with --checks and --checks-file, or else those
+/// configured, less any --skip'd; every check if none are named.
+fn selected_checks(opt: &ArgMatches, configured: &[String]) -> Vec<&'static Check> {
+  let mut tags: Vec<String> = opt.values_of("checks").into_iter().flatten().map(String::from).collect();
+  if let Some(path) = opt.value_of("checks-file") {
+    tags.extend(checks_file(path).expect("validated"))
+  }
+  if tags.is_empty() {
+    tags = configured.to_vec()
+  }
+
+  let mut checks = if tags.is_empty() {
+    Check::all_checks()
+  } else {
+    Check::tagged_checks(tags.iter().map(String::as_str))
+  };
+
+  if let Some(tags) = opt.values_of("skip") {
+    checks.skip(tags)
+  }
+  // git status only lists ignored files when asked to: with --ignored, or
+  // by naming the ignored check (not just "all").
+  let named = tags.iter().any(|tag| tag != "all" && preserves::tag_matches(tag, "ignored"));
+  if !opt.is_present("ignored") && !named {
+    checks.skip(vec!["ignored"])
+  }
+  checks
+}
+
+/// Checks that the tags set in git config name checks, as --checks would.
+fn git_config_tags(git_config: &config::GitConfig) -> Result<(), String> {
+  for tag in &git_config.checks {
+    valid_tag(tag.clone()).map_err(|e| format!("git config confit.checks: {}", e))?
+  }
+  for tag in git_config.thresholds.keys() {
+    let key = format!("git config confit.threshold.{}", tag.replace('_', "-"));
+    match Check::by_tag(tag) {
+      None => return Err(format!("{}: no check is tagged '{}'", key, tag)),
+      Some(check) if !check.has_threshold() => return Err(format!("{}: {} has no threshold to set", key, tag)),
+      Some(_) => (),
+    }
+  }
+  Ok(())
+}
+
+/// The tags listed in a file: separated by commas or newlines, with blank
+/// lines and anything after a # left out.
+fn checks_file(path: &str) -> Result<Vec<String>, String> {
+  let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+  let tags: Vec<String> = text.lines()
+    .map(|line| line.split('#').next().unwrap_or_default())
+    .flat_map(|line| line.split(','))
+    .map(str::trim)
+    .filter(|tag| !tag.is_empty())
+    .map(String::from)
+    .collect();
+  if tags.is_empty() {
+    return Err(format!("no checks listed in {}", path))
+  }
+  for tag in &tags {
+    valid_tag(tag.clone()).map_err(|e| format!("{}: {}", path, e))?
+  }
+  Ok(tags)
+}
+
+/// Why git can't be asked about this directory, if it can't.
+fn outside_work_tree(runner: &impl git::exec::Runner) -> Option<String> {
+  let probe = doctor::work_tree(runner);
+  (probe.verdict == doctor::Verdict::Fail)
+    .then(|| format!("error: not inside a git repository\n  {}\n", probe.message))
+}
+
+/// Marks the summary as being of a shallow clone, if it is one and any of
+/// the checks would be misled by that.
+fn check_history(summary: &mut Summary, runner: &impl git::exec::Runner) {
+  if summary.needs_history() && doctor::is_shallow(runner) {
+    summary.mark_shallow()
+  }
+}
+
+/// The data given with --from-* options, in place of running git.
+fn supplied(opt: &ArgMatches) -> Group {
+  [
+    (LsRemote::default().provides(), "from-ls-remote"),
+    (GetStatus::default().provides(), "from-status"),
+    (ForEachRef::default().provides(), "from-for-each-ref"),
+  ].iter()
+    .filter(|(_, source)| opt.is_present(source))
+    .fold(datasource::EMPTY, |acc, &(group, _)| acc | group)
+}
+
+/// The providers for status, the unpushed log, the latest tag, ignored
+/// tracked files, operations in progress and the diff of uncommitted work,
+/// as the options shape them.
+fn providers(opt: &ArgMatches, checks: &[&Check]) -> (GetStatus, Unpushed, Describe, TrackedIgnored, InProgress, Uncommitted) {
+  let mut status = GetStatus::within(opt.values_of("paths").map_or(vec![], |paths| paths.map(String::from).collect()));
+  if opt.is_present("dirty-submodules") {
+    status.count_dirty_submodules()
+  }
+  if opt.is_present("ignored") || checks.iter().any(preserves::select::by_tag(vec!["ignored"])) {
+    status.list_ignored()
+  }
+
+  let mut unpushed = opt.value_of("since").map_or_else(Unpushed::default, |rev| Unpushed::since(rev.into()));
+
+  let mut describe = Describe::default();
+  let mut tracked_ignored = TrackedIgnored::default();
+  let mut in_progress = InProgress::default();
+  let mut uncommitted = Uncommitted::default();
+
+  if let Some(rev) = opt.value_of("at") {
+    status.at(rev.into());
+    unpushed.at(rev.into());
+    describe.at(rev.into());
+    tracked_ignored.at(rev.into());
+    in_progress.at(rev.into());
+    uncommitted.at(rev.into());
+  }
+  (status, unpushed, describe, tracked_ignored, in_progress, uncommitted)
+}
+
+/// Runs the checks over the workspace in the current directory (or the
+/// captured data, when offline), configured by the options.
+fn analyze<'c>(
+  opt: &ArgMatches,
+  checks: Vec<&'c Check>,
+  glyphs: &HashMap<String, String>,
+  git_config: &config::GitConfig,
+  offline: bool,
+  reqs: Group,
+) -> Result<Summary<'c>, Failure> {
+  let (status, unpushed, describe, tracked_ignored, in_progress, uncommitted) = providers(opt, &checks);
+  let for_each_ref = ForEachRef::within(checks.ref_pattern());
+
+  let mut summary = if opt.is_present("example") {
+    example(&mut load_rng(opt.value_of("seed-file")), checks)
+  } else {
+    Summary::new(LsRemote::default().empty(), status.empty(), for_each_ref.empty(), checks)
+  };
+
+  if let Some(tags) = opt.values_of("warn") {
+    summary.warn_for(Check::tagged_checks(tags))
+  }
+
+  if let Some(hours) = opt.value_of("max-unpushed-age") {
+    summary.limit_unpushed_age(chrono::Duration::hours(hours.parse().expect("validated")))
+  }
+
+  if opt.value_of("sort") == Some("path") {
+    summary.sort_paths(PathOrder::Path)
+  }
+
+  if opt.is_present("sort-checks") {
+    summary.sort_checks()
+  }
+
+  if opt.value_of("exit-mode") == Some("weight") {
+    summary.exit_by(ExitMode::Weight)
+  }
+
+  match opt.value_of("gate") {
+    Some("preserve") => summary.gate_on(Gate::Preserve),
+    Some("release") => summary.gate_on(Gate::Release),
+    _ => (),
+  }
+
+  summary.draw_with(Glyphs::new(glyphs.clone(), opt.is_present("ascii")));
+  summary.set_thresholds(git_config.thresholds.clone());
+
+  if offline {
+    summary.set_ls_remote(read(LsRemote::default(), opt.value_of("from-ls-remote"), 128)?);
+    summary.status = read(status, opt.value_of("from-status"), 129)?;
+    summary.for_each_ref = read(ForEachRef::default(), opt.value_of("from-for-each-ref"), 130)?;
+    summary.available_only(supplied(opt));
+    summary.collected_from(supplied(opt));
+  } else if !opt.is_present("example") {
+    check_history(&mut summary, &git::exec::System);
+    let progress = Verbosity::from_opts(opt) == Verbosity::Full && !opt.is_present("json") && progress::visible();
+    let log_from_upstream = unpushed.logs_from_upstream();
+    let ls_remote = git::Retry::new(
+      LsRemote::of(git_config.remote.clone()),
+      opt.value_of("retries").expect("defaulted").parse().expect("validated"),
+      std::time::Duration::from_millis(opt.value_of("retry-delay").expect("defaulted").parse().expect("validated")),
+    );
+    let providers = Providers {
+      ls_remote,
+      status,
+      for_each_ref,
+      unpushed,
+      describe,
+      tracked_ignored,
+      in_progress,
+      uncommitted,
+      log_from_upstream,
+    };
+    collect_by_cost(&mut summary, providers, reqs, opt.is_present("fail-fast"), progress)?
+  }
+
+  trace!("status: {:#?}", summary.status);
+  trace!("for-each-ref: {:#?}", summary.for_each_ref);
+  trace!("ls-remote: {:#?}", summary.remote());
+  debug!("will exit: {}", summary.exit_status());
+  Ok(summary)
+}
+
+/// Prints the report in whichever form the options ask for.
+fn print_report(opt: &ArgMatches, args: &[OsString], summary: &Summary) {
+  write_report(opt, args, summary, &mut std::io::stdout().lock(), atty::is(atty::Stream::Stdout))
+}
+
+/// Writes the report in each format asked for: to its --output file if it
+/// has one, otherwise to `out`. Only there is it colored, if `terminal`.
+/// The options were parsed from `args`, which --json records.
+fn write_report(opt: &ArgMatches, args: &[OsString], summary: &Summary, out: &mut impl Write, terminal: bool) {
+  let only_failing = opt.is_present("only-failing");
+  if let Some(report) = report_at(summary, Verbosity::from_opts(opt), only_failing) {
+    let report = report.with_meta(meta(summary, args));
+    let all_clear = only_failing && report.is_empty();
+    let mut context = report_context(report);
+    let written = if opt.is_present("json") {
+      writeln!(out, "{}", context.into_json())
+    } else if opt.is_present("porcelain") {
+      write!(out, "{}", summary.porcelain())
+    } else if opt.is_present("count") {
+      writeln!(out, "{}", summary.totals())
+    } else {
+      let json = context.clone().into_json();
+      context.insert("details", &opt.is_present("details"));
+      context.insert("verbose", &opt.is_present("verbose"));
+      for (format, output) in format_targets(opt).expect("checked before collecting") {
+        context.insert("color", &(output.is_none() && terminal && std::env::var_os("NO_COLOR").is_none()));
+        let body = if format == JSON_FORMAT {
+          format!("{}\n", json)
+        } else if SILENT_FORMATS.contains(&format.as_str()) {
+          String::new()
+        } else if all_clear {
+          "all clear\n".to_string()
+        } else {
+          render(opt, &context, &format)
+        };
+        match output {
+          Some(path) => std::fs::write(&path, body).unwrap_or_else(|e| {
+            eprintln!("error: couldn't write {}: {}", path.display(), e);
+            std::process::exit(143)
+          }),
+          None => out.write_all(body.as_bytes()).unwrap_or_else(&error_status(143)),
+        }
+      }
+      Ok(())
+    };
+    written.unwrap_or_else(&error_status(143))
+  }
+}
+
+/// The report in one format, from the --template directory if there is one,
+/// or by the --template-file in place of any format.
+fn render(opt: &ArgMatches, context: &Context, format: &str) -> String {
+  if let Some(file) = opt.value_of("template-file") {
+    let t = load_template_file(Path::new(file)).unwrap_or_else(|e| {
+      eprint!("{}", e);
+      std::process::exit(132)
+    });
+    t.render(file, context)
+      .unwrap_or_else(&template_error_status(file, 131))
+  } else if let Some(tdir) = opt.value_of("template") {
+    let t = load_templates(Path::new(tdir)).unwrap_or_else(|e| {
+      eprint!("{}", e);
+      std::process::exit(132)
+    });
+    if t.get_template(format).is_err() {
+      eprint!("{}", missing_format(tdir, format, &t));
+      std::process::exit(131)
+    }
+    t.render(format, context)
+      .unwrap_or_else(&template_error_status(format, 131))
+  } else {
+    TMPL.render(format, context)
+      .unwrap_or_else(&template_error_status(format, 131))
+  }
+}
+
+/// Each format named with --format, and the file --output sends it to, if
+/// any. At most one (besides none) may be left to go to stdout.
+fn format_targets(opt: &ArgMatches) -> Result<Vec<(String, Option<PathBuf>)>, String> {
+  let formats: Vec<&str> = opt.value_of("format").expect("defaulted")
+    .split(',')
+    .map(str::trim)
+    .filter(|format| !format.is_empty())
+    .collect();
+  let mut outputs = HashMap::new();
+  for output in opt.values_of("output").into_iter().flatten() {
+    let (format, path) = output.split_once('=').expect("validated");
+    if !formats.contains(&format) {
+      return Err(format!("--output {}: {} isn't one of the formats given with --format", output, format))
+    }
+    outputs.insert(format, PathBuf::from(path));
+  }
+  let targets: Vec<_> = formats.iter().map(|&format| (format.to_string(), outputs.remove(format))).collect();
+  let to_stdout: Vec<_> = targets.iter()
+    .filter(|(format, output)| output.is_none() && !SILENT_FORMATS.contains(&format.as_str()))
+    .map(|(format, _)| format.as_str())
+    .collect();
+  if to_stdout.len() > 1 {
+    return Err(format!("formats {} would all go to stdout; send all but one to files with --output FORMAT=PATH", to_stdout.join(", ")))
+  }
+  Ok(targets)
+}
+
+/// The JSON reports of several repositories, each with its path.
+fn repos_json(summaries: &[(String, Result<Summary, String>)], args: &[OsString], only_failing: bool) -> serde_json::Value {
+  summaries.iter().map(|(repo, summary)| {
+    let mut json = match summary {
+      Ok(summary) => {
+        let report = if only_failing { summary.report().only_failing() } else { summary.report() };
+        report_context(report.with_meta(meta(summary, args))).into_json()
+      },
+      Err(message) => serde_json::json!({"error": message.trim_end()}),
+    };
+    json["path"] = repo.as_str().into();
+    json
+  }).collect()
+}
+
+/// The exit statuses of the repositories that could be checked, combined by
+/// bitwise OR.
+fn combined_exit(summaries: &[(String, Result<Summary, String>)]) -> i32 {
+  summaries.iter()
+    .filter_map(|(_, summary)| summary.as_ref().ok())
+    .fold(0, |status, summary| status | summary.exit_status())
+}
+
+/// Checks each repository in turn. One that can't be checked is reported,
+/// and the rest are checked all the same.
+fn check_repos<'c>(
+  repos: Vec<String>,
+  mut check: impl FnMut(&str) -> Result<Summary<'c>, Failure>,
+) -> Vec<(String, Result<Summary<'c>, String>)> {
+  repos.into_iter().map(|repo| {
+    let summary = check(&repo).map_err(|failure| {
+      eprint!("{}: {}", repo, failure.message);
+      failure.message
+    });
+    (repo, summary)
+  }).collect()
+}
+
+/// The paths given with --repo, with a last component like * or web-?,
+/// quoted from the shell, expanded to the directories it matches, in order.
+/// Hidden ones are left out unless the pattern starts with a dot. A pattern
+/// matching nothing is kept, to be reported as it is.
+fn repo_paths<'a>(repos: impl IntoIterator<Item=&'a str>) -> Vec<String> {
+  repos.into_iter().flat_map(|repo| {
+    let path = Path::new(repo);
+    let pattern = match path.file_name().and_then(|name| name.to_str()) {
+      Some(name) if name.contains(['*', '?']) => name,
+      _ => return vec![repo.to_string()],
+    };
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let mut matched: Vec<String> = std::fs::read_dir(parent).into_iter().flatten().flatten()
+      .filter(|entry| entry.path().is_dir())
+      .filter_map(|entry| entry.file_name().into_string().ok())
+      .filter(|name| pattern.starts_with('.') || !name.starts_with('.'))
+      .filter(|name| preserves::tag_matches(pattern, name))
+      .map(|name| path.with_file_name(name).to_string_lossy().into_owned())
+      .collect();
+    matched.sort();
+    if matched.is_empty() { vec![repo.to_string()] } else { matched }
+  }).collect()
+}
+
+/// The app with the summary as the default format, as the options are first
+/// parsed: before git config, which can set another, has been read.
+fn app() -> App<'static, 'static> {
+  build_app(false, "summary")
+}
+
+/// The app, with a default format other than the summary.
+fn app_formatting(format: &str) -> App<'_, '_> {
+  build_app(false, format)
+}
+
+/// The app as shell completion should see it: with the check tags and
+/// formats as the possible values of the options taking them. Those aren't
+/// enforced when parsing, which has to allow globs and external templates.
+fn completion_app() -> App<'static, 'static> {
+  build_app(true, "summary")
+}
+
+fn build_app<'a>(listing: bool, format: &'a str) -> App<'a, 'a> {
+  let listed = |arg: Arg<'a, 'a>, values: &[&'a str]| {
+    if listing { arg.possible_values(values).hide_possible_values(true) } else { arg }
+  };
+  App::new("Confit")
     //.version(option_env!("CARGO_PKG_VERSION").unwrap_or("dev"))
     .version(crate_version!())
     .author(crate_authors!(", "))
     .setting(AppSettings::VersionlessSubcommands)
+    .setting(AppSettings::ArgsNegateSubcommands)
     .setting(AppSettings::ColoredHelp)
     .about("makes sure your work is properly preserved in git")
     .long_about(include_str!("about.txt"))
     .after_help(include_str!("after.txt"))
-    .subcommand(subcommands::write_templates::def())
+    .setting(AppSettings::AllArgsOverrideSelf)
+    .subcommands(subcommands())
     .arg(
       Arg::with_name("debug")
       .long("debug")
-      .help("outputs debug data")
+      .help("logs debug data to stderr (otherwise, set RUST_LOG)")
     )
     .arg(
       Arg::with_name("quiet")
       .long("quiet")
       .short("q")
-      .help("suppress normal state summary; scripts can rely on the status code")
+      .help("leaves passing checks out of the summary; given twice (-qq), prints nothing, so scripts can rely on the status code")
+      .multiple(true)
       .conflicts_with("format")
       .conflicts_with("template")
     )
-    .arg(
+    .arg(listed(
       Arg::with_name("format")
       .long("format")
       .short("f")
-      .help(format!("choose a format for output [included: {}]",
-          TMPL.get_template_names()
-          .filter(|&n| n != "macros")
-          .collect::<Vec<_>>().as_slice().join(", ")).as_ref())
-      .default_value("summary")
+      .help(FORMAT_HELP.as_str())
+      .long_help(FORMAT_LONG_HELP.as_str())
+      .default_value(format),
+      &FORMATS,
+    ))
+    .arg(
+      Arg::with_name("output")
+      .long("output")
+      .short("o")
+      .value_name("FORMAT=PATH")
+      .help("writes one of the formats to a file rather than stdout, e.g. --format summary,json --output json=confit.json")
+      .long_help("writes one of the formats to a file rather than stdout, e.g. --format summary,json --output json=confit.json. Only one format can go to stdout; each of the others needs an --output. Files are never colored.")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1)
+      .conflicts_with("repo")
+      .validator(|output| match output.split_once('=') {
+        Some((format, path)) if !format.is_empty() && !path.is_empty() => Ok(()),
+        _ => Err(format!("'{}' should name a format and a file, as FORMAT=PATH", output)),
+      })
+    )
+    .arg(
+      Arg::with_name("ascii")
+      .long("ascii")
+      .help("draws checks with ASCII glyphs, for terminals and fonts that lack the usual ones")
+      .long_help("draws checks with ASCII glyphs (^ for unpushed commits, T for an untagged commit, and so on), for terminals and fonts that lack the usual ones. A [glyphs] table in .confit.toml sets the glyph for any check by tag.")
     )
     .arg(
       Arg::with_name("template")
@@ -74,20 +685,239 @@ fn main() -> ! {
       .help("provide a template source directory")
       .takes_value(true)
     )
+    .arg(
+      Arg::with_name("template-file")
+      .long("template-file")
+      .value_name("PATH")
+      .help("renders the report with the one template in PATH, rather than a format")
+      .long_help("renders the report with the one Tera template in PATH, rather than a format, with no directory to set up. It can import the built-in \"macros\". Errors name the file: 132 if it can't be read or parsed, 131 if it fails to render.")
+      .takes_value(true)
+      .conflicts_with("template")
+      .conflicts_with("format")
+      .conflicts_with("output")
+      .conflicts_with("quiet")
+      .conflicts_with("json")
+      .conflicts_with("porcelain")
+      .conflicts_with("count")
+    )
     .arg(
       Arg::with_name("json")
       .long("json")
       .short("j")
       .help("emits json rather than human readable report")
+      .long_help("emits json rather than human readable report. It records how it was made under \"meta\": the confit_version, the checks run (by their first tags), the args (CONFIT_OPTS's included), and a timestamp. `confit schema` prints a JSON Schema describing it.")
+      .conflicts_with("format")
+      .conflicts_with("template")
+      .conflicts_with("quiet")
+    )
+    .arg(
+      Arg::with_name("porcelain")
+      .long("porcelain")
+      .help("emits a stable, tab-separated line per check: STATUS GROUP TAG COUNT")
+      .long_help("emits a stable, tab-separated line per check: STATUS (pass, fail, or warn for a failure that's only a warning), GROUP, TAG and COUNT (empty for checks that simply fail). Unlike --json, this format is stable across releases; new fields will only be appended.")
+      .conflicts_with("format")
+      .conflicts_with("template")
+      .conflicts_with("quiet")
+      .conflicts_with("json")
+    )
+    .arg(
+      Arg::with_name("count")
+      .long("count")
+      .help("emits a single line of totals: checks=N passed=N failed=N warned=N")
       .conflicts_with("format")
       .conflicts_with("template")
       .conflicts_with("quiet")
+      .conflicts_with("json")
+      .conflicts_with("porcelain")
+    )
+    .arg(
+      Arg::with_name("from-ls-remote")
+      .long("from-ls-remote")
+      .help("reads captured `git ls-remote` output from a file (or - for stdin) instead of running git")
+      .takes_value(true)
+      .conflicts_with("example")
+    )
+    .arg(
+      Arg::with_name("from-status")
+      .long("from-status")
+      .help("reads captured `git status --branch --porcelain=v2` output from a file (or - for stdin) instead of running git")
+      .long_help("reads captured `git status --branch --porcelain=v2` output from a file (or - for stdin) instead of running git. When any --from-* option is given, git isn't run at all, and checks that need a source that wasn't supplied are left out.")
+      .takes_value(true)
+      .conflicts_with("example")
+    )
+    .arg(
+      Arg::with_name("from-for-each-ref")
+      .long("from-for-each-ref")
+      .help("reads captured `git for-each-ref` output (see --dry-run for the format) from a file (or - for stdin) instead of running git")
+      .takes_value(true)
+      .conflicts_with("example")
+    )
+    .group(
+      ArgGroup::with_name("captured")
+      .args(&["from-ls-remote", "from-status", "from-for-each-ref"])
+      .multiple(true)
+    )
+    .arg(
+      Arg::with_name("dry-run")
+      .long("dry-run")
+      .alias("show-commands")
+      .help("prints the git commands the selected checks would run, without running them")
+      .conflicts_with("example")
+    )
+    .arg(
+      Arg::with_name("details")
+      .long("details")
+      .help("lists the paths behind failing file checks, and how each conflict arose")
+      .long_help("lists the paths behind failing file checks, and how each conflict arose (both modified, deleted by us, and so on), under each failing check in the summary. Ignored files are gathered by top-level directory, as in \"node_modules/ (ignored, 4012 files)\".")
+    )
+    .arg(
+      Arg::with_name("verbose")
+      .long("verbose")
+      .short("v")
+      .help("shows which git data each check needs, and whether it was collected")
+      .long_help("shows which git data each check needs, and whether it was collected, named by the command that collects it, as in \"[status, ls-remote (not collected)]\". A check needing data that wasn't collected, because it's offline or there's no remote, fails as unevaluable. Each item in --json output lists its \"sources\" the same way.")
+    )
+    .arg(
+      Arg::with_name("diff-stat")
+      .long("diff-stat")
+      .help("counts the lines added and deleted in uncommitted files, totalled after the summary")
+      .long_help("counts the lines added and deleted in uncommitted files, staged and unstaged, with git diff --numstat, totalled after the summary, as in \"uncommitted: +120 -14 in 6 files\". Binary files count as files, without lines, and a renamed file goes by its new path. --json has each file's counts under \"diff_stat\".")
+    )
+    .arg(
+      Arg::with_name("only-failing")
+      .long("only-failing")
+      .help("leaves passing checks out of the report; prints \"all clear\" if none fail")
+      .long_help("leaves passing checks out of the report, in any format (or --json); prints \"all clear\" if none fail. Totals still count every check.")
+      .conflicts_with("porcelain")
+      .conflicts_with("count")
+    )
+    .arg(
+      Arg::with_name("dirty-submodules")
+      .long("dirty-submodules")
+      .help("counts submodules with changed content as unstaged changes, even if git is configured to ignore them")
+      .long_help("counts submodules with changed content as unstaged changes, even if git is configured to ignore them (submodule.<name>.ignore or diff.ignoreSubmodules), so vendored submodules are held to the same standard")
+      .conflicts_with("from-status")
+    )
+    .arg(
+      Arg::with_name("ignored")
+      .long("ignored")
+      .help("has git status list ignored files, for the ignored check, which only runs with this or when named with --checks; --details summarizes them by top-level directory")
+      .long_help("has git status list ignored files, for the ignored check, which only runs with this or when named with --checks, and warns that they aren't preserved. --details summarizes them by top-level directory; --json has both the groups and every path.")
+    )
+    .arg(
+      Arg::with_name("max-unpushed-age")
+      .long("max-unpushed-age")
+      .help("hours the newest unpushed commit may age before the stale_local check fails [default: 24]")
+      .long_help("hours the newest unpushed commit may age before the stale_local check fails [default: 24]. Takes precedence over confit.threshold.stale-local.")
+      .takes_value(true)
+      .validator(|hours| hours.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+    )
+    .arg(
+      Arg::with_name("repo")
+      .long("repo")
+      .value_name("PATH")
+      .help("checks this repository (or each matching a quoted glob, like 'checkouts/*'), in turn with any other --repo, exiting with their statuses ORed together")
+      .long_help("checks this repository (or each matching a quoted glob, like 'checkouts/*'), in turn with any other --repo, exiting with their statuses ORed together. Each report is printed under its path (unless there's nothing to report, as with -qq), or with --json, as an array of reports, each with its \"path\". A repository that can't be checked, because the path isn't one, its confit.* config is malformed or git fails in it, is reported (with --json, as an \"error\" in place of its report) and the rest are checked all the same, but then confit exits 140. Each repository's own git config applies to it.")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1)
+      .conflicts_with_all(&["from-ls-remote", "from-status", "from-for-each-ref", "example", "porcelain", "count"])
+    )
+    .arg(
+      Arg::with_name("at")
+      .long("at")
+      .value_name("REV")
+      .help("judges REV as if it were checked out, rather than the work tree")
+      .long_help("judges REV as if it were checked out, rather than the work tree, as for a release. The checks over files always pass, with no work tree to look at. A branch is compared with its upstream as of the last fetch; a tag or a bare commit leaves HEAD detached, so the detached check fails and track_remote only warns. The unpushed log runs up to REV. Checks of refs and the remote are unchanged.")
+      .takes_value(true)
+      .validator(|rev| git::verify_rev(&rev).map_err(|e| e.to_string()))
+      .conflicts_with_all(&["from-status", "paths", "dirty-submodules", "example"])
+    )
+    .arg(
+      Arg::with_name("since")
+      .long("since")
+      .value_name("REV")
+      .help("judges unpushed work by the commits made since REV, rather than since the upstream")
+      .long_help("judges unpushed work by the commits made since REV, rather than since the upstream, e.g. --since main, or --since v1.2 for work since the last release")
+      .takes_value(true)
+      .validator(|rev| git::verify_rev(&rev).map_err(|e| e.to_string()))
+    )
+    .arg(
+      Arg::with_name("sort")
+      .long("sort")
+      .help("orders the paths listed in check details: as git reports them, or by path")
+      .takes_value(true)
+      .possible_values(&["git", "path"])
+      .default_value("git")
+    )
+    .arg(
+      Arg::with_name("sort-checks")
+      .long("sort-checks")
+      .help("reports checks by status group, then label, rather than in the order they're defined")
+      .long_help("reports checks by status group, then label, rather than in the order they're defined, as a prompt that shows the most basic problems first would want")
+    )
+    .arg(
+      Arg::with_name("exit-mode")
+      .long("exit-mode")
+      .help("how failing checks make up the exit status: OR of their status group bits, or the sum of their weights")
+      .long_help("how failing checks make up the exit status: OR of their status group bits, or the sum of their weights, capped at 255. Every check weighs 1 unless a custom check sets, say, `weight = 10`. Since the sum can pass 127, it can be mistaken for one of confit's own errors.")
+      .takes_value(true)
+      .possible_values(&["bits", "weight"])
+      .default_value("bits")
+    )
+    .arg(
+      Arg::with_name("gate")
+      .long("gate")
+      .help("which failing checks count toward the exit status: those of unsaved work (status groups 1-3), of unreleased work (group 4), or all")
+      .long_help("which failing checks count toward the exit status: those of unsaved work (status groups 1-3), of unreleased work (group 4), or all, including custom checks in groups 0, 5 and 6. Every check is still reported either way, so a pipeline can save work in one stage and release it in another, gating each on its own checks.")
+      .takes_value(true)
+      .possible_values(&["preserve", "release", "all"])
+      .default_value("all")
+    )
+    .arg(
+      Arg::with_name("no-fail")
+      .long("no-fail")
+      .visible_alias("exit-zero")
+      .help("reports as usual, but always exits 0, whatever the checks found")
+      .long_help("reports as usual, but always exits 0, whatever the checks found, as for an informational CI step; exit_code still says what the status would have been")
+    )
+    .arg(
+      Arg::with_name("strict-data")
+      .long("strict-data")
+      .help("with --from-* options, fails the checks needing data that wasn't supplied, rather than leaving them out")
+      .long_help("with --from-* options, fails the checks needing data that wasn't supplied, rather than leaving them out, with evaluable set to false in --json output, so a missing file can't pass unnoticed")
+      .requires("captured")
+    )
+    .arg(
+      Arg::with_name("fail-fast")
+      .long("fail-fast")
+      .help("stops collecting data, cheapest source first, as soon as a check fails; skipped checks aren't reported")
+      .long_help("stops collecting data, cheapest source first (status, then local refs, then the remote), as soon as a check it can already judge fails, so a dirty workspace is reported without waiting on the network; skipped checks aren't reported")
+      .conflicts_with("example")
+    )
+    .arg(
+      Arg::with_name("retries")
+      .long("retries")
+      .value_name("N")
+      .help("tries contacting the remote again up to N times after network failures")
+      .long_help("tries contacting the remote again up to N times after failures that look like network trouble, waiting --retry-delay before the first. Authentication failures and unknown remotes aren't retried.")
+      .takes_value(true)
+      .default_value("0")
+      .validator(|n| n.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+    )
+    .arg(
+      Arg::with_name("retry-delay")
+      .long("retry-delay")
+      .value_name("MS")
+      .help("milliseconds to wait before the first retry, doubling for each after")
+      .takes_value(true)
+      .default_value("500")
+      .validator(|ms| ms.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
     )
     .arg(
       Arg::with_name("example")
       .long("example")
-      .help("generates example output for template development")
-      .conflicts_with("checks")
+      .help("generates example output for template development, for the selected checks")
     )
     .arg(
       Arg::with_name("seed-file")
@@ -96,105 +926,437 @@ fn main() -> ! {
       .requires("example")
       .takes_value(true)
     )
-    .arg(
+    .arg(listed(
       Arg::with_name("checks")
       .long("checks")
       .short("c")
+      .help(CHECKS_HELP.as_str())
+      .long_help(CHECKS_LONG_HELP.as_str())
       .use_delimiter(true)
+      .require_delimiter(true)
       .takes_value(true)
       .multiple(true)
-      .possible_values(&Check::all_tags()))
-    .get_matches();
-
-    if let (name, Some(sub_opt)) = opt.subcommand() {
-      match name {
-        "write-templates" => subcommands::write_templates::run(sub_opt),
-        _ => {
-          println!("Unknown subcommand: {}", name);
-        } //?
-      }
-
-      std::process::exit(0)
-    }
-
-    let mut checks = if let Some(tags) = opt.values_of("checks") {
-      Check::tagged_checks(tags)
-    } else {
-      Check::all_checks()
-    };
-
-    let reqs = checks.required_sources();
-
-    if opt.is_present("debug") {
-      println!("Required sources: {:?}", reqs)
-    }
-
-    let summary = if opt.is_present("example") {
-      let mut r = load_rng(opt.value_of("seed-file"));
-      Summary::new(
-        (Faker, 0..10).fake_with_rng(&mut r),
-        Faker.fake_with_rng(&mut r),
-        (Faker, 0..10).fake_with_rng(&mut r),
-        Check::all_checks()
-      )
-    } else {
-      Summary::new(
-        collect(LsRemote, reqs, 128),
-        collect(GetStatus, reqs, 129),
-        collect(ForEachRef, reqs, 130),
-        checks
-      )
-    };
-
-    if opt.is_present("debug") {
-      println!("{:#?}\n{:#?}\n{:#?}", summary.status, summary.for_each_ref, summary.ls_remote);
-    }
-
-    if opt.is_present("debug") {
-      println!("will exit: {}", summary.exit_status())
-    }
-
-    if !opt.is_present("quiet") {
-        let mut context = Context::default();
-        context.insert("items", &summary.items());
-        context.insert("status", &summary.status);
-      if opt.is_present("json") {
-        println!("{}", context.into_json());
-      } else {
-        //println!("status: {}", serde_json::to_string(&summary.status)?);
-        //println!("items: {}", serde_json::to_string(&summary.items())?);
-        let body = if let Some(tdir) = opt.value_of("template") {
-          let tpath = Path::new(tdir).join("**");
-          let t = Tera::new(
-            tpath.to_str()
-            .ok_or("couldn't convert path to utf8")
-            .unwrap_or_else(&error_status(133))
-          ).unwrap_or_else(&error_status(132));
-          t.render(opt.value_of("format").expect("format has no value"), &context)
-            .unwrap_or_else(&error_status(131))
-        } else {
-          TMPL.render(opt.value_of("format").expect("format has no value"), &context)
-            .unwrap_or_else(&error_status(131))
-        };
+      .validator(valid_tag),
+      &TAGS,
+    ))
+    .arg(
+      Arg::with_name("checks-file")
+      .long("checks-file")
+      .value_name("PATH")
+      .help("runs the checks listed in a file, one per line or comma-separated, with # comments; adds to --checks")
+      .long_help("runs the checks listed in a file, one per line or comma-separated, with blank lines and # comments ignored, as well as any given with --checks; a longer list can be shared between repositories this way")
+      .takes_value(true)
+      .validator(|path| checks_file(&path).map(|_| ())))
+    .arg(listed(
+      Arg::with_name("skip")
+      .long("skip")
+      .help("leaves out checks with these tags, e.g. --checks all --skip merge")
+      .long_help("leaves out checks with these tags, e.g. --checks all --skip merge; tags may be globs, comma-separated or repeated, as for --checks")
+      .use_delimiter(true)
+      .require_delimiter(true)
+      .takes_value(true)
+      .multiple(true)
+      .validator(valid_tag),
+      &TAGS,
+    ))
+    .arg(
+      Arg::with_name("paths")
+      .help("limits the checks over files to these paths (passed to git status as pathspecs)")
+      .multiple(true)
+      .conflicts_with("from-status")
+      .conflicts_with("example")
+    )
+    .arg(listed(
+      Arg::with_name("warn")
+      .long("warn")
+      .short("w")
+      .help("report failures of these checks as warnings, without affecting the exit status")
+      .long_help("report failures of these checks as warnings (⚠, in yellow on a terminal), without affecting the exit status: they're neither passed nor failed. Checks that are warnings by default, like stale_local, and custom checks with `severity = \"warn\"`, behave the same way. Tags may be globs, comma-separated or repeated, as for --checks.")
+      .long_help("limits the checks over files to these paths (passed to git status as pathspecs), as in a monorepo; the branch and remote checks are unaffected")
+      .use_delimiter(true)
+      .require_delimiter(true)
+      .takes_value(true)
+      .multiple(true)
+      .validator(valid_tag),
+      &TAGS,
+    ))
+}
 
-        print!("{}", body);
-      }
-    }
+/// The names that start a subcommand, rather than a report.
+fn subcommand_names() -> Vec<String> {
+  let mut names: Vec<_> = subcommands().iter().map(|sub| sub.get_name().to_string()).collect();
+  names.push("help".into());
+  names
+}
 
-    std::process::exit(summary.exit_status())
+fn subcommands() -> Vec<App<'static, 'static>> {
+  vec![
+    subcommands::write_templates::def(),
+    subcommands::schema::def(),
+    subcommands::doctor::def(),
+    subcommands::check_templates::def(),
+    subcommands::completions::def(),
+  ]
 }
 
-fn collect<T>( provider: impl git::Provider<Data = T>, reqs: Group, errcode: i32,) -> T {
-  provider.collect(reqs).unwrap_or_else(&error_status(errcode))
+/// Puts the flags from CONFIT_OPTS, split as a shell would, before those
+/// given on the command line, so that the command line's take precedence.
+/// Subcommands don't take them.
+fn with_default_opts(mut args: Vec<OsString>, opts: Option<&str>) -> Result<Vec<OsString>, shell_words::ParseError> {
+  let subcommand = args.get(1).and_then(|arg| arg.to_str()).map_or(false, |arg| subcommand_names().iter().any(|name| name == arg));
+  if let (Some(opts), false) = (opts, subcommand) {
+    let defaults = bounded(shell_words::split(opts)?);
+    let at = args.len().min(1);
+    args.splice(at..at, defaults.into_iter().map(OsString::from));
+  }
+  Ok(args)
 }
 
-fn error_status<T, E: core::fmt::Debug>(n: i32) -> impl Fn(E) -> T {
-  return move |e: E| {
-    println!("{:?}", e);
-    std::process::exit(n)
+/// The long name of an option whose values run on to the next flag.
+fn list_option(word: &str) -> Option<&'static str> {
+  match word {
+    "-c" | "--checks" => Some("--checks"),
+    "--skip" => Some("--skip"),
+    "-w" | "--warn" => Some("--warn"),
+    "--repo" => Some("--repo"),
+    _ => None,
   }
 }
 
+/// The words of CONFIT_OPTS, with each value of a list option joined to it
+/// by =, as in --checks=push, which ends it there. Otherwise the last list
+/// option would take the command line's paths as more of its values.
+fn bounded(words: Vec<String>) -> Vec<String> {
+  let mut out = vec![];
+  let mut list: Option<(&str, bool)> = None;
+  for word in words {
+    if let Some((long, taken)) = list.as_mut() {
+      if !word.starts_with('-') {
+        out.push(format!("{}={}", long, word));
+        *taken = true;
+        continue
+      }
+      if !*taken {
+        out.push(long.to_string())
+      }
+    }
+    list = list_option(&word).map(|long| (long, false));
+    if list.is_none() {
+      out.push(word)
+    }
+  }
+  if let Some((long, false)) = list {
+    out.push(long.to_string())
+  }
+  out
+}
+
+fn valid_tag(tag: String) -> Result<(), String> {
+  if Check::all_tags().iter().any(|known| preserves::tag_matches(&tag, known)) {
+    return Ok(())
+  }
+  if tag.contains(['*', '?']) {
+    return Err(format!("no check matches '{}' [possible values: {}]", tag, Check::all_tags().join(", ")))
+  }
+  Err(match Check::suggest_tag(&tag) {
+    Some(near) => format!("unknown check '{}': did you mean '{}'?", tag, near),
+    None => format!("unknown check '{}' [possible values: {}]", tag, Check::all_tags().join(", ")),
+  })
+}
+
+/// How much of the report -q leaves: everything, the failures, or nothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Verbosity {
+  Full,
+  Failures,
+  Silent,
+}
+
+/// The formats that print nothing, as -qq does, for wrappers that always
+/// pass --format.
+const SILENT_FORMATS: &[&str] = &["none", "null"];
+
+// The --json document, as a format, so that it can go to a file alongside another.
+const JSON_FORMAT: &str = "json";
+
+impl Verbosity {
+  fn from_quiet(occurrences: u64) -> Self {
+    match occurrences {
+      0 => Verbosity::Full,
+      1 => Verbosity::Failures,
+      _ => Verbosity::Silent,
+    }
+  }
+
+  fn from_opts(opt: &ArgMatches) -> Self {
+    if opt.value_of("format").map_or(false, |formats| formats.split(',').all(|format| SILENT_FORMATS.contains(&format.trim()))) {
+      Verbosity::Silent
+    } else {
+      Verbosity::from_quiet(opt.occurrences_of("quiet"))
+    }
+  }
+}
+
+/// The report to print, if there's anything to print. With only the
+/// failures asked for and none to show, there isn't.
+fn report_at<'s>(summary: &'s Summary, verbosity: Verbosity, only_failing: bool) -> Option<Report<'s>> {
+  match verbosity {
+    Verbosity::Silent => None,
+    Verbosity::Failures => Some(summary.report().only_failing()).filter(|report| !report.is_empty()),
+    Verbosity::Full if only_failing => Some(summary.report().only_failing()),
+    Verbosity::Full => Some(summary.report()),
+  }
+}
+
+fn report_context(report: Report) -> Context {
+  Context::from_serialize(report).expect("report serializes to a map")
+}
+
+/// How this run's report is produced: this version of confit, with these
+/// checks and arguments, now.
+/// How the report was made. The arguments are those the options were parsed
+/// from, CONFIT_OPTS's included, less the program's name.
+fn meta(summary: &Summary, args: &[OsString]) -> Meta {
+  Meta::new(crate_version!(), summary.checks(), args.iter().skip(1).map(|arg| arg.to_string_lossy().into_owned()).collect())
+}
+
+fn example<'a>(r: &mut impl Rng, checks: Vec<&'a Check>) -> Summary<'a> {
+  Summary::new(
+    (Faker, 0..10).fake_with_rng(r),
+    Faker.fake_with_rng(r),
+    (Faker, 0..10).fake_with_rng(r),
+    checks
+  )
+}
+
+/// Where collect_by_cost gets each kind of data: from git, or in tests,
+/// from canned output.
+#[derive(Default)]
+struct Providers<R, S, F, U, D, T, I, C> {
+  ls_remote: R,
+  status: S,
+  for_each_ref: F,
+  unpushed: U,
+  describe: D,
+  tracked_ignored: T,
+  in_progress: I,
+  uncommitted: C,
+  /// Whether the unpushed log runs from the upstream, so that it's only
+  /// worth asking for with commits ahead of it.
+  log_from_upstream: bool,
+}
+
+fn collect<T>(provider: impl git::Provider<Data = T>, reqs: Group, errcode: i32) -> Result<T, Failure> {
+  provider.collect(reqs).map_err(git_failure(errcode))
+}
+
+/// Collects data cheapest source first: status, then local refs, the log of
+/// unpushed commits, the latest tag, ignored tracked files, operations in
+/// progress and the diff, then the remote. With fail_fast, stops as soon as
+/// the checks that can be judged so far include a failure, dropping the
+/// checks that would need more. The log from the upstream is only asked for
+/// when there are commits ahead of it. With progress, a spinner shows while
+/// the remote is contacted.
+fn collect_by_cost<R, S, F, U, D, T, I, C>(
+  summary: &mut Summary,
+  providers: Providers<R, S, F, U, D, T, I, C>,
+  reqs: Group,
+  fail_fast: bool,
+  progress: bool,
+) -> Result<(), Failure>
+where
+  R: git::Provider<Data = Vec<git::RefPair>>,
+  S: git::Provider<Data = git::Status>,
+  F: git::Provider<Data = Vec<git::RefLine>>,
+  U: git::Provider<Data = Option<chrono::DateTime<chrono::Utc>>>,
+  D: git::Provider<Data = Option<git::Description>>,
+  T: git::Provider<Data = Vec<git::parse::WorkPath>>,
+  I: git::Provider<Data = Vec<git::Operation>>,
+  C: git::Provider<Data = git::DiffStat>,
+{
+  let Providers { ls_remote, status, for_each_ref, unpushed, describe, tracked_ignored, in_progress, uncommitted, log_from_upstream } = providers;
+  let stop = |summary: &mut Summary, collected| {
+    summary.collected_from(collected);
+    let failed = fail_fast && summary.fails_within(collected);
+    if failed {
+      summary.restrict_to(collected)
+    }
+    failed
+  };
+
+  let mut collected = status.provides();
+  summary.status = collect(status, reqs, 129)?;
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  collected = collected | for_each_ref.provides();
+  summary.for_each_ref = collect(for_each_ref, reqs, 130)?;
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  // Without commits ahead of a fetched upstream, there's no log to ask for;
+  // a log since some other revision is always worth asking for.
+  collected = collected | unpushed.provides();
+  let ahead = summary.status.branch.as_ref().map_or(false, |b| b.commits.map_or(false, |c| c.0 > 0));
+  if ahead || !log_from_upstream {
+    summary.newest_unpushed = collect(unpushed, reqs, 137)?;
+  }
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  collected = collected | describe.provides();
+  summary.described = collect(describe, reqs, 138)?;
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  collected = collected | tracked_ignored.provides();
+  summary.tracked_ignored = collect(tracked_ignored, reqs, 139)?;
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  collected = collected | in_progress.provides();
+  summary.in_progress = collect(in_progress, reqs, 141)?;
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  collected = collected | uncommitted.provides();
+  if reqs.includes(uncommitted.provides()) {
+    summary.diff_stat = Some(collect(uncommitted, reqs, 142)?);
+  }
+  if stop(summary, collected) {
+    return Ok(())
+  }
+
+  // The remote is the slow one, and the spinner has to be gone before any error is reported.
+  let remote = {
+    let _spinner = (progress && reqs.includes(ls_remote.provides()))
+      .then(|| progress::Spinner::start("contacting remote…"));
+    ls_remote.collect(reqs)
+  };
+  match remote {
+    Err(git::Error::NoRemote) => summary.without_remote(),
+    remote => summary.set_ls_remote(remote.map_err(git_failure(128))?),
+  }
+  summary.collected_from(ls_remote.provides());
+  Ok(())
+}
+
+fn read<T>(provider: impl git::Provider<Data = T>, path: Option<&str>, errcode: i32) -> Result<T, Failure> {
+  match path {
+    Some(path) => provider.read_from(path).map_err(git_failure(errcode)),
+    None => Ok(provider.empty()),
+  }
+}
+
+fn error_status<T, E: core::fmt::Debug>(n: i32) -> impl Fn(E) -> T {
+  return move |e: E| {
+    println!("{:?}", e);
+    std::process::exit(n)
+  }
+}
+
+/// The .txt files beneath a directory, by their paths relative to it with
+/// the extension dropped, as the built-in templates are named: so
+/// "summary.txt" is the summary format, and `{% import "macros" %}` finds
+/// "macros.txt".
+pub(crate) fn template_files(dir: &Path) -> Vec<(String, PathBuf)> {
+  fn walk(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+      let path = entry.path();
+      if path.is_dir() {
+        walk(&path, paths)
+      } else if path.extension().map_or(false, |ext| ext == "txt") {
+        paths.push(path)
+      }
+    }
+  }
+  let mut paths = vec![];
+  walk(dir, &mut paths);
+  paths.sort();
+  paths.into_iter().map(|path| {
+    let name = path.strip_prefix(dir).unwrap_or(&path).with_extension("");
+    (name.to_string_lossy().into_owned(), path)
+  }).collect()
+}
+
+/// Loads the templates for --template, with confit's filters registered.
+pub(crate) fn load_templates(dir: &Path) -> Result<Tera, String> {
+  let files = template_files(dir);
+  if files.is_empty() {
+    return Err(format!("no templates (*.txt files) in {}\n", dir.display()))
+  }
+  let mut tera = Tera::default();
+  filters::register(&mut tera);
+  tera.add_template_files(files.iter().map(|(name, path)| (path, Some(name))).collect::<Vec<_>>())
+    .map_err(|e| template_error(&dir.to_string_lossy(), &e))?;
+  Ok(tera)
+}
+
+/// Loads the single template for --template-file, named by its path, so
+/// that errors name the file. The built-in templates come along, so that it
+/// can import "macros".
+pub(crate) fn load_template_file(path: &Path) -> Result<Tera, String> {
+  let mut tera = TMPL.clone();
+  tera.add_template_file(path, Some(&path.to_string_lossy()))
+    .map_err(|e| template_error(&path.to_string_lossy(), &e))?;
+  Ok(tera)
+}
+
+fn missing_format(dir: &str, format: &str, tera: &Tera) -> String {
+  format!("no template for format '{}' in {} (found: {})\n", format, dir, template_formats(tera).join(", "))
+}
+
+/// Why the checks couldn't be run: what to say, and the status to exit with.
+#[derive(Debug)]
+struct Failure {
+  status: i32,
+  message: String,
+}
+
+impl Failure {
+  fn exit<T>(self) -> T {
+    eprint!("{}", self.message);
+    std::process::exit(self.status)
+  }
+}
+
+fn git_failure(n: i32) -> impl Fn(git::Error) -> Failure {
+  move |e: git::Error| Failure { status: n, message: git_error(&e) }
+}
+
+/// Describes a failure to get data from git, in full: what git said, when
+/// it was git that failed.
+fn git_error(e: &git::Error) -> String {
+  format!("error: {}\n", e)
+}
+
+fn template_error_status<T>(name: &str, n: i32) -> impl Fn(tera::Error) -> T + '_ {
+  move |e: tera::Error| {
+    eprint!("{}", template_error(name, &e));
+    std::process::exit(n)
+  }
+}
+
+/// Describes a template failure: which template, then each cause in turn.
+/// Tera's syntax errors carry the line and column where parsing stopped.
+fn template_error(name: &str, e: &tera::Error) -> String {
+  let mut message = format!("error in template '{}':\n", name);
+  let mut cause: Option<&dyn std::error::Error> = Some(e);
+  while let Some(e) = cause {
+    for line in e.to_string().lines().filter(|l| !l.trim().is_empty()) {
+      message.push_str(&format!("  {}\n", line));
+    }
+    cause = e.source();
+  }
+  message
+}
+
 fn load_rng(seedpath: Option<&str>) -> impl Rng {
   let ref mut seed = [0; 32];
   use std::fs::File;
@@ -216,6 +1378,867 @@ fn load_rng(seedpath: Option<&str>) -> impl Rng {
   StdRng::from_seed(*seed)
 }
 
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn context(summary: &Summary) -> Context {
+    report_context(summary.report())
+  }
+
+  #[test]
+  fn summary_shows_ahead_behind() {
+    let status = git::parse::status(
+      "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+       # branch.head feature\n\
+       # branch.upstream origin/feature\n\
+       # branch.ab +3 -1\n"
+    ).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::tagged_checks(vec!["push", "merge"]));
+    let body = TMPL.render("summary", &context(&summary)).unwrap();
+    assert!(body.contains("all commits pushed to remote: false(3) (↑3 ↓1)"), "{}", body);
+    assert!(body.contains("all commits merged from remote: false(1) (↑3 ↓1)"), "{}", body);
+  }
+
+  #[derive(Default)]
+  struct Captured<P>(P, &'static str);
+
+  // rev-parse's report of a git dir with nothing in progress, nor anything at all.
+  const NO_GIT_DIR: &str = "/nonexistent/.git\n/nonexistent/.git\n";
+
+  impl<P: Provider> Provider for Captured<P> {
+    type Data = P::Data;
+    const PROVIDES: Group = P::PROVIDES;
+    const COMMAND: &'static [&'static str] = P::COMMAND;
+
+    fn get(&self) -> Result<Self::Data, git::Error> {
+      self.0.parse(self.1)
+    }
+
+    fn parse(&self, input: &str) -> Result<Self::Data, git::Error> {
+      self.0.parse(input)
+    }
+
+    fn empty(&self) -> Self::Data {
+      self.0.empty()
+    }
+  }
+
+  #[derive(Default)]
+  struct Unreachable;
+
+  impl Provider for Unreachable {
+    type Data = Vec<git::RefPair>;
+    const PROVIDES: Group = datasource::REMOTE;
+    const COMMAND: &'static [&'static str] = git::exec::LS_REMOTE;
+
+    fn get(&self) -> Result<Self::Data, git::Error> {
+      panic!("fetched from the remote")
+    }
+
+    fn parse(&self, input: &str) -> Result<Self::Data, git::Error> {
+      LsRemote::default().parse(input)
+    }
+
+    fn empty(&self) -> Self::Data {
+      vec![]
+    }
+  }
+
+  #[derive(Default)]
+  struct NoRemote;
+
+  impl Provider for NoRemote {
+    type Data = Vec<git::RefPair>;
+    const PROVIDES: Group = datasource::REMOTE;
+    const COMMAND: &'static [&'static str] = git::exec::LS_REMOTE;
+
+    fn get(&self) -> Result<Self::Data, git::Error> {
+      Err(git::Error::NoRemote)
+    }
+
+    fn parse(&self, input: &str) -> Result<Self::Data, git::Error> {
+      LsRemote::default().parse(input)
+    }
+
+    fn empty(&self) -> Self::Data {
+      vec![]
+    }
+  }
+
+  const CLEAN: &str = "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+                       # branch.head master\n\
+                       # branch.upstream origin/master\n\
+                       # branch.ab +0 -0\n";
+
+  const DIRTY: &str = "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+                       # branch.head master\n\
+                       # branch.upstream origin/master\n\
+                       # branch.ab +0 -0\n\
+                       ? scratch.txt\n";
+
+  type Canned<R> = Providers<R, Captured<GetStatus>, Captured<ForEachRef>, Captured<Unpushed>, Captured<Describe>,
+    Captured<TrackedIgnored>, Captured<InProgress>, Captured<Uncommitted>>;
+
+  // Nothing from any source, a git dir with nothing in progress, and the
+  // unpushed log from the upstream.
+  fn canned<R: Default>() -> Canned<R> {
+    Providers { in_progress: Captured(InProgress::default(), NO_GIT_DIR), log_from_upstream: true, ..Default::default() }
+  }
+
+  fn collected(status: &'static str, checks: Vec<&'static Check>, fail_fast: bool) -> Summary<'static> {
+    let mut checks = checks;
+    let reqs = checks.required_sources();
+    let mut summary = Summary::new(vec![], Default::default(), vec![], checks);
+    collect_by_cost(&mut summary, Providers { status: Captured(GetStatus::default(), status), ..canned::<Unreachable>() }, reqs, fail_fast, false).unwrap();
+    summary
+  }
+
+  #[test]
+  fn fail_fast_skips_remote() {
+    let summary = collected(DIRTY, Check::all_checks(), true);
+    let mut local = Check::all_checks();
+    local.restrict_to(datasource::STATUS);
+    assert_eq!(summary.items().len(), local.len());
+    assert_ne!(summary.exit_status(), 0);
+  }
+
+  #[test]
+  fn fail_fast_honors_required_sources() {
+    let summary = collected(CLEAN, Check::tagged_checks(vec!["local"]), true);
+    assert_eq!(summary.items().len(), Check::tagged_checks(vec!["local"]).len());
+  }
+
+  #[test]
+  #[should_panic(expected = "fetched from the remote")]
+  fn remote_fetched_without_fail_fast() {
+    collected(DIRTY, Check::all_checks(), false);
+  }
+
+  #[test]
+  fn remote_checks_fail_without_a_remote() {
+    let mut checks = Check::tagged_checks(vec!["stage", "tag", "push_tag"]);
+    let reqs = checks.required_sources();
+    let mut summary = Summary::new(vec![], Default::default(), vec![], checks);
+    collect_by_cost(&mut summary, Providers { status: Captured(GetStatus::default(), CLEAN), ..canned::<NoRemote>() }, reqs, false, false).unwrap();
+
+    let stage = summary.result_for("stage").unwrap();
+    assert!(stage.passed());
+    let push_tag = summary.result_for("push_tag").unwrap();
+    assert!(!push_tag.passed());
+    let json = context(&summary).into_json();
+    assert_eq!(json["items"][2]["detail"]["unavailable"], "no remote configured");
+    assert!(TMPL.render("summary", &context(&summary)).unwrap().contains("(no remote configured)"));
+  }
+
+  // Answers any git command the same way.
+  struct Answer(bool, &'static str);
+
+  impl git::exec::Runner for Answer {
+    fn run(&self, _: &[&str]) -> git::exec::Result<git::exec::Ran> {
+      let Answer(success, out) = *self;
+      Ok(git::exec::Ran {
+        success,
+        stdout: if success { out.into() } else { String::new() },
+        stderr: if success { String::new() } else { out.into() },
+      })
+    }
+  }
+
+  #[test]
+  fn outside_any_repository() {
+    assert_eq!(outside_work_tree(&Answer(true, "true\n")), None);
+    assert_eq!(
+      outside_work_tree(&Answer(false, "fatal: not a git repository (or any of the parent directories): .git\n")).unwrap(),
+      "error: not inside a git repository\n  fatal: not a git repository (or any of the parent directories): .git\n"
+    );
+    assert!(outside_work_tree(&Answer(true, "false\n")).unwrap().starts_with("error: not inside a git repository\n"));
+  }
+
+  #[test]
+  fn no_fail_exits_zero() {
+    let summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files"]));
+    assert_ne!(summary.exit_status(), 0);
+    let opt = app().get_matches_from(vec!["confit"]);
+    assert_eq!(exit_code(&opt, summary.exit_status()), summary.exit_status());
+    for flag in &["--no-fail", "--exit-zero"] {
+      let opt = app().get_matches_from(vec!["confit", flag]);
+      assert_eq!(exit_code(&opt, summary.exit_status()), 0);
+    }
+    // The report is rendered all the same, failures included.
+    assert!(TMPL.render("summary", &context(&summary)).unwrap().contains(": false"));
+  }
+
+  #[test]
+  fn strict_data_fails_remote_checks() {
+    let opt = app().get_matches_from(vec!["confit", "--from-status", "-", "--strict-data", "-c", "stage,push_tag"]);
+    assert!(supplied(&opt) == datasource::STATUS);
+    // Without any --from-* option, there's nothing to be strict about.
+    assert!(app().get_matches_from_safe(vec!["confit", "--strict-data"]).is_err());
+
+    let mut summary = Summary::new(vec![], git::parse::status(CLEAN).unwrap(), vec![], Check::tagged_checks(vec!["stage", "push_tag"]));
+    summary.available_only(supplied(&opt));
+    assert!(summary.result_for("stage").unwrap().passed());
+    assert!(!summary.result_for("push_tag").unwrap().passed());
+    assert_eq!(summary.exit_status(), 1 << 4);
+
+    let json = context(&summary).into_json();
+    assert_eq!(json["items"][0]["evaluable"], true);
+    assert_eq!(json["items"][1]["evaluable"], false);
+    assert_eq!(json["items"][1]["detail"]["unavailable"], "its data wasn't collected");
+  }
+
+  #[test]
+  fn log_since_ignores_upstream() {
+    let mut checks = Check::tagged_checks(vec!["stale_local"]);
+    let reqs = checks.required_sources();
+    let collect = |log_from_upstream| {
+      let mut summary = Summary::new(vec![], Default::default(), vec![], Check::tagged_checks(vec!["stale_local"]));
+      let providers = Providers {
+        status: Captured(GetStatus::default(), CLEAN),
+        unpushed: Captured(Unpushed::since("v1.0".into()), "1600000000\n"),
+        log_from_upstream,
+        ..canned::<Unreachable>()
+      };
+      collect_by_cost(&mut summary, providers, reqs, false, false).unwrap();
+      summary.newest_unpushed
+    };
+    assert_eq!(collect(true), None);
+    assert_eq!(collect(false).map(|t| t.timestamp()), Some(1600000000));
+  }
+
+  #[test]
+  fn exit_code_in_context() {
+    let summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files", "push"]));
+    let json = context(&summary).into_json();
+    assert_eq!(json["exit_code"], summary.exit_status());
+    assert_ne!(json["exit_code"], 0);
+    assert_eq!(json["all_passed"], false);
+    assert!(TMPL.render("debug", &context(&summary)).unwrap().ends_with(&format!("exit code: {}\n", summary.exit_status())));
+
+    let clean = Summary::new(vec![], git::parse::status(CLEAN).unwrap(), vec![], Check::tagged_checks(vec!["track_files", "push"]));
+    let json = context(&clean).into_json();
+    assert_eq!(json["exit_code"], 0);
+    assert_eq!(json["all_passed"], true);
+    assert!(TMPL.render("debug", &context(&clean)).unwrap().ends_with("exit code: 0 (all passed)\n"));
+  }
+
+  #[test]
+  fn combined_repos() {
+    let ahead = "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+                 # branch.head master\n\
+                 # branch.upstream origin/master\n\
+                 # branch.ab +2 -0\n";
+    let repos = vec!["checkouts/dirty", "checkouts/broken", "checkouts/ahead"];
+    let summaries = check_repos(repos.into_iter().map(String::from).collect(), |repo| {
+      let mut checks = Check::tagged_checks(vec!["track_files", "push", "tag"]);
+      let reqs = checks.required_sources();
+      let mut summary = Summary::new(vec![], Default::default(), vec![], checks);
+      let (status, refs) = match repo {
+        "checkouts/dirty" => (DIRTY, ""),
+        "checkouts/broken" => (CLEAN, "not a ref listing\n"),
+        _ => (ahead, ""),
+      };
+      let providers = Providers {
+        status: Captured(GetStatus::default(), status),
+        for_each_ref: Captured(ForEachRef::default(), refs),
+        ..canned::<Unreachable>()
+      };
+      collect_by_cost(&mut summary, providers, reqs, false, false)?;
+      Ok(summary)
+    });
+
+    // The failure in one repository doesn't stop the next being checked.
+    assert_eq!(summaries.len(), 3);
+    assert!(matches!(&summaries[1].1, Err(message) if message.starts_with("error: ")), "{:?}", summaries[1].1.as_ref().err());
+    let json = repos_json(&summaries, &[], false);
+    let repos = json.as_array().unwrap();
+    assert_eq!(repos[0]["path"], "checkouts/dirty");
+    assert_eq!(repos[1]["path"], "checkouts/broken");
+    assert_eq!(repos[2]["path"], "checkouts/ahead");
+    assert!(repos[1].get("items").is_none() && repos[1]["error"].is_string());
+    assert!([&repos[0], &repos[2]].iter().all(|r| r["items"].as_array().unwrap().len() == 3));
+
+    let dirty = summaries[0].1.as_ref().unwrap().exit_status();
+    let unpushed = summaries[2].1.as_ref().unwrap().exit_status();
+    assert_eq!(repos[0]["exit_code"], dirty);
+    assert_ne!(dirty, unpushed);
+    assert_eq!(combined_exit(&summaries), dirty | unpushed);
+
+    let failing = repos_json(&summaries, &[], true);
+    assert_eq!(failing[0]["items"].as_array().unwrap().len(), 2);
+  }
+
+  #[test]
+  fn repo_globs() {
+    let dir = template_dir("repo-globs", &[("README", "")]);
+    for sub in &["web-a", "web-b", "api", ".cache"] {
+      std::fs::create_dir(dir.join(sub)).unwrap();
+    }
+    let within = |pattern: &str| dir.join(pattern).to_string_lossy().into_owned();
+    let expanded = repo_paths(vec![within("web-?").as_str(), within("*").as_str(), "plain", within("none-*").as_str()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(expanded, vec![within("web-a"), within("web-b"), within("api"), within("web-a"), within("web-b"), "plain".into(), within("none-*")]);
+  }
+
+  #[test]
+  fn suggestion_footer() {
+    let summary = Summary::new(vec![], Default::default(), vec![], Check::tagged_checks(vec!["push"]));
+    let body = TMPL.render("summary", &context(&summary)).unwrap();
+    assert!(body.ends_with("next: git push\n"), "{}", body);
+
+    let summary = Summary::new(vec![], Default::default(), vec![], vec![]);
+    assert_eq!(context(&summary).get("suggestion"), None);
+    assert!(!TMPL.render("summary", &context(&summary)).unwrap().contains("next:"));
+  }
+
+  #[test]
+  fn example_json_matches_schema() {
+    let schema: serde_json::Value = serde_json::from_str(&subcommands::schema::document()).unwrap();
+    let schema = jsonschema::JSONSchema::compile(&schema).unwrap();
+    let summary = example(&mut StdRng::seed_from_u64(328), Check::all_checks());
+    let document = report_context(summary.report().with_meta(meta(&summary, &[]))).into_json();
+    if let Err(errors) = schema.validate(&document) {
+      panic!("{}", errors.map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))
+    };
+  }
+
+  #[test]
+  fn json_records_how_it_was_made() {
+    let summary = Summary::new(vec![], Default::default(), vec![], Check::tagged_checks(vec!["stage", "push"]));
+    let args = with_default_opts(vec!["confit".into(), "--json".into()], Some("--format markdown -c stage")).unwrap();
+    let json = report_context(summary.report().with_meta(meta(&summary, &args))).into_json();
+    assert_eq!(json["meta"]["confit_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(json["meta"]["checks"], serde_json::json!(["push", "stage"]));
+    // CONFIT_OPTS's, as if they came first on the command line
+    assert_eq!(json["meta"]["args"], serde_json::json!(["--format", "markdown", "--checks=stage", "--json"]));
+    assert!(json["meta"]["timestamp"].as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().is_ok());
+    assert!(context(&summary).get("meta").is_none());
+  }
+
+  #[test]
+  fn conflict_details_listed() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-conflict-kinds")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::tagged_checks(vec!["conflict"]));
+    let mut context = context(&summary);
+    assert!(!TMPL.render("summary", &context).unwrap().contains("lib/legacy.rs"));
+
+    context.insert("details", &true);
+    let body = TMPL.render("summary", &context).unwrap();
+    assert!(body.contains("no merge conflicts: false(4)\n    lib/legacy.rs (deleted by them)\n"), "{}", body);
+    assert!(body.contains("    CHANGELOG.md (both added)\n"), "{}", body);
+  }
+
+  #[test]
+  fn verbose_sources_column() {
+    let mut summary = Summary::new(vec![], Default::default(), vec![], Check::tagged_checks(vec!["push_tag"]));
+    summary.collected_from(datasource::STATUS | datasource::REMOTE);
+    summary.without_remote();
+    let mut context = context(&summary);
+    assert!(!TMPL.render("summary", &context).unwrap().contains('['));
+
+    context.insert("verbose", &true);
+    let body = TMPL.render("summary", &context).unwrap();
+    assert!(body.contains("  [status, ls-remote (not collected)]\n"), "{}", body);
+
+    // Made up, rather than collected.
+    let example = example(&mut StdRng::seed_from_u64(394), Check::tagged_checks(vec!["push_tag"]));
+    let json = report_context(example.report()).into_json();
+    assert_eq!(json["items"][0]["sources"][0], serde_json::json!({"command": "status", "collected": false}));
+  }
+
+  #[test]
+  fn diff_stat_totals() {
+    let mut summary = Summary::new(vec![], Default::default(), vec![], Check::tagged_checks(vec!["stage"]));
+    assert!(!TMPL.render("summary", &context(&summary)).unwrap().contains("uncommitted:"));
+    assert!(context(&summary).into_json().get("diff_stat").is_none());
+
+    let mut stat = Uncommitted::default().parse(include_str!("git/parse/testdata/diff-numstat")).unwrap();
+    stat.staged = git::parse::numstat("4\t0\tsrc/main.rs\0").unwrap();
+    summary.diff_stat = Some(stat);
+    let body = TMPL.render("summary", &context(&summary)).unwrap();
+    assert!(body.contains("uncommitted: +17 -8 in 4 files\n"), "{}", body);
+    assert_eq!(context(&summary).into_json()["diff_stat"]["unstaged"][1], serde_json::json!({"path": "jar.svg", "added": null, "deleted": null}));
+
+    summary.diff_stat = Some(Default::default());
+    assert!(!TMPL.render("summary", &context(&summary)).unwrap().contains("uncommitted:"));
+  }
+
+  #[test]
+  fn summary_and_json_at_once() {
+    let dir = template_dir("outputs", &[]);
+    let json_path = dir.join("confit.json");
+    let output = format!("json={}", json_path.display());
+    let status = git::parse::status(DIRTY).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::tagged_checks(vec!["track_files"]));
+
+    let opt = app().get_matches_from(vec!["confit", "--format", "summary,json", "--output", &output]);
+    let mut out = vec![];
+    write_report(&opt, &[], &summary, &mut out, false);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(": false"), "{}", out);
+    assert!(!out.contains('{'), "{}", out);
+    let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert!(json.as_object_mut().unwrap().remove("meta").is_some());
+    assert_eq!(json, context(&summary).into_json());
+
+    let both = app().get_matches_from(vec!["confit", "--format", "summary,json"]);
+    assert_eq!(format_targets(&both).unwrap_err(), "formats summary, json would all go to stdout; send all but one to files with --output FORMAT=PATH");
+    let stray = app().get_matches_from(vec!["confit", "--output", &output]);
+    assert!(format_targets(&stray).unwrap_err().contains("json isn't one of the formats"));
+    assert!(app().get_matches_from_safe(vec!["confit", "--output", "confit.json"]).is_err());
+  }
+
+  #[test]
+  fn shallow_clone_caveat() {
+    let shallow = |answer: &'static str, tags: Vec<&str>| {
+      let status = git::parse::status(DIRTY).unwrap();
+      let mut summary = Summary::new(vec![], status, vec![], Check::tagged_checks(tags));
+      check_history(&mut summary, &Answer(true, answer));
+      summary
+    };
+
+    let complete = shallow("false\n", vec!["push", "stage"]);
+    assert_eq!(complete.totals().warned, 0);
+    assert!(!context(&complete).into_json().to_string().contains("caveat"));
+
+    let cut = shallow("true\n", vec!["push", "stage"]);
+    let json = context(&cut).into_json();
+    assert_eq!(json["items"][0]["caveat"], "shallow clone: history is incomplete");
+    assert_eq!(json["items"][0]["passed"], true);
+    assert_eq!(json["items"][0]["warned"], false);
+    assert!(json["items"][1].get("caveat").is_none());
+    assert!(TMPL.render("summary", &context(&cut)).unwrap().contains("(shallow clone: history is incomplete)"));
+
+    // a clean shallow checkout, as CI makes, still passes
+    let clean = shallow("true\n", vec!["push", "merge", "stale_local", "since_tag"]);
+    assert_eq!(clean.totals().failed, 0);
+    assert_eq!(clean.totals().warned, 0);
+    assert!(clean.passed());
+    assert_eq!(clean.porcelain().lines().filter(|line| !line.starts_with("pass")).count(), 0);
+
+    let unaffected = shallow("true\n", vec!["stage"]);
+    assert!(!context(&unaffected).into_json().to_string().contains("caveat"));
+
+    // unpushed work still fails, caveat and all
+    let mut ahead = Summary::new(vec![], git::parse::status(&DIRTY.replace("+0 -0", "+2 -0")).unwrap(), vec![], Check::tagged_checks(vec!["push"]));
+    check_history(&mut ahead, &Answer(true, "true\n"));
+    let json = context(&ahead).into_json();
+    assert_eq!(json["items"][0]["passed"], false);
+    assert_eq!(json["items"][0]["warned"], false);
+    assert_eq!(json["items"][0]["caveat"], "shallow clone: history is incomplete");
+    assert_ne!(ahead.exit_status(), 0);
+  }
+
+  #[test]
+  fn git_error_message() {
+    let e = git::Error::LsRemote("fatal: could not read Username for 'https://github.com': terminal prompts disabled\n".into());
+    assert_eq!(git_error(&e), "error: git ls-remote failed: fatal: could not read Username for 'https://github.com': terminal prompts disabled\n");
+    let e = Captured(GetStatus::default(), "not porcelain\n").collect(datasource::STATUS).unwrap_err();
+    assert!(git_error(&e).starts_with("error: parse error: "), "{}", git_error(&e));
+  }
+
+  #[test]
+  fn broken_template_message() {
+    let mut tera = Tera::default();
+    let e = tera.add_raw_template("mine", "{{ item.check.label }\n").unwrap_err();
+    let message = template_error("templates/mine.txt", &e);
+    assert!(message.starts_with("error in template 'templates/mine.txt':\n"), "{}", message);
+    assert!(message.contains("'mine'"), "{}", message);
+    assert!(message.contains("1:"), "{}", message);
+
+    tera.add_raw_template("mine", "{{ nothing.here }}").unwrap();
+    let e = tera.render("mine", &Context::default()).unwrap_err();
+    let message = template_error("mine", &e);
+    assert!(message.contains("nothing.here"), "{}", message);
+  }
+
+  fn template_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("confit-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, body) in files {
+      std::fs::write(dir.join(file), body).unwrap();
+    }
+    dir
+  }
+
+  #[test]
+  fn external_templates_named_like_builtins() {
+    let dir = template_dir("templates", &[
+      ("macros.txt", "{% macro glyph(item) %}{{ item.glyph }}{% endmacro %}"),
+      ("mine.txt", "{% import \"macros\" as m %}{% for item in items %}{{ m::glyph(item=item) }}{% endfor %}"),
+      ("layout.txt", "[{% block body %}{% endblock body %}]"),
+      ("framed.txt", "{% extends \"layout\" %}{% block body %}{{ items | length }}{% endblock body %}"),
+      ("README", "{{ not a template"),
+    ]);
+    let loaded = load_templates(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let tera = loaded.unwrap();
+    let summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files"]));
+    assert_eq!(tera.render("mine", &context(&summary)).unwrap(), "?");
+    assert_eq!(tera.render("framed", &context(&summary)).unwrap(), "[1]");
+    assert_eq!(missing_format("dir", "summary", &tera), "no template for format 'summary' in dir (found: framed, mine)\n");
+  }
+
+  #[test]
+  fn checks_from_file() {
+    let dir = template_dir("checks-file", &[
+      ("release", "# before tagging a release\nstage, track_files\n\n  push   # and pushing\n"),
+      ("typo", "stage\npsuh\n"),
+      ("blank", "# nothing yet\n\n"),
+    ]);
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let release = checks_file(&path("release"));
+    let typo = checks_file(&path("typo"));
+    let blank = checks_file(&path("blank"));
+    let opt = app().get_matches_from(vec!["confit", "--checks-file", &path("release"), "-c", "tag", "--skip", "stage"]);
+    let checks = selected_checks(&opt, &[]);
+    let bad_opt = app().get_matches_from_safe(vec!["confit", "--checks-file", &path("typo")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(release.unwrap(), vec!["stage", "track_files", "push"]);
+    assert_eq!(typo.unwrap_err(), format!("{}: unknown check 'psuh': did you mean 'push'?", path("typo")));
+    assert_eq!(blank.unwrap_err(), format!("no checks listed in {}", path("blank")));
+    assert!(bad_opt.unwrap_err().message.contains("did you mean 'push'?"));
+    let expected = Check::tagged_checks(vec!["track_files", "push", "tag"]);
+    assert_eq!(checks.len(), expected.len());
+    assert!(checks.iter().zip(expected).all(|(&a, b)| std::ptr::eq(a, b)));
+  }
+
+  #[test]
+  fn naming_the_ignored_check_lists_ignored_files() {
+    let ignored = Check::by_tag("ignored").unwrap();
+    let selecting = |args: Vec<&str>| {
+      let opt = app().get_matches_from(args);
+      let checks = selected_checks(&opt, &[]);
+      let (status, ..) = providers(&opt, &checks);
+      let listed = status.command_line(datasource::STATUS).unwrap().contains("--ignored");
+      (checks.iter().any(|&check| std::ptr::eq(check, ignored)), checks.len(), listed)
+    };
+    assert_eq!(selecting(vec!["confit", "-c", "ignored"]), (true, 1, true));
+    assert_eq!(selecting(vec!["confit", "-c", "stage,ignore*"]), (true, 3, true));
+    assert_eq!(selecting(vec!["confit", "-c", "stage", "--ignored"]), (false, 1, true));
+    assert_eq!(selecting(vec!["confit", "-c", "stage"]), (false, 1, false));
+    assert!(!selecting(vec!["confit", "-c", "all"]).0);
+    assert!(selecting(vec!["confit", "-c", "all", "--ignored"]).0);
+  }
+
+  #[test]
+  fn user_facing_lists() {
+    assert_eq!(format_names(), vec!["compact", "debug", "markdown", "prometheus", "statusline", "summary", "json", "none", "null"]);
+    assert!(FORMAT_HELP.contains("[included: compact, debug, markdown, prometheus, statusline, summary, json, or none"));
+
+    let mut tera = TMPL.clone();
+    tera.add_raw_template("macros2", "{% macro dot() %}.{% endmacro %}").unwrap();
+    tera.add_raw_template("terse", "{% import \"macros2\" as m %}{{ m::dot() }}").unwrap();
+    assert_eq!(template_formats(&tera), vec!["compact", "debug", "markdown", "prometheus", "statusline", "summary", "terse"]);
+
+    assert_eq!(*TAGS, vec![
+      "all", "commit", "conflict", "detached", "git_prompt", "ignore_conflict", "ignored", "in_progress",
+      "local", "merge", "push", "push_branches", "push_strict", "push_tag", "since_tag", "stage",
+      "stale_local", "stale_tag", "tag", "track_files", "track_name", "track_remote", "unpushed_branch", "upstream_gone",
+    ]);
+    assert!(CHECKS_HELP.ends_with(&format!("[possible values: {}]", TAGS.join(", "))));
+  }
+
+  #[test]
+  fn single_template_file() {
+    let dir = template_dir("template-file", &[
+      ("one-off.txt", "{% import \"macros\" as macros %}{{ totals.checks }} checks, exit {{ exit_code }}"),
+      ("broken.txt", "{{ totals.checks }"),
+    ]);
+    let loaded = load_template_file(&dir.join("one-off.txt"));
+    let broken = load_template_file(&dir.join("broken.txt"));
+    let missing = load_template_file(&dir.join("missing.txt"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let summary = example(&mut StdRng::seed_from_u64(404), Check::all_checks());
+    let name = dir.join("one-off.txt").to_string_lossy().into_owned();
+    assert_eq!(
+      loaded.unwrap().render(&name, &context(&summary)).unwrap(),
+      format!("{} checks, exit {}", summary.totals().checks, summary.exit_status())
+    );
+    let broken_name = dir.join("broken.txt").to_string_lossy().into_owned();
+    assert!(broken.unwrap_err().starts_with(&format!("error in template '{}':\n", broken_name)));
+    assert!(missing.unwrap_err().contains("missing.txt"));
+  }
+
+  #[test]
+  fn empty_template_dir() {
+    let dir = template_dir("no-templates", &[("summary", "{{ items }}")]);
+    let loaded = load_templates(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded.unwrap_err(), format!("no templates (*.txt files) in {}\n", dir.display()));
+  }
+
+  #[test]
+  fn compact_format() {
+    let status = git::parse::status(
+      "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+       # branch.head feature\n\
+       # branch.upstream origin/feature\n\
+       # branch.ab +2 -0\n\
+       1 .M N... 100644 100644 100644 c68d13474cd3f99964c052e5acc771f4df1e668e c68d13474cd3f99964c052e5acc771f4df1e668e README.md\n\
+       ? a.txt\n\
+       ? b.txt\n\
+       ? c.txt\n"
+    ).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::tagged_checks(vec!["push", "stage", "track_files", "detached", "tag"]));
+    let json = context(&summary).into_json();
+    assert_eq!(json["items"][0]["check"]["glyph"], "↑");
+    assert_eq!(json["items"][0]["result"]["bad"], 2);
+    assert_eq!(TMPL.render("compact", &context(&summary)).unwrap(), "↑2 +1 ?3 🏷");
+
+    let clean = git::parse::status(
+      "# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n\
+       # branch.head feature\n\
+       # branch.upstream origin/feature\n\
+       # branch.ab +0 -0\n"
+    ).unwrap();
+    let summary = Summary::new(vec![], clean, vec![], Check::tagged_checks(vec!["push", "stage", "track_files", "detached"]));
+    assert_eq!(TMPL.render("compact", &context(&summary)).unwrap(), "");
+    assert_eq!(summary.exit_status(), 0);
+  }
+
+  #[test]
+  fn tag_patterns_must_match() {
+    assert!(valid_tag("track_*".into()).is_ok());
+    assert!(valid_tag("push".into()).is_ok());
+    assert!(valid_tag("nothing_*".into()).unwrap_err().starts_with("no check matches 'nothing_*'"));
+    assert!(valid_tag("psh".into()).unwrap_err().contains("did you mean 'push'?"));
+  }
+
+  #[test]
+  fn ascii_glyphs() {
+    let mut summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["push", "track_files", "detached", "tag"]));
+    summary.draw_with(Glyphs::new(Default::default(), true));
+    assert_eq!(TMPL.render("compact", &context(&summary)).unwrap(), "?1 T");
+    assert_eq!(TMPL.render("statusline", &context(&summary)).unwrap().trim(), "master|?1T");
+  }
+
+  #[test]
+  fn markdown_format() {
+    let summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files", "detached"]));
+    let table = TMPL.render("markdown", &context(&summary)).unwrap();
+    let mut lines = table.lines();
+    assert_eq!(lines.next(), Some("| Check | Status | Count |"));
+    assert_eq!(lines.next(), Some("| --- | :---: | ---: |"));
+    assert_eq!(lines.next(), Some("| all files tracked | ❌ | 1 |"));
+    assert_eq!(lines.next(), Some("| commit tracked by local ref | ✅ |  |"));
+    assert_eq!(lines.next(), None);
+  }
+
+  #[test]
+  fn markdown_escapes_pipes() {
+    let piped = Check::from_def(preserves::CheckDef {
+      label: "left | right".into(),
+      tags: vec!["piped".into()],
+      glyph: 'p',
+      status_group: 1,
+      required_data: vec!["status".into()],
+      fail_when: "untracked > 0".into(),
+      severity: None,
+      weight: None,
+    }).unwrap();
+    let summary = Summary::new(vec![], git::Status::default(), vec![], vec![&piped]);
+    let table = TMPL.render("markdown", &context(&summary)).unwrap();
+    assert_eq!(table.lines().nth(2), Some(r"| left \| right | ✅ |  |"));
+  }
+
+  #[test]
+  fn warnings_render_distinctly() {
+    let mut summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files", "detached"]));
+    summary.warn_for(Check::tagged_checks(vec!["track_files"]));
+    let body = TMPL.render("summary", &context(&summary)).unwrap();
+    assert!(body.contains("all files tracked: ⚠(1)\n"), "{}", body);
+
+    let mut colored = context(&summary);
+    colored.insert("color", &true);
+    let body = TMPL.render("summary", &colored).unwrap();
+    assert!(body.contains("all files tracked: \x1b[33m⚠\x1b[0m(1)\n"), "{}", body);
+
+    let table = TMPL.render("markdown", &context(&summary)).unwrap();
+    assert_eq!(table.lines().nth(2), Some("| all files tracked | ⚠️ | 1 |"));
+    let prom = TMPL.render("prometheus", &context(&summary)).unwrap();
+    assert!(prom.contains("confit_check{tag=\"track_files\",group=\"1\"} 0\n"), "{}", prom);
+    assert!(prom.ends_with("confit_repo_clean 1\n"), "{}", prom);
+    assert_eq!(TMPL.render("compact", &context(&summary)).unwrap(), "?1");
+  }
+
+  #[test]
+  fn prometheus_format() {
+    let summary = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["track_files", "detached"]));
+    assert_eq!(TMPL.render("prometheus", &context(&summary)).unwrap(), "\
+      # HELP confit_check Whether the check failed (1) or not (0); warnings are 0.\n\
+      # TYPE confit_check gauge\n\
+      confit_check{tag=\"track_files\",group=\"1\"} 1\n\
+      confit_check{tag=\"detached\",group=\"1\"} 0\n\
+      # HELP confit_check_count How many problems a counting check found.\n\
+      # TYPE confit_check_count gauge\n\
+      confit_check_count{tag=\"track_files\"} 1\n\
+      # HELP confit_repo_clean Whether no check failed (1) or not (0); warnings don't count.\n\
+      # TYPE confit_repo_clean gauge\n\
+      confit_repo_clean 0\n");
+  }
+
+  #[test]
+  fn quiet_tiers() {
+    let checks = || Check::tagged_checks(vec!["push", "stage", "track_files"]);
+    let mixed = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], checks());
+    let items = |report: Option<Report>| report.map(|r| report_context(r).into_json()["items"].as_array().unwrap().len());
+
+    assert_eq!(Verbosity::from_quiet(0), Verbosity::Full);
+    assert_eq!(Verbosity::from_quiet(1), Verbosity::Failures);
+    assert_eq!(Verbosity::from_quiet(2), Verbosity::Silent);
+    assert_eq!(Verbosity::from_quiet(3), Verbosity::Silent);
+
+    assert_eq!(items(report_at(&mixed, Verbosity::Full, false)), Some(3));
+    assert_eq!(items(report_at(&mixed, Verbosity::Failures, false)), Some(1));
+    assert_eq!(items(report_at(&mixed, Verbosity::Silent, false)), None);
+
+    // failures only, and there are none: -q prints nothing, --only-failing says so
+    let clean = Summary::new(vec![], git::parse::status(CLEAN).unwrap(), vec![], checks());
+    assert_eq!(items(report_at(&clean, Verbosity::Failures, false)), None);
+    assert_eq!(items(report_at(&clean, Verbosity::Full, true)), Some(0));
+  }
+
+  #[test]
+  fn format_none_prints_nothing() {
+    let dirty = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], Check::tagged_checks(vec!["stage", "track_files"]));
+    for format in &["none", "null"] {
+      let opt = app().get_matches_from(vec!["confit", "--format", format, "--template", "templates/"]);
+      assert_eq!(Verbosity::from_opts(&opt), Verbosity::Silent);
+      assert!(report_at(&dirty, Verbosity::from_opts(&opt), false).is_none());
+    }
+    assert_eq!(dirty.exit_status(), 1 << 1);
+
+    let opt = app().get_matches_from(vec!["confit", "--format", "compact"]);
+    assert_eq!(Verbosity::from_opts(&opt), Verbosity::Full);
+  }
+
+  #[test]
+  fn only_failing_excludes_passing() {
+    let checks = || Check::tagged_checks(vec!["push", "stage", "track_files"]);
+    let dirty = Summary::new(vec![], git::parse::status(DIRTY).unwrap(), vec![], checks());
+    let json = report_context(dirty.report().only_failing()).into_json();
+    assert_eq!(json["items"].as_array().unwrap().len(), 1);
+    assert_eq!(json["items"][0]["passed"], false);
+    assert_eq!(json["totals"]["checks"], 3);
+
+    let clean = Summary::new(vec![], git::parse::status(CLEAN).unwrap(), vec![], checks());
+    let report = clean.report().only_failing();
+    assert!(report.is_empty());
+    assert_eq!(report_context(report).into_json()["items"], serde_json::json!([]));
+  }
+
+  #[test]
+  fn summary_accessors() {
+    let summary = example(&mut StdRng::seed_from_u64(357), Check::all_checks());
+    assert_eq!(summary.checks().len(), summary.items().len());
+    let failed = summary.failed_items();
+    assert!(failed.iter().all(|i| !i.passed() && i.failed()));
+    assert_eq!(failed.len(), summary.totals().failed);
+    let push = summary.result_for("push").expect("push is among all checks");
+    assert!(std::ptr::eq(push.check(), Check::tagged_checks(vec!["push"])[0]));
+    assert!(summary.result_for("no_such_tag").is_none());
+
+    let clean = Summary::new(vec![], git::parse::status(CLEAN).unwrap(), vec![], Check::tagged_checks(vec!["stage", "track_files"]));
+    assert!(clean.passed());
+    assert!(clean.failed_items().is_empty());
+    assert!(clean.result_for("track_files").unwrap().passed());
+  }
+
+  #[test]
+  fn example_limited_to_checks() {
+    let local = || {
+      let mut checks = Check::tagged_checks(vec!["local"]);
+      checks.skip(vec!["tag"]);
+      checks
+    };
+    let summary = example(&mut StdRng::seed_from_u64(362), local());
+    assert!(summary.checks().iter().zip(local()).all(|(&a, b)| std::ptr::eq(a, b)));
+    assert!(!summary.checks().to_vec().required_sources().includes(datasource::REMOTE));
+    assert_eq!(summary.items().len(), local().len());
+  }
+
+  #[test]
+  fn default_opts_from_env() {
+    let opts = Some("--format markdown --checks 'push' stage");
+    let args = |cli: &[&str]| with_default_opts(cli.iter().map(OsString::from).collect(), opts).unwrap();
+
+    let opt = app().get_matches_from(args(&["confit"]));
+    assert_eq!(opt.value_of("format"), Some("markdown"));
+    assert_eq!(opt.values_of("checks").unwrap().collect::<Vec<_>>(), vec!["push", "stage"]);
+
+    let opt = app().get_matches_from(args(&["confit", "--format", "compact"]));
+    assert_eq!(opt.value_of("format"), Some("compact"));
+
+    // CONFIT_OPTS's --checks doesn't run on into the paths.
+    let opt = app().get_matches_from(args(&["confit", "src/", "docs/"]));
+    assert_eq!(opt.values_of("checks").unwrap().collect::<Vec<_>>(), vec!["push", "stage"]);
+    assert_eq!(opt.values_of("paths").unwrap().collect::<Vec<_>>(), vec!["src/", "docs/"]);
+
+    assert_eq!(bounded(vec!["-c".into(), "push".into(), "-q".into(), "--warn".into()]), vec!["--checks=push", "-q", "--warn"]);
+    assert_eq!(bounded(vec!["--checks".into(), "push,stage".into()]), vec!["--checks=push,stage"]);
+    assert_eq!(args(&["confit", "doctor"]), vec![OsString::from("confit"), "doctor".into()]);
+    assert!(with_default_opts(vec!["confit".into()], Some("--format 'markdown")).is_err());
+  }
+
+  #[test]
+  fn paths_after_list_options() {
+    let opt = app().get_matches_from(vec!["confit", "-c", "push,stage", "--skip", "merge", "-w", "tag", "../elsewhere", "src/"]);
+    assert_eq!(opt.values_of("checks").unwrap().collect::<Vec<_>>(), vec!["push", "stage"]);
+    assert_eq!(opt.values_of("skip").unwrap().collect::<Vec<_>>(), vec!["merge"]);
+    assert_eq!(opt.values_of("warn").unwrap().collect::<Vec<_>>(), vec!["tag"]);
+    // Outside the work tree or not, they're git's to judge as pathspecs.
+    assert_eq!(opt.values_of("paths").unwrap().collect::<Vec<_>>(), vec!["../elsewhere", "src/"]);
+
+    let opt = app().get_matches_from(vec!["confit", "-c", "push", "-c", "stage", "/tmp"]);
+    assert_eq!(opt.values_of("checks").unwrap().collect::<Vec<_>>(), vec!["push", "stage"]);
+    assert_eq!(opt.values_of("paths").unwrap().collect::<Vec<_>>(), vec!["/tmp"]);
+  }
+
+  #[test]
+  fn git_config_beneath_options() {
+    let configured = vec!["push".to_string(), "stage".to_string()];
+    let same = |a: Vec<&Check>, b: Vec<&Check>| a.len() == b.len() && a.iter().zip(&b).all(|(&x, &y)| std::ptr::eq(x, y));
+
+    let opt = app_formatting("compact").get_matches_from(vec!["confit"]);
+    assert_eq!(opt.value_of("format"), Some("compact"));
+    assert!(same(selected_checks(&opt, &configured), Check::tagged_checks(vec!["push", "stage"])));
+
+    let opt = app_formatting("compact").get_matches_from(vec!["confit", "--format", "markdown", "-c", "tag"]);
+    assert_eq!(opt.value_of("format"), Some("markdown"));
+    assert!(same(selected_checks(&opt, &configured), Check::tagged_checks(vec!["tag"])));
+    // a configured format is only a default, so it doesn't conflict with --json
+    assert!(app_formatting("compact").get_matches_from_safe(vec!["confit", "--json"]).is_ok());
+
+    let mut git_config = config::GitConfig { checks: configured, ..Default::default() };
+    assert!(git_config_tags(&git_config).is_ok());
+    git_config.thresholds.insert("stale_tga".into(), 10);
+    assert_eq!(git_config_tags(&git_config).unwrap_err(), "git config confit.threshold.stale-tga: no check is tagged 'stale_tga'");
+    git_config.thresholds.clear();
+    git_config.thresholds.insert("stale_tag".into(), 60);
+    assert!(git_config_tags(&git_config).is_ok());
+    git_config.thresholds.insert("push".into(), 1);
+    assert_eq!(git_config_tags(&git_config).unwrap_err(), "git config confit.threshold.push: push has no threshold to set");
+    git_config.thresholds.remove("push");
+    git_config.checks.push("psh".into());
+    assert_eq!(git_config_tags(&git_config).unwrap_err(), "git config confit.checks: unknown check 'psh': did you mean 'push'?");
+  }
+
+  #[test]
+  fn example_totals_add_up() {
+    let summary = example(&mut StdRng::seed_from_u64(309), Check::all_checks());
+    let totals = summary.totals();
+    assert_eq!(totals.checks, summary.items().len());
+    assert_eq!(totals.checks, totals.passed + totals.failed + totals.warned);
+    assert_eq!(context(&summary).get("totals"), Some(&serde_json::to_value(&totals).unwrap()));
+  }
+}
+
 /* Stages of execution:
  * Choose Checks
  * Hassle Git for data
@@ -1,21 +1,66 @@
 use crate::git;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::ops::RangeInclusive;
 
-use git::parse::for_each_ref::ObjectType::*;
-use git::parse::status::{Head, LineStatus, Oid, StatusLine::*, StatusPair};
-use git::parse::{ObjectName, TrackingCounts};
-use serde::Serialize;
-use datasource::{STATUS, REFS, REMOTE, union};
+use git::parse::for_each_ref::{ObjectType::*, TrackSync};
+use git::parse::status::{ConflictKind, Head, Oid, StatusLine, StatusLine::*};
+use git::parse::{ObjectName, RefName, TrackingCounts, WorkPath};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+use datasource::{STATUS, REFS, REMOTE, LOG, DESCRIBE, LS_FILES, GIT_DIR, union};
+
+pub mod expr;
 
 pub mod datasource {
   use serde::Serialize;
+  use schemars::JsonSchema;
 
-  #[derive(Clone, Copy, Serialize)]
+  /// A set of data sources, one bit each.
+  #[derive(Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
   pub struct Group(u16);
 
   impl Group {
+    /// Whether the groups share any source. A provider's group is a single
+    /// source, so for `reqs.intersects(provider.provides())` this is the same
+    /// as asking whether the source is required.
+    pub fn intersects(self, other: Group) -> bool {
+      (self.0 & other.0) != 0
+    }
+
+    /// Whether every source in this group is also in the other.
+    pub fn subset_of(self, other: Group) -> bool {
+      (self.0 & other.0) == self.0
+    }
+
+    /// The same as `intersects`, despite the name: `STATUS | REFS` "includes"
+    /// `REFS | REMOTE`, since they share REFS. Use `subset_of` to ask whether
+    /// one group holds all of another.
     pub fn includes(self, item: Group) -> bool {
-      (self.0 & item.0) != 0
+      self.intersects(item)
+    }
+
+    /// The git command a single source is collected with, which names it.
+    pub fn command(self) -> &'static str {
+      match self {
+        STATUS => "status",
+        REFS => "for-each-ref",
+        REMOTE => "ls-remote",
+        LOG => "log",
+        DESCRIBE => "describe",
+        LS_FILES => "ls-files",
+        GIT_DIR => "rev-parse",
+        DIFF => "diff",
+        _ => "(several)",
+      }
+    }
+
+    /// Each single data source, in the order they're cheapest to collect.
+    pub fn all() -> impl Iterator<Item = Group> {
+      [STATUS, REFS, LOG, DESCRIBE, LS_FILES, GIT_DIR, DIFF, REMOTE].iter().copied()
     }
   }
 
@@ -39,22 +84,109 @@ pub mod datasource {
   pub const STATUS: Group = Group(1);
   pub const REFS: Group = Group(1 << 1);
   pub const REMOTE: Group = Group(1 << 2);
+  pub const LOG: Group = Group(1 << 3);
+  pub const DESCRIBE: Group = Group(1 << 4);
+  pub const LS_FILES: Group = Group(1 << 5);
+  pub const GIT_DIR: Group = Group(1 << 6);
+  pub const DIFF: Group = Group(1 << 7);
 
   pub const fn union(l: Group, r: Group) -> Group {
     Group(l.0 | r.0)
   }
 
+  pub fn by_name(name: &str) -> Option<Group> {
+    match name {
+      "status" => Some(STATUS),
+      "refs" => Some(REFS),
+      "remote" => Some(REMOTE),
+      "log" => Some(LOG),
+      _ => None,
+    }
+  }
+
 }
 
 pub struct Summary<'a> {
   pub status: git::Status,
-  pub(crate) ls_remote: Vec<git::RefPair>,
+  remote: RemoteIndex,
   pub(crate) for_each_ref: Vec<git::RefLine>,
+  pub(crate) newest_unpushed: Option<DateTime<Utc>>,
+  /// The latest tag reachable from HEAD, if there is one.
+  pub(crate) described: Option<git::Description>,
+  /// Tracked files that the ignore rules match.
+  pub(crate) tracked_ignored: Vec<WorkPath>,
+  /// Merges, rebases and the like that were started but not finished.
+  pub(crate) in_progress: Vec<git::Operation>,
+  /// Lines added and deleted in uncommitted files, if they were counted.
+  pub(crate) diff_stat: Option<git::DiffStat>,
+  // Whether the repository is a shallow clone, so its history is cut short.
+  shallow: bool,
+  // The data that checks can be judged with: everything, unless only some
+  // of it was collected or supplied with --from-*.
+  available: datasource::Group,
+  // The data that git was actually run for, or that was read from files.
+  collected: datasource::Group,
+  max_unpushed_age: Option<Duration>,
+  // Thresholds set in git config, by tag, in place of the checks' own.
+  thresholds: HashMap<String, u16>,
   checks: Vec<&'a Check>,
+  warned: Vec<&'a Check>,
+  path_order: PathOrder,
+  exit_mode: ExitMode,
+  gate: Gate,
+  glyphs: Glyphs,
+}
+
+
+/// The remote's refs, indexed once so that tag checks don't scan every ref
+/// the remote reports.
+#[derive(Debug, Default)]
+pub struct RemoteIndex {
+  by_ref: HashMap<RefName, ObjectName>,
+  // Objects that some branch or tag on the remote points at.
+  published: HashSet<ObjectName>,
+  // There's no remote configured to list refs from.
+  missing: bool,
+}
+
+impl RemoteIndex {
+  fn has_ref(&self, refname: &str) -> bool {
+    self.by_ref.contains_key(&RefName::from(refname))
+  }
+
+  fn object_of(&self, refname: &str) -> Option<&ObjectName> {
+    self.by_ref.get(&RefName::from(refname))
+  }
+
+  // Whether some ref on the remote points at the object. Only an
+  // abbreviated name needs the scan.
+  fn publishes(&self, object: &ObjectName) -> bool {
+    self.published.contains(object)
+      || (object.is_abbreviated() && self.published.iter().any(|p| p.matches(object)))
+  }
 }
 
+impl From<Vec<git::RefPair>> for RemoteIndex {
+  fn from(ls_remote: Vec<git::RefPair>) -> Self {
+    let mut index = RemoteIndex {
+      by_ref: HashMap::with_capacity(ls_remote.len()),
+      published: HashSet::new(),
+      missing: false,
+    };
+    for rp in ls_remote {
+      let name = rp.refname.as_ref();
+      if name.starts_with("refs/heads/") || name.starts_with("refs/tags/") {
+        index.published.insert(rp.object_name.clone());
+        // an annotated tag publishes the commit it refers to, too
+        index.published.extend(rp.peeled);
+      }
+      index.by_ref.insert(rp.refname, rp.object_name);
+    }
+    index
+  }
+}
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct Check {
   label: &'static str,
   tags: &'static [&'static str],
@@ -62,11 +194,140 @@ pub struct Check {
   status_group: u8,
   required_data: datasource::Group,
   threshold: u16,
+  weight: u8,
+  /// Whether the check counts the problems it finds, so that passing means
+  /// a count of zero rather than just a yes.
+  counted: bool,
+  severity: Severity,
+  #[serde(skip)]
+  eval: &'static (dyn Fn(&Summary) -> CheckResult + Sync),
   #[serde(skip)]
-  eval: fn(&Summary) -> CheckResult,
+  detail: fn(&Summary) -> Option<Detail>,
+}
+
+/// Further information about a check's result, for reports to elaborate on.
+#[derive(Clone,Debug,PartialEq,Serialize,JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Detail {
+  /// How far the branch and its upstream have diverged, and the commits at
+  /// either end when they're known: the remote's from ls-remote.
+  Tracking {
+    ahead: u64,
+    behind: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local: Option<ObjectName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote: Option<ObjectName>,
+  },
+  /// The branch has an upstream, but git can't compare with it (yet): it
+  /// hasn't been fetched, or is gone from the remote.
+  Unfetched { upstream: String },
+  Paths(Vec<WorkPath>),
+  Conflicts(Vec<Conflict>),
+  /// The tag on the current commit, by its short name.
+  Tag { name: String, kind: TagKind },
+  Branches(Vec<String>),
+  /// Ignored paths, both as git listed them and gathered by top-level
+  /// directory, since a tree like node_modules/ can hold thousands.
+  Ignored { groups: Vec<PathGroup>, paths: Vec<WorkPath> },
+  /// Why the check couldn't be judged, so fails.
+  Unavailable(String),
+  /// What exactly is wrong, when the check alone doesn't say.
+  Note(String),
+}
+
+/// Annotated tags are objects of their own, with a tagger and a message;
+/// lightweight tags are bare refs to a commit.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagKind {
+  Annotated,
+  Lightweight,
+}
+
+/// A path, or a top-level directory standing for the several paths in it.
+#[derive(Clone,Debug,PartialEq,Serialize,JsonSchema)]
+pub struct PathGroup {
+  path: WorkPath,
+  files: usize,
+}
+
+impl PathGroup {
+  /// Gathers paths under their top-level directory, in the order first
+  /// seen. A directory with only one path in it is left as that path.
+  fn by_top_dir(paths: &[WorkPath]) -> Vec<PathGroup> {
+    let mut dirs: Vec<(WorkPath, Vec<&WorkPath>)> = vec![];
+    for path in paths {
+      let dir = path.top_dir().unwrap_or_else(|| path.clone());
+      match dirs.iter_mut().find(|(d, _)| *d == dir) {
+        Some((_, members)) => members.push(path),
+        None => dirs.push((dir, vec![path])),
+      }
+    }
+    dirs.into_iter().map(|(dir, members)| match members.as_slice() {
+      [only] => PathGroup { path: (*only).clone(), files: 1 },
+      _ => PathGroup { path: dir, files: members.len() },
+    }).collect()
+  }
+}
+
+#[derive(Clone,Debug,PartialEq,Serialize,JsonSchema)]
+pub struct Conflict {
+  path: WorkPath,
+  kind: ConflictKind,
+}
+
+/// How failing checks add up to the exit status.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ExitMode {
+  /// The bitwise OR of each failing check's status group bit.
+  Bits,
+  /// The sum of the failing checks' weights, capped at 255.
+  Weight,
+}
+
+/// Which checks' failures count toward the exit status, by status group, so
+/// that saving work and releasing it can be gated separately.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Gate {
+  /// Status groups 1 to 3: the work isn't committed, pushed or otherwise saved.
+  Preserve,
+  /// Status group 4: the work isn't tagged, or the tag isn't pushed.
+  Release,
+  /// Every status group.
+  All,
+}
+
+impl Gate {
+  /// The status groups the gate admits.
+  pub fn groups(self) -> RangeInclusive<u8> {
+    match self {
+      Gate::Preserve => 1..=3,
+      Gate::Release => 4..=4,
+      Gate::All => 0..=u8::MAX,
+    }
+  }
+}
+
+/// How the paths listed in a check's details are ordered.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum PathOrder {
+  /// As git reported them.
+  Git,
+  /// Sorted by path.
+  Path,
+}
+
+/// How a failing check is treated: errors contribute to the exit status,
+/// warnings are reported but leave it alone.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize,JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  Error,
+  Warn,
 }
 
-#[derive(Clone,Copy,Serialize)]
+#[derive(Clone,Copy,Serialize,JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum CheckResult {
   // check passed
@@ -74,7 +335,36 @@ pub enum CheckResult {
   // some checks simply fail
   Failed,
   // some checks have a count of failures
-  Bad(usize)
+  Bad(usize),
+  // advisory: reported, but neither passed nor failed, and no exit bit
+  Warn(Option<usize>),
+}
+
+impl CheckResult {
+  /// The number of problems found, for checks that count them.
+  pub fn count(&self) -> Option<usize> {
+    match self {
+      CheckResult::Bad(n) | CheckResult::Warn(Some(n)) => Some(*n),
+      _ => None,
+    }
+  }
+
+  pub fn is_failure(&self) -> bool {
+    matches!(self, CheckResult::Failed | CheckResult::Bad(_))
+  }
+
+  pub fn is_warning(&self) -> bool {
+    matches!(self, CheckResult::Warn(_))
+  }
+
+  /// The same result as a warning, if it was a failure.
+  fn as_warning(self) -> Self {
+    match self {
+      CheckResult::Failed => CheckResult::Warn(None),
+      CheckResult::Bad(n) => CheckResult::Warn(Some(n)),
+      other => other,
+    }
+  }
 }
 
 impl From<usize> for CheckResult {
@@ -103,32 +393,341 @@ impl From<bool> for CheckResult {
   }
 }
 
+/// A check defined at runtime rather than compiled in, e.g. in .confit.toml:
+///
+///     [[check]]
+///     label = "no more than five untracked files"
+///     tags = ["few_untracked"]
+///     glyph = "?"
+///     status_group = 1
+///     required_data = ["status"]
+///     fail_when = "untracked > 5"
+///
+/// `fail_when` is an expression over the counts listed in `expr::VARIABLES`.
+#[derive(Debug, Deserialize)]
+pub struct CheckDef {
+  pub label: String,
+  pub tags: Vec<String>,
+  pub glyph: char,
+  pub status_group: u8,
+  #[serde(default)]
+  pub required_data: Vec<String>,
+  pub fail_when: String,
+  #[serde(default)]
+  pub severity: Option<Severity>,
+  /// What failing contributes to the exit status under ExitMode::Weight; 1 if unset.
+  #[serde(default)]
+  pub weight: Option<u8>,
+}
+
+/// Selects every check, wherever tags are accepted.
+pub const ALL_TAG: &str = "all";
+
+/// The highest status group a check can belong to. Each group sets bit
+/// `1 << status_group` of the exit status, and statuses of 128 and above are
+/// reserved: for confit's own errors, and by shells for processes killed by signals.
+pub const MAX_STATUS_GROUP: u8 = 6;
+
+/// Commands that fix failing checks, by tag, most pressing first: changes
+/// need staging before they can be committed, and committing before pushing.
+const REMEDIES: &[(&str, &str)] = &[
+  ("stage", "git add -A"),
+  ("commit", "git commit"),
+  ("push", "git push"),
+  ("push_strict", "git push"),
+  ("tag", "git tag"),
+];
+
+/// Checks that only look at tags among the local refs, so that when they're
+/// the only ones needing refs, the listing can be limited to refs/tags/.
+const TAG_REF_CHECKS: &[&str] = &["tag"];
+
+/// Checks that count or date commits by walking history, which a shallow
+/// clone cuts short, so that their results can't be trusted there.
+const ANCESTRY_CHECKS: &[&str] = &["push", "merge", "stale_tag", "stale_local", "since_tag", "push_branches"];
+
+const SHALLOW_CAVEAT: &str = "shallow clone: history is incomplete";
+
+/// Stand-ins, by tag, for the glyphs that need more than ASCII to draw.
+const ASCII_GLYPHS: &[(&str, &str)] = &[
+  ("push", "^"),
+  ("merge", "v"),
+  ("conflict", "X"),
+  ("detached", "@"),
+  ("track_remote", "R"),
+  ("track_name", "~"),
+  ("tag", "T"),
+  ("push_tag", "P"),
+  ("stale_tag", "Z"),
+  ("stale_local", "z"),
+  ("push_strict", "!"),
+  ("upstream_gone", "0"),
+  ("unpushed_branch", "Y"),
+  ("since_tag", "S"),
+  ("ignore_conflict", "I"),
+  ("push_branches", "B"),
+  ("ignored", "i"),
+  ("in_progress", "%"),
+];
+
+/// How checks are drawn in the compact formats: by the glyph configured for
+/// any of their tags, else their own glyph, or its ASCII stand-in.
+#[derive(Debug, Default)]
+pub struct Glyphs {
+  overrides: HashMap<String, String>,
+  ascii: bool,
+}
+
+impl Glyphs {
+  pub fn new(overrides: HashMap<String, String>, ascii: bool) -> Self {
+    Glyphs { overrides, ascii }
+  }
+
+  pub fn for_check(&self, check: &Check) -> String {
+    if let Some(glyph) = check.tags.iter().find_map(|t| self.overrides.get(*t)) {
+      return glyph.clone()
+    }
+    if self.ascii && !check.glyph.is_ascii() {
+      return check.tags.iter()
+        .find_map(|t| ASCII_GLYPHS.iter().find(|(tag, _)| tag == t))
+        .map_or("*", |(_, glyph)| glyph)
+        .to_string()
+    }
+    check.glyph.to_string()
+  }
+}
+
+lazy_static! {
+  static ref CUSTOM_CHECKS: RwLock<&'static [&'static Check]> = RwLock::new(&[]);
+}
+
+// Custom checks live as long as the process does, like the built in ones.
+fn leak(s: String) -> &'static str {
+  Box::leak(s.into_boxed_str())
+}
+
 impl Check {
+  /// Every check, built in then custom, to select from with the filters in
+  /// `select`. The lock is only held long enough to take the custom checks'
+  /// slice, which lives as long as the process does.
+  pub fn iter() -> impl Iterator<Item = &'static Check> {
+    let custom: &'static [&'static Check] = *CUSTOM_CHECKS.read().expect("custom checks lock");
+    ALL_CHECKS.iter().chain(custom.iter().copied())
+  }
+
   pub fn all_checks<'a>() -> Vec<&'a Check> {
-    ALL_CHECKS.iter().collect()
+    Check::iter().collect()
   }
 
+  /// The checks carrying any of the given tags, which may be glob patterns
+  /// (see `tag_matches`); the tag "all" selects every check.
   pub fn tagged_checks<'a, 'b>(tags: impl Clone + IntoIterator<Item=&'b str>) -> Vec<&'a Check> {
-    ALL_CHECKS.iter().filter(move |ch| tags.clone().into_iter()
-        .any(|t|  ch.tags.iter().any(|&c| (t == c) ))).collect()
+    if tags.clone().into_iter().any(|t| t == ALL_TAG) {
+      return Check::all_checks()
+    }
+    Check::iter().filter(select::by_tag(tags)).collect()
+  }
+
+  fn tagged<'b>(&self, patterns: impl IntoIterator<Item=&'b str>) -> bool {
+    patterns.into_iter().any(|p| self.tags.iter().any(|t| tag_matches(p, t)))
+  }
+
+  /// Whether the check judges by a threshold, which git config can override.
+  pub fn has_threshold(&self) -> bool {
+    self.threshold > 0
+  }
+
+  /// Finds the first check carrying the given tag.
+  pub fn by_tag(tag: &str) -> Option<&'static Check> {
+    Check::iter().find(|ch| ch.tags.contains(&tag))
+  }
+
+  /// Builds a check from its definition, failing if the definition is malformed.
+  pub fn from_def(def: CheckDef) -> Result<Check, String> {
+    Check::from_def_among(def, &Check::all_checks())
+  }
+
+  /// Builds a check from its definition alongside the checks already
+  /// registered. Its first tag names it, so that can't be any tag those
+  /// checks have; its others can join their groups, but not name one of them.
+  fn from_def_among(def: CheckDef, registered: &[&Check]) -> Result<Check, String> {
+    let predicate = expr::parse(&def.fail_when)
+      .map_err(|e| format!("check '{}': {}", def.label, e))?;
+    // Every variable fail_when can use is counted from git status, so that's
+    // always needed, whatever else is listed.
+    let required_data = def.required_data.iter().try_fold(STATUS, |acc, name| {
+      datasource::by_name(name)
+        .map(|group| acc | group)
+        .ok_or_else(|| format!("check '{}': unknown data source '{}' (known: status, refs, remote, log)", def.label, name))
+    })?;
+    if def.tags.is_empty() {
+      return Err(format!("check '{}' needs at least one tag", def.label))
+    }
+    if def.tags.iter().any(|tag| tag == ALL_TAG) {
+      return Err(format!("check '{}': tag '{}' is reserved", def.label, ALL_TAG))
+    }
+    let taken = def.tags.iter().enumerate().find_map(|(n, tag)| {
+      registered.iter()
+        .find(|ch| ch.tags[0] == tag || (n == 0 && ch.tags.contains(&tag.as_str())))
+        .map(|ch| (tag, ch.label))
+    });
+    if let Some((tag, label)) = taken {
+      return Err(format!("check '{}': tag '{}' is taken by check '{}'", def.label, tag, label))
+    }
+    if def.status_group > MAX_STATUS_GROUP {
+      return Err(format!("check '{}': status_group must be between 0 and {}", def.label, MAX_STATUS_GROUP))
+    }
+
+    let eval = move |s: &Summary| CheckResult::from(predicate.eval(&|var| s.count(var)) == 0);
+    Ok(Check {
+      label: leak(def.label),
+      tags: Box::leak(def.tags.into_iter().map(leak).collect::<Vec<_>>().into_boxed_slice()),
+      glyph: def.glyph,
+      status_group: def.status_group,
+      required_data,
+      threshold: 0,
+      weight: def.weight.unwrap_or(1),
+      counted: false,
+      severity: def.severity.unwrap_or(Severity::Error),
+      eval: Box::leak(Box::new(eval)),
+      detail: no_detail,
+    })
+  }
+
+  /// Makes a check available alongside the built in ones. Checks are only
+  /// registered at startup, so the slice is simply replaced by a longer one.
+  pub fn register(check: Check) {
+    let mut custom = CUSTOM_CHECKS.write().expect("custom checks lock");
+    let mut checks = custom.to_vec();
+    checks.push(Box::leak(Box::new(check)));
+    *custom = Box::leak(checks.into_boxed_slice())
   }
 
   pub fn all_tags() -> Vec<&'static str> {
-    let mut tags = ALL_CHECKS.iter().flat_map(|ch| ch.tags.iter().copied()).collect::<Vec<_>>();
+    let mut tags = Check::iter().flat_map(|ch| ch.tags.iter().copied()).collect::<Vec<_>>();
+    tags.push(ALL_TAG);
     tags.sort_unstable();
     tags.dedup();
     tags
   }
+
+  /// Suggests the known tag closest to a mistyped one, if any is close enough.
+  pub fn suggest_tag(typo: &str) -> Option<&'static str> {
+    Check::all_tags()
+      .into_iter()
+      .map(|tag| (edit_distance(typo, tag), tag))
+      .filter(|&(d, _)| d <= 2)
+      .min_by_key(|&(d, _)| d)
+      .map(|(_, tag)| tag)
+  }
+}
+
+/// Filters for `Check::iter()` and other runs of checks, to be combined:
+/// `Check::iter().filter(select::in_groups(2..=2)).filter(select::requiring(REMOTE))`.
+pub mod select {
+  use super::{datasource::Group, Check};
+  use std::ops::RangeInclusive;
+
+  /// Checks carrying a tag matching any of the patterns, as `tag_matches`
+  /// has it.
+  pub fn by_tag<'p, P>(patterns: P) -> impl Fn(&&Check) -> bool
+  where
+    P: Clone + IntoIterator<Item = &'p str>,
+  {
+    move |check| check.tagged(patterns.clone())
+  }
+
+  /// Checks in any of the status groups, such as a gate admits.
+  pub fn in_groups(status_groups: RangeInclusive<u8>) -> impl Fn(&&Check) -> bool {
+    move |check| status_groups.contains(&check.status_group)
+  }
+
+  /// Checks needing any of the data sources in the group.
+  pub fn requiring(sources: Group) -> impl Fn(&&Check) -> bool {
+    move |check| check.required_data.includes(sources)
+  }
+}
+
+// Levenshtein distance, by the usual single-row dynamic programming.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut row: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut diag = row[0];
+    row[0] = i + 1;
+    for (j, &cb) in b.iter().enumerate() {
+      let next = (row[j + 1] + 1)
+        .min(row[j] + 1)
+        .min(diag + if ca == cb { 0 } else { 1 });
+      diag = row[j + 1];
+      row[j + 1] = next;
+    }
+  }
+  row[b.len()]
+}
+
+/// Matches a tag against a pattern in which `*` stands for any run of
+/// characters and `?` for any one character. Without either, the pattern
+/// must equal the tag.
+pub fn tag_matches(pattern: &str, tag: &str) -> bool {
+  fn matches(p: &[char], t: &[char]) -> bool {
+    match p.split_first() {
+      None => t.is_empty(),
+      Some(('*', rest)) => (0..=t.len()).any(|i| matches(rest, &t[i..])),
+      Some(('?', rest)) => !t.is_empty() && matches(rest, &t[1..]),
+      Some((c, rest)) => t.first() == Some(c) && matches(rest, &t[1..]),
+    }
+  }
+  let p: Vec<char> = pattern.chars().collect();
+  let t: Vec<char> = tag.chars().collect();
+  matches(&p, &t)
 }
 
 pub trait CheckList {
   fn required_sources(&mut self) -> datasource::Group;
+
+  /// The pattern to limit for-each-ref to, if the checks needing refs can
+  /// make do with some of them.
+  fn ref_pattern(&self) -> Option<&'static str>;
+
+  /// Drops the checks that need data beyond what's available.
+  fn restrict_to(&mut self, available: datasource::Group);
+
+  /// Drops the checks carrying any of the given tags.
+  fn skip<'b>(&mut self, tags: impl Clone + IntoIterator<Item=&'b str>);
+
+  /// Orders the checks by status group, then label.
+  fn sort_by_group(&mut self);
 }
 
 impl CheckList for Vec<&Check> {
   fn required_sources(&mut self) -> datasource::Group {
     self.iter().fold(datasource::EMPTY, |acc, check| acc | check.required_data)
   }
+
+  fn ref_pattern(&self) -> Option<&'static str> {
+    let mut needing_refs = self.iter().copied().filter(select::requiring(datasource::REFS)).peekable();
+    needing_refs.peek()?;
+    let for_tags = select::by_tag(TAG_REF_CHECKS.iter().copied());
+    if needing_refs.all(|check| for_tags(&check)) {
+      Some("refs/tags/")
+    } else {
+      None
+    }
+  }
+
+  fn restrict_to(&mut self, available: datasource::Group) {
+    self.retain(|check| check.required_data.subset_of(available))
+  }
+
+  fn skip<'b>(&mut self, tags: impl Clone + IntoIterator<Item=&'b str>) {
+    let skipped = select::by_tag(tags);
+    self.retain(|check| !skipped(check))
+  }
+
+  fn sort_by_group(&mut self) {
+    self.sort_by_key(|check| (check.status_group, check.label))
+  }
 }
 
 /// Collects and reports reasons that your current workspace
@@ -142,9 +741,133 @@ impl<'a> Summary<'a> {
   ) -> Self {
     Summary {
       status,
-      ls_remote,
+      remote: ls_remote.into(),
       for_each_ref,
+      newest_unpushed: None,
+      described: None,
+      tracked_ignored: vec![],
+      in_progress: vec![],
+      diff_stat: None,
+      shallow: false,
+      available: datasource::Group::all().fold(datasource::EMPTY, |acc, g| acc | g),
+      collected: datasource::EMPTY,
+      max_unpushed_age: None,
+      thresholds: HashMap::new(),
       checks,
+      warned: vec![],
+      path_order: PathOrder::Git,
+      exit_mode: ExitMode::Bits,
+      gate: Gate::All,
+      glyphs: Glyphs::default(),
+    }
+  }
+
+  pub fn remote(&self) -> &RemoteIndex {
+    &self.remote
+  }
+
+  /// Replaces the remote's refs, reindexing them for the tag checks.
+  pub fn set_ls_remote(&mut self, ls_remote: Vec<git::RefPair>) {
+    self.remote = ls_remote.into()
+  }
+
+  /// Records that the workspace has no remote, so that the checks needing
+  /// one fail, saying why.
+  pub fn without_remote(&mut self) {
+    self.remote = RemoteIndex { missing: true, ..Default::default() }
+  }
+
+  /// Records that only some of the data was collected, so that checks
+  /// needing the rest fail as unevaluable, rather than pass on empty data.
+  pub fn available_only(&mut self, available: datasource::Group) {
+    self.available = available
+  }
+
+  /// Records that these sources were collected, or read from files, for
+  /// each check's sources to say so.
+  pub fn collected_from(&mut self, sources: datasource::Group) {
+    self.collected = self.collected | sources
+  }
+
+  /// Reports the checks by status group, then label, rather than in the
+  /// order they were selected.
+  pub fn sort_checks(&mut self) {
+    self.checks.sort_by_group()
+  }
+
+  pub fn sort_paths(&mut self, order: PathOrder) {
+    self.path_order = order
+  }
+
+  pub fn exit_by(&mut self, mode: ExitMode) {
+    self.exit_mode = mode
+  }
+
+  pub fn gate_on(&mut self, gate: Gate) {
+    self.gate = gate
+  }
+
+  pub fn draw_with(&mut self, glyphs: Glyphs) {
+    self.glyphs = glyphs
+  }
+
+  /// Whether any of the checks walks history, and so would be misled by a
+  /// shallow clone.
+  pub fn needs_history(&self) -> bool {
+    self.checks.iter().any(select::by_tag(ANCESTRY_CHECKS.iter().copied()))
+  }
+
+  /// Records that the repository is a shallow clone, so that checks walking
+  /// its history note that they judged only what history there is.
+  pub fn mark_shallow(&mut self) {
+    self.shallow = true
+  }
+
+  /// Overrides the thresholds of the checks with these tags.
+  pub fn set_thresholds(&mut self, thresholds: HashMap<String, u16>) {
+    self.thresholds = thresholds
+  }
+
+  /// The threshold of the check with this tag, as overridden if it is.
+  fn threshold(&self, tag: &str) -> u16 {
+    self.thresholds.get(tag).copied()
+      .or_else(|| Check::by_tag(tag).map(|ch| ch.threshold))
+      .unwrap_or(0)
+  }
+
+  /// Overrides how old the newest unpushed commit may be before stale_local fails.
+  pub fn limit_unpushed_age(&mut self, age: Duration) {
+    self.max_unpushed_age = Some(age)
+  }
+
+  /// How long ago the newest unpushed commit was made, if there is one.
+  pub fn unpushed_age(&self) -> Option<Duration> {
+    self.newest_unpushed.map(|t| Utc::now() - t)
+  }
+
+  /// Downgrades the given checks to warnings, regardless of their declared severity.
+  pub fn warn_for(&mut self, checks: Vec<&'a Check>) {
+    self.warned = checks
+  }
+
+  /// Drops the checks that need data beyond what's available.
+  pub fn restrict_to(&mut self, available: datasource::Group) {
+    self.checks.restrict_to(available)
+  }
+
+  /// Whether any check that can be judged with only the available data fails
+  /// in a way that would affect the exit status.
+  pub fn fails_within(&self, available: datasource::Group) -> bool {
+    self.items().iter().any(|item| {
+      item.check.required_data.subset_of(available) && item.failed()
+    })
+  }
+
+  fn severity_of(&self, check: &Check) -> Severity {
+    if self.warned.iter().any(|&w| std::ptr::eq(w, check)) {
+      Severity::Warn
+    } else {
+      check.severity
     }
   }
 
@@ -152,82 +875,435 @@ impl<'a> Summary<'a> {
     self.checks.iter().map(|ch| Item::build(ch, self)).collect()
   }
 
+  /// The checks this summary judges the workspace by.
+  pub fn checks(&self) -> &[&'a Check] {
+    &self.checks
+  }
+
+  /// The items for checks that failed, leaving out warnings.
+  pub fn failed_items(&self) -> Vec<Item<'_>> {
+    self.items().into_iter().filter(Item::failed).collect()
+  }
+
+  /// Whether every check passed. Warnings don't count as passing here,
+  /// though they leave the exit status alone.
+  pub fn passed(&self) -> bool {
+    self.items().iter().all(|i| i.passed)
+  }
+
+  /// The item for the first check carrying the tag, if any such check is
+  /// being run. Items are built on demand, so this is owned.
+  #[cfg(test)]
+  pub fn result_for(&self, tag: &str) -> Option<Item<'_>> {
+    self.checks.iter().find(|ch| ch.tags.contains(&tag)).map(|ch| Item::build(ch, self))
+  }
+
+  /// Renders the porcelain report: one line per check, formatted as
+  /// `STATUS\tGROUP\tTAG\tCOUNT`, where STATUS is `pass`, `fail` or `warn`
+  /// (for a failure that's only a warning), GROUP is the check's status
+  /// group, TAG is its first (primary) tag, and COUNT is the number of
+  /// problems found, or empty for checks that simply fail.
+  ///
+  /// This format is a stability contract for scripts: it does not follow
+  /// changes to the JSON output, and fields will only ever be appended.
+  pub fn porcelain(&self) -> String {
+    self.items().iter().map(|item| {
+      format!("{}\t{}\t{}\t{}\n",
+        if item.passed { "pass" } else if item.warned { "warn" } else { "fail" },
+        item.check.status_group,
+        item.check.tags[0],
+        item.result.count().map_or_else(String::new, |n| n.to_string()))
+    }).collect()
+  }
+
+  /// Counts the checks run, and those that passed, failed and warned:
+  /// every check is one of the three.
+  pub fn totals(&self) -> Totals {
+    let items = self.items();
+    let passed = items.iter().filter(|i| i.passed).count();
+    let warned = items.iter().filter(|i| i.warned).count();
+    Totals {
+      checks: items.len(),
+      passed,
+      failed: items.len() - passed - warned,
+      warned,
+    }
+  }
+
+  pub fn report(&self) -> Report<'_> {
+    Report {
+      items: self.items(),
+      status: &self.status,
+      totals: self.totals(),
+      suggestion: self.suggestion(),
+      unpushed_age: self.unpushed_age().map(|age| age.num_seconds()),
+      newest_unpushed: self.newest_unpushed,
+      head_date: self.head_date(),
+      latest_tag_date: self.latest_tag_date(),
+      branches: self.branch_tracking().into_iter().map(BranchTracking::from).collect(),
+      diff_stat: self.diff_stat.as_ref().map(DiffTotals::from),
+      exit_code: self.exit_status(),
+      all_passed: self.passed(),
+      meta: None,
+    }
+  }
+
+  /// A command to fix the most pressing failing check, if any has one.
+  pub fn suggestion(&self) -> Option<&'static str> {
+    let failing = self.failed_items();
+    REMEDIES.iter()
+      .find(|(tag, _)| failing.iter().any(|i| i.check.tags.contains(tag)))
+      .map(|&(_, command)| command)
+  }
+
+  /// By default, the bitwise OR of `1 << status_group` over the failing
+  /// checks. Groups beyond MAX_STATUS_GROUP saturate to it, so the result is
+  /// always under 128. Under ExitMode::Weight, the sum of the failing checks'
+  /// weights instead, capped at 255. Either way, only over the checks whose
+  /// status groups the gate admits.
   pub fn exit_status(&self) -> i32 {
-    self.items()
+    let admitted = select::in_groups(self.gate.groups());
+    let failing = self.items().into_iter().filter(Item::failed).filter(|item| admitted(&item.check));
+    match self.exit_mode {
+      ExitMode::Bits => failing.fold(0, |status, item| status | (1 << item.check.status_group.min(MAX_STATUS_GROUP))),
+      ExitMode::Weight => failing.map(|item| i32::from(item.check.weight)).sum::<i32>().min(255),
+    }
+  }
+
+  /// The named count (one of `expr::VARIABLES`) for custom check predicates.
+  pub fn count(&self, name: &str) -> i64 {
+    let lines = |pred: &dyn Fn(&git::parse::status::StatusLine) -> bool| {
+      self.status.lines.iter().filter(|l| pred(l)).count() as i64
+    };
+    let commits = self.status.branch.as_ref().and_then(|b| b.commits);
+    match name {
+      "untracked" => lines(&|l| matches!(l, Untracked { .. })),
+      "ignored" => lines(&|l| matches!(l, Ignored { .. })),
+      "staged" => lines(&|l| l.staged_status().map_or(false, |m| !m.is_clean())),
+      "unstaged" => lines(&|l| l.unstaged_status().map_or(false, |m| !m.is_clean())),
+      "conflicts" => lines(&|l| l.is_conflict()),
+      "ahead" => commits.map_or(0, |TrackingCounts(ahead, _)| ahead as i64),
+      "behind" => commits.map_or(0, |TrackingCounts(_, behind)| behind as i64),
+      _ => 0,
+    }
+  }
+
+  // git describe dates the commit itself; otherwise, a ref pointing at it has to.
+  fn head_date(&self) -> Option<DateTime<Utc>> {
+    self.described.as_ref().and_then(|d| d.committed).or_else(|| {
+      match self.status.branch.as_ref().map(|b| &b.oid) {
+        Some(Oid::Commit(c)) => self.commit_date(c),
+        _ => None,
+      }
+    })
+  }
+
+  fn commit_date(&self, c: &ObjectName) -> Option<DateTime<Utc>> {
+    self.for_each_ref
+      .iter()
+      .find(|rl| rl.object_type == Commit && rl.object_name.matches(c))
+      .map(|rl| rl.creation_date)
+  }
+
+  /// How far each local branch with an upstream is ahead of and behind it,
+  /// as for-each-ref reports. Branches without one, or whose upstream is
+  /// gone, are left out.
+  pub fn branch_tracking(&self) -> Vec<(RefName, TrackingCounts)> {
+    self.for_each_ref
       .iter()
-      .fold(0, |status, item| {
-        if item.passed {
-          status
-        } else {
-          status | (1 << item.check.status_group)
-        }
+      .filter(|rl| rl.local_ref.as_ref().starts_with("refs/heads/"))
+      .filter_map(|rl| match rl.upstream {
+        TrackSync::Track { counts, .. } => Some((rl.local_ref.clone(), counts)),
+        _ => None,
       })
+      .collect()
+  }
+
+  // The tag git describe found, rather than the newest anywhere, which might
+  // be on another branch. Annotated tags are dated by their tagger,
+  // lightweight ones by their commit.
+  fn latest_tag_date(&self) -> Option<DateTime<Utc>> {
+    let tag = format!("refs/tags/{}", self.described.as_ref()?.tag);
+    self.for_each_ref
+      .iter()
+      .find(|rl| rl.local_ref.as_ref() == tag)
+      .map(|rl| rl.tagger_date.unwrap_or(rl.creation_date))
   }
 
-  fn tag_on_commit(&self, c: ObjectName) -> Option<ObjectName> {
+  // Annotated tags refer to the commit; lightweight ones are refs under
+  // refs/tags/ that name it directly.
+  fn tag_on_commit(&self, c: ObjectName) -> Option<&git::RefLine> {
     self.for_each_ref
       .iter()
-      .find(|rl| {
-        rl.object_type == Tag
-          && rl
-          .referred_object
-          .clone()
-          .map(|ro| ro == c)
-          .unwrap_or(false)
+      .find(|rl| match rl.object_type {
+        Tag => rl.referred_object.as_ref().map_or(false, |r| r.matches(&c)),
+        _ => rl.local_ref.as_ref().starts_with("refs/tags/") && rl.object_name.matches(&c),
       })
-    .map(|rl| rl.object_name.clone())
   }
 }
 
-#[derive(Serialize)]
+#[derive(Debug,PartialEq,Serialize,JsonSchema)]
+pub struct Totals {
+  pub checks: usize,
+  pub passed: usize,
+  pub failed: usize,
+  pub warned: usize,
+}
+
+impl fmt::Display for Totals {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "checks={} passed={} failed={} warned={}", self.checks, self.passed, self.failed, self.warned)
+  }
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct Item<'a> {
   check: &'a Check,
+  /// The check's glyph, as configured; templates should prefer it to check.glyph.
+  glyph: String,
   result: CheckResult,
-  passed: bool
+  /// How many problems a counting check found, zero if it passed; null for
+  /// checks that only pass or fail.
+  count: Option<usize>,
+  passed: bool,
+  /// Whether the check warned: neither passed nor failed.
+  warned: bool,
+  /// Whether the data the check needs was there to judge it by; if not, it fails.
+  evaluable: bool,
+  /// The data the check needs, cheapest first.
+  sources: Vec<Source>,
+  severity: Severity,
+  detail: Option<Detail>,
+  /// Why the result can't be fully trusted, if it can't.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  caveat: Option<&'static str>,
 }
 
-impl<'a> Item<'a> {
-  fn build(check: &'a Check, summary: &Summary) -> Self {
-    let result = (check.eval)(summary);
-    Item{
-      check,
-      result,
-      passed: matches!(result, CheckResult::Passed)
-    }
-  }
+/// A source of data a check needs, named for the git command that collects
+/// it, and whether it was collected this time.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Source {
+  command: &'static str,
+  collected: bool,
 }
 
-impl fmt::Display for Summary<'_> {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let width = self.items().iter().map(|i| i.check.label.len()).max();
-    for i in self.items() {
-      writeln!(
-        f,
-        "  {:>width$}: {}",
-        i.check.label,
-        i.passed,
-        width = width.unwrap_or(0)
-      )?;
+/// Everything a report is rendered from: the context for templates, and
+/// the document --json emits.
+#[derive(Serialize, JsonSchema)]
+pub struct Report<'a> {
+  items: Vec<Item<'a>>,
+  status: &'a git::Status,
+  totals: Totals,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  suggestion: Option<&'static str>,
+  /// Seconds since the newest unpushed commit, if it's known.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  unpushed_age: Option<i64>,
+  /// When the newest unpushed commit was made. Like the other timestamps,
+  /// templates can show it relative to now: `{{ newest_unpushed | reltime }}`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[schemars(with = "Option<String>")]
+  newest_unpushed: Option<DateTime<Utc>>,
+  /// When the current commit was made, if it was described or a local ref
+  /// points at it.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[schemars(with = "Option<String>")]
+  head_date: Option<DateTime<Utc>>,
+  /// When the latest tag reachable from the current commit was made: by its
+  /// tagger if annotated, else its commit.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[schemars(with = "Option<String>")]
+  latest_tag_date: Option<DateTime<Utc>>,
+  /// Every local branch with an upstream, and how far they've diverged.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  branches: Vec<BranchTracking>,
+  /// Lines added and deleted in uncommitted files, with --diff-stat.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  diff_stat: Option<DiffTotals<'a>>,
+  /// The status confit will exit with.
+  exit_code: i32,
+  /// Whether every check passed, warnings included.
+  all_passed: bool,
+  /// How the report was produced, for telling archived reports apart.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  meta: Option<Meta>,
+}
+
+/// The confit version that made a report, the checks it ran (by primary
+/// tag), the arguments it was given, and when.
+#[derive(Debug,Serialize,JsonSchema)]
+pub struct Meta {
+  confit_version: &'static str,
+  checks: Vec<&'static str>,
+  args: Vec<String>,
+  #[schemars(with = "String")]
+  timestamp: DateTime<Utc>,
+}
+
+impl Meta {
+  pub fn new(confit_version: &'static str, checks: &[&Check], args: Vec<String>) -> Self {
+    Meta {
+      confit_version,
+      checks: checks.iter().map(|check| check.tags[0]).collect(),
+      args,
+      timestamp: Utc::now(),
     }
-    Ok(())
   }
 }
 
-impl fmt::Display for Item<'_> {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}: {}", self.check.label, self.passed)
+/// A local branch, by its short name, and how far it's ahead of and behind
+/// its upstream.
+#[derive(Debug,PartialEq,Serialize,JsonSchema)]
+pub struct BranchTracking {
+  name: String,
+  ahead: u64,
+  behind: u64,
+}
+
+impl From<(RefName, TrackingCounts)> for BranchTracking {
+  fn from((name, TrackingCounts(ahead, behind)): (RefName, TrackingCounts)) -> Self {
+    BranchTracking { name: name.short().to_string(), ahead, behind }
   }
 }
 
-static ALL_CHECKS: [Check; 9] = [
-  Check {
-    label: "all commits pushed to remote",
-    tags: &["push", "local", "git_prompt"],
+/// Each uncommitted file's counts, staged and not, and what they add up to.
+/// Binary files are counted among the files, but have no lines.
+#[derive(Debug,PartialEq,Serialize,JsonSchema)]
+pub struct DiffTotals<'a> {
+  staged: &'a [git::FileStat],
+  unstaged: &'a [git::FileStat],
+  /// Files with changes, staged or not, each counted once.
+  files: usize,
+  added: u64,
+  deleted: u64,
+}
+
+impl<'a> From<&'a git::DiffStat> for DiffTotals<'a> {
+  fn from(stat: &'a git::DiffStat) -> Self {
+    let both = || stat.staged.iter().chain(&stat.unstaged);
+    DiffTotals {
+      staged: &stat.staged,
+      unstaged: &stat.unstaged,
+      files: both().map(|file| &file.path).collect::<std::collections::BTreeSet<_>>().len(),
+      added: both().filter_map(|file| file.added).sum(),
+      deleted: both().filter_map(|file| file.deleted).sum(),
+    }
+  }
+}
+
+impl Report<'_> {
+  /// Leaves out the items for checks that passed; totals still count every check.
+  pub fn only_failing(mut self) -> Self {
+    self.items.retain(|item| !item.passed);
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  pub fn with_meta(mut self, meta: Meta) -> Self {
+    self.meta = Some(meta);
+    self
+  }
+}
+
+impl<'a> Item<'a> {
+  #[cfg(test)]
+  pub fn check(&self) -> &'a Check {
+    self.check
+  }
+
+  #[cfg(test)]
+  pub fn passed(&self) -> bool {
+    self.passed
+  }
+
+  /// Whether the check failed in a way that counts toward the exit status.
+  pub fn failed(&self) -> bool {
+    self.result.is_failure()
+  }
+
+  // Checks with Severity::Warn, declared or given by --warn, report their
+  // failures as warnings.
+  fn build(check: &'a Check, summary: &Summary) -> Self {
+    let severity = summary.severity_of(check);
+    let unavailable = if summary.remote.missing && check.required_data.includes(datasource::REMOTE) {
+      Some("no remote configured")
+    } else if !check.required_data.subset_of(summary.available) {
+      Some("its data wasn't collected")
+    } else {
+      None
+    };
+    let (result, detail) = match unavailable {
+      Some(why) => (CheckResult::Failed, Some(Detail::Unavailable(why.into()))),
+      None => ((check.eval)(summary), (check.detail)(summary)),
+    };
+    let caveat = (summary.shallow && check.tagged(ANCESTRY_CHECKS.iter().copied())).then_some(SHALLOW_CAVEAT);
+    // A pass on cut-short history stands, with the caveat to say so; a clean
+    // CI checkout is shallow more often than not.
+    let result = match result {
+      result if severity == Severity::Warn => result.as_warning(),
+      result => result,
+    };
+    let passed = matches!(result, CheckResult::Passed);
+    Item{
+      check,
+      glyph: summary.glyphs.for_check(check),
+      result,
+      count: result.count().or(if check.counted && passed { Some(0) } else { None }),
+      passed,
+      warned: result.is_warning(),
+      evaluable: unavailable.is_none(),
+      sources: datasource::Group::all()
+        .filter(|&g| check.required_data.intersects(g))
+        .map(|g| Source {
+          command: g.command(),
+          collected: g.subset_of(summary.collected) && !(g == datasource::REMOTE && summary.remote.missing),
+        })
+        .collect(),
+      severity,
+      detail,
+      caveat,
+    }
+  }
+}
+
+impl fmt::Display for Summary<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let width = self.items().iter().map(|i| i.check.label.len()).max();
+    for i in self.items() {
+      writeln!(
+        f,
+        "  {:>width$}: {}",
+        i.check.label,
+        i.passed,
+        width = width.unwrap_or(0)
+      )?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Item<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.check.label, self.passed)
+  }
+}
+
+static ALL_CHECKS: [Check; 21] = [
+  Check {
+    label: "all commits pushed to remote",
+    tags: &["push", "local", "git_prompt"],
     glyph: '↑',
     status_group: 2,
     required_data: STATUS,
-    eval: unpushed_commit,
+    eval: &unpushed_commit,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: tracking_counts,
+    severity: Severity::Error,
   },
   Check {
     label: "all commits merged from remote",
@@ -235,8 +1311,12 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '↓',
     status_group: 3,
     required_data: union(STATUS, REMOTE),
-    eval: remote_changes,
+    eval: &remote_changes,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: tracking_counts,
+    severity: Severity::Error,
   },
   Check {
     label: "no uncommited changes",
@@ -244,8 +1324,12 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '.',
     status_group: 1,
     required_data: STATUS,
-    eval: uncommited_changes,
+    eval: &uncommited_changes,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: staged_paths,
+    severity: Severity::Error,
   },
   Check {
     label: "no unstaged changes",
@@ -253,8 +1337,25 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '+',
     status_group: 1,
     required_data: STATUS,
-    eval: modified_files,
+    eval: &modified_files,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: unstaged_paths,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "no merge conflicts",
+    tags: &["conflict"],
+    glyph: '⚔',
+    status_group: 1,
+    required_data: STATUS,
+    eval: &merge_conflicts,
+    threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: conflict_stages,
+    severity: Severity::Error,
   },
   Check{
     label: "all files tracked",
@@ -262,8 +1363,12 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '?',
     status_group: 1,
     required_data: STATUS,
-    eval: untracked_files,
+    eval: &untracked_files,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: untracked_paths,
+    severity: Severity::Error,
   },
   Check {
     label: "commit tracked by local ref",
@@ -271,8 +1376,12 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '⌱',
     status_group: 1,
     required_data: STATUS,
-    eval: detached_head,
+    eval: &detached_head,
     threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Error,
   },
   Check {
     label: "branch tracks remote",
@@ -280,8 +1389,25 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '⍏',
     status_group: 2,
     required_data: STATUS,
-    eval: untracked_branch,
+    eval: &untracked_branch,
+    threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: untracked_branch_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "branch tracks same-named remote",
+    tags: &["track_name"],
+    glyph: '≠',
+    status_group: 2,
+    required_data: STATUS,
+    eval: &mismatched_upstream,
     threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Error,
   },
   Check {
     label: "current commit is tagged",
@@ -289,8 +1415,12 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '🏷',
     status_group: 4,
     required_data: union(STATUS, REFS),
-    eval: untagged_commit,
+    eval: &untagged_commit,
     threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: tag_detail,
+    severity: Severity::Error,
   },
   Check {
     label: "tag is pushed",
@@ -298,63 +1428,192 @@ static ALL_CHECKS: [Check; 9] = [
     glyph: '🏳',
     status_group: 4,
     required_data: union(STATUS, REMOTE),
-    eval: unpushed_tag,
+    eval: &unpushed_tag,
+    threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: tag_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "latest tag is recent",
+    tags: &["stale_tag"],
+    glyph: '⌛',
+    status_group: 4,
+    required_data: union(union(STATUS, REFS), DESCRIBE),
+    eval: &stale_tag,
+    // days between the latest tag and the current commit
+    threshold: 30,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Warn,
+  },
+  Check {
+    label: "unpushed work is recent",
+    tags: &["stale_local"],
+    glyph: '⏲',
+    status_group: 2,
+    required_data: union(STATUS, LOG),
+    eval: &stale_unpushed,
+    // hours since the newest unpushed commit, unless --max-unpushed-age says otherwise
+    threshold: 24,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Warn,
+  },
+  Check {
+    label: "commit is on a remote branch or tag",
+    tags: &["push_strict"],
+    glyph: '⇡',
+    status_group: 2,
+    required_data: union(STATUS, REMOTE),
+    eval: &head_on_remote,
+    threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "upstream branch exists on remote",
+    tags: &["upstream_gone"],
+    glyph: '∅',
+    status_group: 2,
+    required_data: union(STATUS, REMOTE),
+    eval: &upstream_on_remote,
+    threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: no_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "every local branch is on the remote",
+    tags: &["unpushed_branch"],
+    glyph: '⑂',
+    status_group: 2,
+    required_data: union(REFS, REMOTE),
+    eval: &unpushed_branches,
+    threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: local_only_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "no commits since the latest tag",
+    tags: &["since_tag"],
+    glyph: '⤴',
+    status_group: 4,
+    required_data: DESCRIBE,
+    eval: &commits_since_tag,
+    threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: since_tag_detail,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "no tracked files are ignored",
+    tags: &["ignore_conflict"],
+    glyph: '⊘',
+    status_group: 1,
+    required_data: LS_FILES,
+    eval: &ignored_tracked_files,
+    threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: tracked_ignored_paths,
+    severity: Severity::Error,
+  },
+  Check {
+    label: "every local branch's commits are pushed",
+    tags: &["push_branches"],
+    glyph: '⇈',
+    status_group: 2,
+    required_data: REFS,
+    eval: &branches_ahead,
+    threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: branches_ahead_detail,
+    severity: Severity::Warn,
+  },
+  Check {
+    label: "no ignored files",
+    tags: &["ignored"],
+    glyph: '◌',
+    status_group: 1,
+    required_data: STATUS,
+    eval: &ignored_files,
     threshold: 0,
+    weight: 1,
+    counted: true,
+    detail: ignored_paths,
+    severity: Severity::Warn,
+  },
+  Check {
+    label: "no merge or rebase in progress",
+    tags: &["in_progress"],
+    glyph: '⟳',
+    status_group: 1,
+    required_data: GIT_DIR,
+    eval: &nothing_in_progress,
+    threshold: 0,
+    weight: 1,
+    counted: false,
+    detail: in_progress_detail,
+    severity: Severity::Error,
   },
   ];
 
 fn untracked_files(s: &Summary) -> CheckResult {
-  s.status
-    .lines
-    .iter()
-    .filter(|line| matches!(line, Untracked{..}))
-    .count()
-    .into()
+  count_lines(s, is_untracked)
 }
 
 fn modified_files(s: &Summary) -> CheckResult {
-  s.status
-    .lines
-    .iter()
-    .filter(|line| matches!(line,
-        One {
-          status: StatusPair { unstaged: m, .. },
-          ..
-        }
-        | Two {
-          status: StatusPair { unstaged: m, .. },
-          ..
-        }
-        | Unmerged {
-          status: StatusPair { unstaged: m, .. },
-          ..
-        } if *m != LineStatus::Unmodified
-    ))
-    .count()
-    .into()
+  count_lines(s, is_unstaged)
 }
 
 fn uncommited_changes(s: &Summary) -> CheckResult {
+  count_lines(s, is_staged)
+}
+
+fn merge_conflicts(s: &Summary) -> CheckResult {
+  count_lines(s, StatusLine::is_conflict)
+}
+
+// Only listed when git status is asked for them, with --ignored. Nothing
+// ignored is preserved, though that's usually the point of ignoring it.
+fn ignored_files(s: &Summary) -> CheckResult {
+  count_lines(s, is_ignored)
+}
+
+fn is_ignored(line: &StatusLine) -> bool {
+  matches!(line, Ignored{..})
+}
+
+fn is_untracked(line: &StatusLine) -> bool {
+  matches!(line, Untracked{..})
+}
+
+fn is_unstaged(line: &StatusLine) -> bool {
+  line.unstaged_status().map_or(false, |m| !m.is_clean())
+}
+
+fn is_staged(line: &StatusLine) -> bool {
+  line.staged_status().map_or(false, |m| !m.is_clean())
+}
+
+fn count_lines(s: &Summary, pred: fn(&StatusLine) -> bool) -> CheckResult {
   s.status
     .lines
     .iter()
-    .filter(|line| matches!( line,
-        One {
-          status: StatusPair { staged: m, .. },
-          ..
-        }
-        | Two {
-          status: StatusPair { staged: m, .. },
-          ..
-        }
-        | Unmerged {
-          status: StatusPair { staged: m, .. },
-          ..
-        } if *m != LineStatus::Unmodified
-    ))
+    .filter(|line| pred(line))
     .count()
     .into()
-
 }
 
 fn detached_head(s: &Summary) -> CheckResult {
@@ -365,21 +1624,145 @@ fn detached_head(s: &Summary) -> CheckResult {
     .into()
 }
 
+// A detached HEAD has no branch to track anything, which is the detached
+// check's failure, and a different fix from giving a branch an upstream. So
+// this only warns, rather than failing twice over.
 fn untracked_branch(s: &Summary) -> CheckResult {
+  match &s.status.branch {
+    Some(b) if b.upstream.is_none() && b.head == Head::Detached => CheckResult::Warn(None),
+    branch => branch.as_ref().map_or(false, |b| b.upstream.is_some()).into(),
+  }
+}
+
+fn untracked_branch_detail(s: &Summary) -> Option<Detail> {
+  let note = match &s.status.branch {
+    None => "no branch information".to_string(),
+    Some(b) => match (&b.head, &b.upstream) {
+      (_, Some(_)) => return None,
+      (Head::Detached, None) => "n/a while HEAD is detached".to_string(),
+      (Head::Branch(name), None) => format!("branch '{}' has no upstream", name.short()),
+    },
+  };
+  Some(Detail::Note(note))
+}
+
+fn mismatched_upstream(s: &Summary) -> CheckResult {
   s.status
     .branch
     .clone()
-    .map_or(false, |b| b.upstream.is_some())
+    .map_or(true, |b| match (b.head, b.upstream) {
+      (Head::Branch(local), Some(upstream)) => upstream.upstream_branch() == local.short(),
+      _ => true,
+    })
     .into()
 }
 
+fn no_detail(_: &Summary) -> Option<Detail> {
+  None
+}
+
+fn untracked_paths(s: &Summary) -> Option<Detail> {
+  matching_paths(s, is_untracked)
+}
+
+fn unstaged_paths(s: &Summary) -> Option<Detail> {
+  matching_paths(s, is_unstaged)
+}
+
+fn staged_paths(s: &Summary) -> Option<Detail> {
+  matching_paths(s, is_staged)
+}
+
+fn matching_paths(s: &Summary, pred: fn(&StatusLine) -> bool) -> Option<Detail> {
+  let paths: Vec<WorkPath> = s.status
+    .lines
+    .iter()
+    .filter(|line| pred(line))
+    .map(|line| line.path().clone())
+    .collect();
+  path_detail(s, paths)
+}
+
+fn ignored_paths(s: &Summary) -> Option<Detail> {
+  let mut paths: Vec<WorkPath> = s.status
+    .lines
+    .iter()
+    .filter(|line| is_ignored(line))
+    .map(|line| line.path().clone())
+    .collect();
+  if paths.is_empty() {
+    return None
+  }
+  if s.path_order == PathOrder::Path {
+    paths.sort()
+  }
+  Some(Detail::Ignored { groups: PathGroup::by_top_dir(&paths), paths })
+}
+
+fn tracked_ignored_paths(s: &Summary) -> Option<Detail> {
+  path_detail(s, s.tracked_ignored.clone())
+}
+
+fn path_detail(s: &Summary, mut paths: Vec<WorkPath>) -> Option<Detail> {
+  if paths.is_empty() {
+    return None
+  }
+  if s.path_order == PathOrder::Path {
+    paths.sort()
+  }
+  Some(Detail::Paths(paths))
+}
+
+fn conflict_stages(s: &Summary) -> Option<Detail> {
+  let mut conflicts: Vec<Conflict> = s.status
+    .lines
+    .iter()
+    .filter_map(|line| line.conflict_kind().map(|kind| Conflict { path: line.path().clone(), kind }))
+    .collect();
+  if conflicts.is_empty() {
+    return None
+  }
+  if s.path_order == PathOrder::Path {
+    conflicts.sort_by(|l, r| l.path.cmp(&r.path))
+  }
+  Some(Detail::Conflicts(conflicts))
+}
+
+fn tracking_counts(s: &Summary) -> Option<Detail> {
+  s.status
+    .branch
+    .as_ref()
+    .and_then(|b| match (b.commits, &b.upstream) {
+      (Some(TrackingCounts(ahead, behind)), upstream) => Some(Detail::Tracking {
+        ahead,
+        behind,
+        local: match &b.oid {
+          Oid::Commit(c) => Some(c.clone()),
+          _ => None,
+        },
+        remote: upstream.as_ref().and_then(|u| remote_tip(s, u)).cloned(),
+      }),
+      (None, Some(upstream)) => Some(Detail::Unfetched { upstream: upstream.as_ref().into() }),
+      (None, None) => None,
+    })
+}
+
+// What the remote has for an upstream, going by its branch name.
+fn remote_tip<'s>(s: &'s Summary, upstream: &RefName) -> Option<&'s ObjectName> {
+  s.remote.object_of(&format!("refs/heads/{}", upstream.upstream_branch()))
+}
+
+// An upstream that's known but not yet fetched has nothing there to merge.
 fn remote_changes(s: &Summary) -> CheckResult {
   s.status
     .branch
     .clone()
     .map_or(1, |b| {
-      b.commits
-        .map_or(1, |TrackingCounts(_, behind)| behind)
+      match (b.commits, b.upstream) {
+        (Some(TrackingCounts(_, behind)), _) => behind,
+        (None, Some(_)) => 0,
+        (None, None) => 1,
+      }
     })
     .into()
 
@@ -396,6 +1779,17 @@ fn unpushed_commit(s: &Summary) -> CheckResult {
   .into()
 }
 
+fn tag_detail(s: &Summary) -> Option<Detail> {
+  if let Some(Oid::Commit(c)) = s.status.branch.clone().map(|b| b.oid) {
+    s.tag_on_commit(c).map(|rl| Detail::Tag {
+      name: rl.local_ref.short().into(),
+      kind: if rl.object_type == Tag { TagKind::Annotated } else { TagKind::Lightweight },
+    })
+  } else {
+    None
+  }
+}
+
 fn untagged_commit(s: &Summary) -> CheckResult {
   (if let Some(Oid::Commit(c)) = s.status.branch.clone().map(|b| b.oid) {
     s.tag_on_commit(c).is_some()
@@ -407,8 +1801,8 @@ fn untagged_commit(s: &Summary) -> CheckResult {
 
 fn unpushed_tag(s: &Summary) -> CheckResult {
   (if let Some(Oid::Commit(c)) = s.status.branch.clone().map(|b| b.oid) {
-    if let Some(t) = s.tag_on_commit(c) {
-      s.ls_remote.iter().any(|rp| rp.refname == t)
+    if let Some(tag) = s.tag_on_commit(c) {
+      s.remote.by_ref.get(&tag.local_ref).map_or(false, |remote| remote.matches(&tag.object_name))
     } else {
       false
     }
@@ -417,3 +1811,982 @@ fn unpushed_tag(s: &Summary) -> CheckResult {
   })
   .into()
 }
+
+// Unlike unpushed_commit, asks the remote itself rather than trusting the
+// ahead count from the last fetch.
+fn head_on_remote(s: &Summary) -> CheckResult {
+  (if let Some(Oid::Commit(c)) = s.status.branch.clone().map(|b| b.oid) {
+    s.remote.publishes(&c)
+  } else {
+    false
+  })
+  .into()
+}
+
+// The local tracking ref outlives a deleted upstream until a pruning fetch,
+// so only the remote can say whether the branch is still there.
+fn upstream_on_remote(s: &Summary) -> CheckResult {
+  s.status
+    .branch
+    .as_ref()
+    .and_then(|b| b.upstream.as_ref())
+    .map_or(true, |upstream| {
+      s.remote.has_ref(&format!("refs/heads/{}", upstream.upstream_branch()))
+        || s.remote.has_ref(&format!("refs/remotes/{}", upstream.as_ref()))
+    })
+    .into()
+}
+
+// A local branch is saved if the remote has a branch of the same name, or
+// the one it tracks.
+fn local_only_branches<'s>(s: &'s Summary) -> Vec<&'s str> {
+  let on_remote = |branch: &str| s.remote.has_ref(&format!("refs/heads/{}", branch));
+  s.for_each_ref
+    .iter()
+    .filter(|rl| rl.local_ref.as_ref().starts_with("refs/heads/"))
+    .filter(|rl| {
+      let tracked = match &rl.upstream {
+        TrackSync::Track { remote_ref, .. } | TrackSync::Gone { remote_ref } => on_remote(remote_ref.refname.upstream_branch()),
+        TrackSync::Untracked => false,
+      };
+      !on_remote(rl.local_ref.short()) && !tracked
+    })
+    .map(|rl| rl.local_ref.short())
+    .collect()
+}
+
+fn unpushed_branches(s: &Summary) -> CheckResult {
+  local_only_branches(s).len().into()
+}
+
+fn local_only_detail(s: &Summary) -> Option<Detail> {
+  let branches = local_only_branches(s);
+  if branches.is_empty() {
+    return None
+  }
+  Some(Detail::Branches(branches.into_iter().map(String::from).collect()))
+}
+
+// Unlike unpushed_branch, this looks at branches that do have an upstream,
+// but have commits it doesn't yet.
+fn ahead_branches(s: &Summary) -> Vec<String> {
+  s.branch_tracking()
+    .into_iter()
+    .filter(|(_, TrackingCounts(ahead, _))| *ahead > 0)
+    .map(|(name, _)| name.short().to_string())
+    .collect()
+}
+
+fn branches_ahead(s: &Summary) -> CheckResult {
+  ahead_branches(s).len().into()
+}
+
+fn branches_ahead_detail(s: &Summary) -> Option<Detail> {
+  let branches = ahead_branches(s);
+  if branches.is_empty() {
+    return None
+  }
+  Some(Detail::Branches(branches))
+}
+
+// Teammates who clone afresh get these, though a change to them in the
+// work tree is easy to miss.
+fn ignored_tracked_files(s: &Summary) -> CheckResult {
+  s.tracked_ignored.len().into()
+}
+
+// With no tags at all, there's no release to have committed past.
+fn commits_since_tag(s: &Summary) -> CheckResult {
+  s.described.as_ref().map_or(CheckResult::Passed, |d| d.commits.into())
+}
+
+// Walking away mid-rebase leaves the branch somewhere between its old and
+// new history, which is easy to forget and confusing to come back to.
+fn nothing_in_progress(s: &Summary) -> CheckResult {
+  s.in_progress.is_empty().into()
+}
+
+fn in_progress_detail(s: &Summary) -> Option<Detail> {
+  if s.in_progress.is_empty() {
+    return None
+  }
+  let ops: Vec<String> = s.in_progress.iter().map(ToString::to_string).collect();
+  Some(Detail::Note(format!("{} in progress", ops.join(", "))))
+}
+
+fn since_tag_detail(s: &Summary) -> Option<Detail> {
+  match &s.described {
+    None => Some(Detail::Note("no tags yet".into())),
+    Some(d) if d.commits > 0 => Some(Detail::Note(format!("since {}", d.tag))),
+    Some(_) => None,
+  }
+}
+
+fn stale_tag(s: &Summary) -> CheckResult {
+  let threshold = Duration::days(s.threshold("stale_tag").into());
+  match (s.head_date(), s.latest_tag_date()) {
+    (Some(commit), Some(tag)) => commit - tag <= threshold,
+    _ => true,
+  }
+  .into()
+}
+
+fn stale_unpushed(s: &Summary) -> CheckResult {
+  let threshold = s.max_unpushed_age.unwrap_or_else(|| {
+    Duration::hours(s.threshold("stale_local").into())
+  });
+  s.unpushed_age().map_or(true, |age| age <= threshold).into()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use datasource::Group;
+
+  fn summary_of<'a>(checks: Vec<&'a Check>) -> Summary<'a> {
+    Summary::new(vec![], git::Status::default(), vec![], checks)
+  }
+
+  #[test]
+  fn warnings_leave_exit_status_alone() {
+    let mut summary = summary_of(Check::tagged_checks(vec!["tag"]));
+    assert_eq!(summary.exit_status(), 1 << 4);
+
+    summary.warn_for(Check::tagged_checks(vec!["tag"]));
+    assert_eq!(summary.exit_status(), 0);
+    assert!(summary.items().iter().all(|i| !i.passed && i.warned && i.severity == Severity::Warn));
+    assert!(summary.items().iter().all(|i| matches!(i.result, CheckResult::Warn(None))));
+    assert!(!summary.passed());
+  }
+
+  #[test]
+  fn warnings_are_their_own_state() {
+    assert!(!CheckResult::Warn(None).is_failure());
+    assert!(CheckResult::Warn(None).is_warning());
+    assert_eq!(CheckResult::Warn(Some(2)).count(), Some(2));
+    assert!(matches!(CheckResult::Bad(3).as_warning(), CheckResult::Warn(Some(3))));
+    assert!(matches!(CheckResult::Passed.as_warning(), CheckResult::Passed));
+
+    let mut summary = summary_of(Check::tagged_checks(vec!["tag", "detached"]));
+    summary.warn_for(Check::tagged_checks(vec!["tag"]));
+    assert_eq!(summary.totals(), Totals { checks: 2, passed: 0, failed: 1, warned: 1 });
+    assert_eq!(summary.failed_items().len(), 1);
+    assert_eq!(summary.exit_status(), 1 << 1);
+    assert_eq!(summary.porcelain(), "fail\t1\tdetached\t\nwarn\t4\ttag\t\n");
+    assert_eq!(serde_json::to_value(CheckResult::Warn(Some(2))).unwrap(), serde_json::json!({"warn": 2}));
+  }
+
+  fn on_branch(head: Head, upstream: Option<&str>) -> git::Status {
+    git::Status {
+      branch: Some(git::parse::status::Branch {
+        oid: Oid::Commit("0a03ba3cfde6472cb7431958dd78ca2c0d65de74".into()),
+        head,
+        upstream: upstream.map(|u| u.into()),
+        commits: Some(TrackingCounts(0, 0)),
+      }),
+      lines: vec![],
+    }
+  }
+
+  fn run(check: fn(&Summary) -> CheckResult, status: git::Status) -> bool {
+    matches!(check(&Summary::new(vec![], status, vec![], vec![])), CheckResult::Passed)
+  }
+
+  #[test]
+  fn untracked_branch_notes() {
+    let note = |status| untracked_branch_detail(&Summary::new(vec![], status, vec![], vec![]));
+    assert_eq!(note(on_branch(Head::Branch("feature".into()), Some("origin/feature"))), None);
+    assert_eq!(
+      note(on_branch(Head::Branch("feature".into()), None)),
+      Some(Detail::Note("branch 'feature' has no upstream".into()))
+    );
+    assert_eq!(note(on_branch(Head::Detached, None)), Some(Detail::Note("n/a while HEAD is detached".into())));
+    assert_eq!(note(git::Status::default()), Some(Detail::Note("no branch information".into())));
+    assert!(!run(untracked_branch, on_branch(Head::Branch("feature".into()), None)));
+    assert!(untracked_branch(&summary_of(vec![])).is_failure());
+  }
+
+  #[test]
+  fn detached_head_fails_once() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-detached")).unwrap();
+    let checks = Check::tagged_checks(vec!["detached", "track_remote", "track_name"]);
+    let summary = Summary::new(vec![], status, vec![], checks);
+    let results = summary.items().iter()
+      .map(|i| (i.check.tags[0], i.passed, i.warned))
+      .collect::<Vec<_>>();
+    assert_eq!(results, vec![("detached", false, false), ("track_remote", false, true), ("track_name", true, false)]);
+    assert_eq!(summary.result_for("track_remote").unwrap().detail, Some(Detail::Note("n/a while HEAD is detached".into())));
+    // only the detached check's status group
+    assert_eq!(summary.exit_status(), 1 << 1);
+  }
+
+  #[test]
+  fn upstream_name_matches() {
+    assert!(run(mismatched_upstream, on_branch(Head::Branch("feature".into()), Some("origin/feature"))));
+    assert!(run(mismatched_upstream, on_branch(Head::Branch("team/feature".into()), Some("origin/team/feature"))));
+    // the same refs, in full
+    assert!(run(mismatched_upstream, on_branch(Head::Branch("refs/heads/feature".into()), Some("refs/remotes/origin/feature"))));
+    assert!(run(mismatched_upstream, on_branch(Head::Branch("feature".into()), Some("refs/remotes/origin/feature"))));
+  }
+
+  #[test]
+  fn upstream_name_mismatched() {
+    assert!(!run(mismatched_upstream, on_branch(Head::Branch("feature".into()), Some("origin/main"))));
+    assert!(!run(mismatched_upstream, on_branch(Head::Branch("feature".into()), Some("origin/my-feature"))));
+    assert!(!run(mismatched_upstream, on_branch(Head::Branch("feature".into()), Some("origin/team/feature"))));
+  }
+
+  #[test]
+  fn upstream_name_detached() {
+    assert!(run(mismatched_upstream, on_branch(Head::Detached, Some("origin/main"))));
+    assert!(run(mismatched_upstream, on_branch(Head::Branch("feature".into()), None)));
+  }
+
+  #[test]
+  fn selecting_checks() {
+    let labels = |checks: Vec<&Check>| checks.iter().map(|ch| ch.label).collect::<Vec<_>>();
+    assert_eq!(Check::iter().count(), Check::all_checks().len());
+    assert_eq!(
+      labels(Check::iter().filter(select::by_tag(vec!["track_*"])).collect()),
+      labels(Check::tagged_checks(vec!["track_*"]))
+    );
+    assert_eq!(
+      labels(Check::iter().filter(select::by_tag(vec!["tag", "push_tag"])).collect()),
+      vec!["current commit is tagged", "tag is pushed"]
+    );
+    assert_eq!(
+      labels(Check::iter().filter(select::in_groups(4..=4)).collect()),
+      vec!["current commit is tagged", "tag is pushed", "latest tag is recent", "no commits since the latest tag"]
+    );
+    assert_eq!(
+      labels(Check::iter().filter(select::in_groups(2..=2)).filter(select::requiring(REMOTE)).collect()),
+      vec!["commit is on a remote branch or tag", "upstream branch exists on remote", "every local branch is on the remote"]
+    );
+    assert_eq!(
+      labels(Check::iter().filter(select::by_tag(vec!["local"])).filter(select::requiring(REMOTE)).collect()),
+      Vec::<&str>::new()
+    );
+  }
+
+  #[test]
+  fn check_by_tag() {
+    assert_eq!(Check::by_tag("push").map(|ch| ch.label), Some("all commits pushed to remote"));
+    assert!(Check::by_tag("nope").is_none());
+  }
+
+  #[test]
+  fn tag_suggestions() {
+    assert_eq!(edit_distance("psh", "push"), 1);
+    assert_eq!(edit_distance("", "tag"), 3);
+    assert_eq!(edit_distance("stage", "stage"), 0);
+
+    assert_eq!(Check::suggest_tag("psh"), Some("push"));
+    assert_eq!(Check::suggest_tag("comit"), Some("commit"));
+    assert_eq!(Check::suggest_tag("track_remot"), Some("track_remote"));
+    assert_eq!(Check::suggest_tag("xyzzy"), None);
+  }
+
+  #[test]
+  fn tag_globs() {
+    assert!(tag_matches("track_*", "track_files"));
+    assert!(tag_matches("push", "push"));
+    assert!(tag_matches("*_tag", "push_tag"));
+    assert!(tag_matches("s?age", "stage"));
+    assert!(!tag_matches("push", "push_tag"));
+    assert!(!tag_matches("track_*", "stale_tag"));
+
+    let tracking = Check::tagged_checks(vec!["track_*"]);
+    assert!(tracking.iter().any(|ch| ch.tags.contains(&"track_files")));
+    assert!(tracking.iter().any(|ch| ch.tags.contains(&"track_remote")));
+    assert!(tracking.iter().all(|ch| ch.tags.iter().any(|t| t.starts_with("track_"))));
+
+    assert!(Check::tagged_checks(vec!["nothing_*"]).is_empty());
+  }
+
+  fn ref_line(name: &str, object: &str, object_type: git::parse::for_each_ref::ObjectType, date: &str) -> git::RefLine {
+    let date = DateTime::parse_from_rfc3339(date).unwrap().into();
+    git::RefLine {
+      object_name: object.into(),
+      referred_object: None,
+      object_type,
+      local_ref: name.into(),
+      upstream: git::parse::for_each_ref::TrackSync::Untracked,
+      creator_name: "Judson".into(),
+      creator_email: "nyarly@gmail.com".into(),
+      creation_date: date,
+      tagger_date: None,
+    }
+  }
+
+  #[test]
+  fn stale_tag_by_age() {
+    let head = "0a03ba3cfde6472cb7431958dd78ca2c0d65de74";
+    let refs = |tagged: &str| vec![
+      ref_line("refs/heads/feature", head, Commit, "2021-06-30T12:00:00Z"),
+      ref_line("refs/tags/v1.0", "f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2", Commit, tagged),
+      // newer, but on another branch
+      ref_line("refs/tags/v2.0-rc", "9b5e1bd3a7e0a5f1b2c4d6e8f0a1b3c5d7e9f1a3", Commit, "2021-06-29T12:00:00Z"),
+    ];
+    let status = || on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+    let summary = |status, refs, committed: Option<&str>| {
+      let mut summary = Summary::new(vec![], status, refs, vec![]);
+      summary.described = Some(git::Description {
+        tag: "v1.0".into(),
+        commits: 3,
+        object_name: "0a03ba3".into(),
+        committed: committed.map(|date| DateTime::parse_from_rfc3339(date).unwrap().into()),
+      });
+      summary
+    };
+
+    let fresh = summary(status(), refs("2021-06-20T12:00:00Z"), None);
+    assert!(matches!(stale_tag(&fresh), CheckResult::Passed));
+
+    let stale = summary(status(), refs("2021-01-01T12:00:00Z"), None);
+    assert!(matches!(stale_tag(&stale), CheckResult::Failed));
+
+    let untagged = Summary::new(vec![], status(), vec![], vec![]);
+    assert!(matches!(stale_tag(&untagged), CheckResult::Passed));
+
+    // No ref points at a detached HEAD, but describing it dates it.
+    let detached = |committed| summary(on_branch(Head::Detached, None), refs("2021-01-01T12:00:00Z").into_iter().skip(1).collect(), committed);
+    assert!(matches!(stale_tag(&detached(Some("2021-06-30T12:00:00Z"))), CheckResult::Failed));
+    assert!(matches!(stale_tag(&detached(None)), CheckResult::Passed));
+
+    let report = serde_json::to_value(stale.report()).unwrap();
+    assert_eq!(report["head_date"], "2021-06-30T12:00:00Z");
+    assert_eq!(report["latest_tag_date"], "2021-01-01T12:00:00Z");
+    assert!(serde_json::to_value(untagged.report()).unwrap().get("latest_tag_date").is_none());
+  }
+
+  #[test]
+  fn result_counts() {
+    assert_eq!(CheckResult::Passed.count(), None);
+    assert_eq!(CheckResult::Failed.count(), None);
+    assert_eq!(CheckResult::Bad(3).count(), Some(3));
+    assert_eq!(CheckResult::from(0usize).count(), None);
+
+    assert!(!CheckResult::Passed.is_failure());
+    assert!(CheckResult::Failed.is_failure());
+    assert!(CheckResult::Bad(1).is_failure());
+  }
+
+  #[test]
+  fn item_counts() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-triple-u")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::tagged_checks(vec!["commit", "track_files", "detached", "tag"]));
+    let counts: Vec<_> = summary.items().iter().map(|item| (item.check.tags[0], item.count)).collect();
+    assert_eq!(counts, vec![
+      ("commit", Some(7)),
+      ("track_files", Some(0)),
+      ("detached", None),
+      ("tag", None),
+    ]);
+  }
+
+  #[test]
+  fn glyph_lookup() {
+    let push = Check::by_tag("push").unwrap();
+    let stage = Check::by_tag("stage").unwrap();
+    let custom = Check::from_def(def("staged > 5")).unwrap();
+    let mut unicode_custom = def("staged > 5");
+    unicode_custom.glyph = '★';
+    let unicode_custom = Check::from_def(unicode_custom).unwrap();
+
+    let plain = Glyphs::default();
+    assert_eq!(plain.for_check(push), "↑");
+
+    let ascii = Glyphs::new(HashMap::new(), true);
+    assert_eq!(ascii.for_check(push), "^");
+    assert_eq!(ascii.for_check(stage), "+");
+    assert_eq!(ascii.for_check(&custom), "S");
+    assert_eq!(ascii.for_check(&unicode_custom), "*");
+    assert!(Check::all_checks().iter().all(|ch| ascii.for_check(ch).is_ascii()));
+
+    let configured = Glyphs::new(vec![("push".to_string(), "P".to_string())].into_iter().collect(), true);
+    assert_eq!(configured.for_check(push), "P");
+    assert_eq!(configured.for_check(Check::by_tag("merge").unwrap()), "v");
+  }
+
+  #[test]
+  fn file_checks_over_conflicts() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-triple-u")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], vec![]);
+    assert!(matches!(merge_conflicts(&summary), CheckResult::Bad(1)));
+    assert!(matches!(uncommited_changes(&summary), CheckResult::Bad(7)));
+    assert!(matches!(modified_files(&summary), CheckResult::Bad(1)));
+    assert!(matches!(untracked_files(&summary), CheckResult::Passed));
+  }
+
+  // As git reports them unless they're ignored by configuration: modified
+  // content, untracked content, and new commits in each submodule.
+  #[test]
+  fn file_checks_over_dirty_submodules() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-dirty-submodules")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], vec![]);
+    assert!(matches!(modified_files(&summary), CheckResult::Bad(3)));
+    assert!(matches!(uncommited_changes(&summary), CheckResult::Passed));
+    assert!(matches!(untracked_files(&summary), CheckResult::Passed));
+  }
+
+  #[test]
+  fn path_detail_order() {
+    let status = git::parse::status(include_str!("testdata/status-unsorted")).unwrap();
+    let mut summary = Summary::new(vec![], status, vec![], vec![]);
+    let paths = |names: &[&str]| Some(Detail::Paths(names.iter().map(|&n| WorkPath::from(n)).collect()));
+
+    assert_eq!(untracked_paths(&summary), paths(&["zebra.txt", "Makefile.local", "alpha.txt"]));
+    assert_eq!(unstaged_paths(&summary), paths(&["src/main.rs", "README.md", "Cargo.toml"]));
+    assert_eq!(staged_paths(&summary), paths(&["src/main.rs"]));
+    assert_eq!(conflict_stages(&summary), None);
+
+    summary.sort_paths(PathOrder::Path);
+    assert_eq!(untracked_paths(&summary), paths(&["Makefile.local", "alpha.txt", "zebra.txt"]));
+    assert_eq!(unstaged_paths(&summary), paths(&["Cargo.toml", "README.md", "src/main.rs"]));
+  }
+
+  #[test]
+  fn suggested_command() {
+    let status = git::parse::status(include_str!("testdata/status-unsorted")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], Check::all_checks());
+    assert_eq!(summary.suggestion(), Some("git add -A"));
+
+    let summary = summary_of(Check::tagged_checks(vec!["push", "tag"]));
+    assert_eq!(summary.suggestion(), Some("git push"));
+
+    let clean = on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+    let summary = Summary::new(vec![], clean, vec![], Check::tagged_checks(vec!["stage", "commit", "push"]));
+    assert_eq!(summary.suggestion(), None);
+  }
+
+  #[test]
+  fn tracking_joins_remote_tip() {
+    let local = "0a03ba3cfde6472cb7431958dd78ca2c0d65de74";
+    let tip = "d4ae7077d4ed711a10e89908ab91999ce326dfc0";
+    let remote = vec![
+      git::RefPair { object_name: tip.into(), refname: "refs/heads/feature".into(), peeled: None },
+      git::RefPair { object_name: local.into(), refname: "refs/heads/main".into(), peeled: None },
+    ];
+    let mut status = on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+    status.branch.as_mut().unwrap().commits = Some(TrackingCounts(0, 2));
+    let summary = Summary::new(remote, status, vec![], vec![]);
+    assert_eq!(tracking_counts(&summary), Some(Detail::Tracking {
+      ahead: 0,
+      behind: 2,
+      local: Some(local.into()),
+      remote: Some(tip.into()),
+    }));
+
+    // without the remote's refs, only the local end is known
+    let mut status = on_branch(Head::Branch("feature".into()), Some("refs/remotes/origin/feature"));
+    status.branch.as_mut().unwrap().commits = Some(TrackingCounts(0, 2));
+    match tracking_counts(&Summary::new(vec![], status, vec![], vec![])) {
+      Some(Detail::Tracking { local, remote, .. }) => {
+        assert_eq!(local, Some("0a03ba3cfde6472cb7431958dd78ca2c0d65de74".into()));
+        assert_eq!(remote, None);
+      }
+      other => panic!("not tracking: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn upstream_not_fetched() {
+    let status = || git::parse::status(include_str!("git/parse/testdata/status-upstream-no-ab")).unwrap();
+    assert!(run(remote_changes, status()));
+    assert!(!run(unpushed_commit, status()));
+
+    let summary = Summary::new(vec![], status(), vec![], vec![]);
+    assert_eq!(tracking_counts(&summary), Some(Detail::Unfetched { upstream: "origin/release".into() }));
+
+    let mut untracked = status();
+    untracked.branch.as_mut().unwrap().upstream = None;
+    assert!(!run(remote_changes, untracked));
+  }
+
+  #[test]
+  fn conflict_details() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-conflict-kinds")).unwrap();
+    let mut summary = Summary::new(vec![], status, vec![], vec![]);
+    assert!(matches!(merge_conflicts(&summary), CheckResult::Bad(4)));
+
+    summary.sort_paths(PathOrder::Path);
+    let conflict = |path: &str, kind| Conflict { path: WorkPath::from(path), kind };
+    assert_eq!(conflict_stages(&summary), Some(Detail::Conflicts(vec![
+      conflict("CHANGELOG.md", ConflictKind::BothAdded),
+      conflict("docs/old-guide.md", ConflictKind::DeletedByUs),
+      conflict("lib/legacy.rs", ConflictKind::DeletedByThem),
+      conflict("src/main.rs", ConflictKind::BothModified),
+    ])));
+  }
+
+  #[test]
+  fn all_but_skipped() {
+    let mut checks = Check::tagged_checks(vec!["all"]);
+    assert_eq!(checks.len(), Check::all_checks().len());
+
+    checks.skip(vec!["merge"]);
+    assert_eq!(checks.len(), Check::all_checks().len() - 1);
+    assert!(!checks.iter().any(|ch| ch.tags.contains(&"merge")));
+
+    checks.skip(vec!["push_tag", "push_strict", "upstream_gone", "unpushed_branch"]);
+    assert!(!checks.required_sources().includes(REMOTE));
+  }
+
+  #[test]
+  fn stale_unpushed_by_age() {
+    let mut summary = summary_of(vec![]);
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Passed));
+
+    summary.newest_unpushed = Some(Utc::now() - Duration::hours(3));
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Passed));
+
+    summary.newest_unpushed = Some(Utc::now() - Duration::days(3));
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Failed));
+
+    summary.set_thresholds(vec![("stale_local".to_string(), 96)].into_iter().collect());
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Passed));
+
+    summary.limit_unpushed_age(Duration::days(2));
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Failed));
+
+    summary.limit_unpushed_age(Duration::days(7));
+    assert!(matches!(stale_unpushed(&summary), CheckResult::Passed));
+  }
+
+  #[test]
+  fn head_on_remote_by_sha() {
+    let head = "0a03ba3cfde6472cb7431958dd78ca2c0d65de74";
+    let pair = |sha: &str, refname: &str| git::RefPair { object_name: sha.into(), refname: refname.into(), peeled: None };
+    let status = || on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+
+    let pushed = Summary::new(vec![pair(head, "refs/heads/feature")], status(), vec![], vec![]);
+    assert!(matches!(head_on_remote(&pushed), CheckResult::Passed));
+
+    // an annotated tag's own object isn't the commit; its peeled line names that
+    let annotated = git::parse::ls_remote(&format!("5b0e2e8a6e4f3c1d9a7b2c4e6f8a0b1c3d5e7f90\trefs/tags/v1.0\n{}\trefs/tags/v1.0^{{}}\n", head)).unwrap();
+    let tagged = Summary::new(annotated, status(), vec![], vec![]);
+    assert!(matches!(head_on_remote(&tagged), CheckResult::Passed));
+    assert!(tagged.remote.has_ref("refs/tags/v1.0"));
+    assert!(!tagged.remote.has_ref("refs/tags/v1.0^{}"));
+
+    let elsewhere = Summary::new(vec![
+      pair("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2", "refs/heads/feature"),
+      pair(head, "refs/pull/12/head"),
+    ], status(), vec![], vec![]);
+    assert!(matches!(head_on_remote(&elsewhere), CheckResult::Failed));
+
+    // refs collected after the summary was built are indexed too
+    let mut later = Summary::new(vec![], status(), vec![], vec![]);
+    assert!(matches!(head_on_remote(&later), CheckResult::Failed));
+    later.set_ls_remote(vec![pair(head, "refs/heads/feature")]);
+    assert!(matches!(head_on_remote(&later), CheckResult::Passed));
+  }
+
+  #[test]
+  fn local_only_branch() {
+    let pair = |refname: &str| git::RefPair { object_name: "0a03ba3cfde6472cb7431958dd78ca2c0d65de74".into(), refname: refname.into(), peeled: None };
+    let refs = || git::parse::for_each_ref(include_str!("git/parse/testdata/for-each-ref-local-branch")).unwrap();
+
+    // feature is pushed under the name it tracks; scratch and spike (whose
+    // upstream is gone) are nowhere on the remote
+    let summary = Summary::new(vec![pair("HEAD"), pair("refs/heads/main"), pair("refs/heads/feature-renamed")], git::Status::default(), refs(), vec![]);
+    assert!(matches!(unpushed_branches(&summary), CheckResult::Bad(2)));
+    assert_eq!(local_only_detail(&summary), Some(Detail::Branches(vec!["scratch".into(), "spike".into()])));
+
+    let everywhere = Summary::new(vec![
+      pair("refs/heads/main"), pair("refs/heads/feature-renamed"), pair("refs/heads/scratch"), pair("refs/heads/spike"),
+    ], git::Status::default(), refs(), vec![]);
+    assert!(matches!(unpushed_branches(&everywhere), CheckResult::Passed));
+    assert_eq!(local_only_detail(&everywhere), None);
+  }
+
+  #[test]
+  fn branches_ahead_of_upstream() {
+    let refs = git::parse::for_each_ref(include_str!("git/parse/testdata/for-each-ref-branches")).unwrap();
+    let summary = Summary::new(vec![], git::Status::default(), refs, Check::tagged_checks(vec!["push_branches"]));
+
+    // scratch has no upstream, and spike's is gone; the remote branch isn't local
+    assert_eq!(summary.branch_tracking(), vec![
+      ("refs/heads/main".into(), TrackingCounts(2, 0)),
+      ("refs/heads/feature".into(), TrackingCounts(1, 3)),
+      ("refs/heads/old".into(), TrackingCounts(0, 4)),
+      ("refs/heads/synced".into(), TrackingCounts(0, 0)),
+    ]);
+
+    let item = summary.items().remove(0);
+    assert!(item.warned);
+    assert!(matches!(item.result, CheckResult::Warn(Some(2))));
+    assert_eq!(item.detail, Some(Detail::Branches(vec!["main".into(), "feature".into()])));
+    assert_eq!(summary.exit_status(), 0);
+
+    let json = serde_json::to_value(summary.report()).unwrap();
+    assert_eq!(json["branches"][1], serde_json::json!({"name": "feature", "ahead": 1, "behind": 3}));
+    assert_eq!(json["branches"].as_array().unwrap().len(), 4);
+    assert!(serde_json::to_value(summary_of(vec![]).report()).unwrap().get("branches").is_none());
+  }
+
+  #[test]
+  fn upstream_gone_from_remote() {
+    let pair = |refname: &str| git::RefPair { object_name: "0a03ba3cfde6472cb7431958dd78ca2c0d65de74".into(), refname: refname.into(), peeled: None };
+    let status = || on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+
+    let present = Summary::new(vec![pair("HEAD"), pair("refs/heads/master"), pair("refs/heads/feature")], status(), vec![], vec![]);
+    assert!(matches!(upstream_on_remote(&present), CheckResult::Passed));
+
+    let deleted = Summary::new(vec![pair("HEAD"), pair("refs/heads/master"), pair("refs/heads/feature-2")], status(), vec![], vec![]);
+    assert!(matches!(upstream_on_remote(&deleted), CheckResult::Failed));
+
+    let untracked = Summary::new(vec![pair("refs/heads/master")], on_branch(Head::Branch("feature".into()), None), vec![], vec![]);
+    assert!(matches!(upstream_on_remote(&untracked), CheckResult::Passed));
+  }
+
+  #[test]
+  fn tagged_annotated_or_lightweight() {
+    let at = |commit: &str| {
+      let mut status = on_branch(Head::Branch("main".into()), Some("origin/main"));
+      status.branch.as_mut().unwrap().oid = Oid::Commit(commit.into());
+      let refs = git::parse::for_each_ref(include_str!("git/parse/testdata/for-each-ref-tags")).unwrap();
+      Summary::new(vec![], status, refs, vec![])
+    };
+
+    let annotated = at("f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2");
+    assert!(matches!(untagged_commit(&annotated), CheckResult::Passed));
+    assert_eq!(tag_detail(&annotated), Some(Detail::Tag { name: "v1.0".into(), kind: TagKind::Annotated }));
+
+    let lightweight = at("d98f5dc243faaf545c3fcf08c3b02f44c58981d4");
+    assert!(matches!(untagged_commit(&lightweight), CheckResult::Passed));
+    assert_eq!(tag_detail(&lightweight), Some(Detail::Tag { name: "v1.1".into(), kind: TagKind::Lightweight }));
+
+    let untagged = at("0a03ba3cfde6472cb7431958dd78ca2c0d65de74");
+    assert!(matches!(untagged_commit(&untagged), CheckResult::Failed));
+    assert_eq!(tag_detail(&untagged), None);
+
+    // with HEAD abbreviated
+    let abbreviated = at("f8f4934");
+    assert!(matches!(untagged_commit(&abbreviated), CheckResult::Passed));
+    assert!(matches!(untagged_commit(&at("f8f493")), CheckResult::Failed));
+  }
+
+  #[test]
+  fn unpushed_tag_by_ref_and_sha() {
+    let head = "0a03ba3cfde6472cb7431958dd78ca2c0d65de74";
+    let tag_object = "f8f49343edaa2a1e6903cbad13ddbc50ad9e12d2";
+    let summary = |remote: Vec<git::RefPair>| {
+      let status = on_branch(Head::Branch("feature".into()), Some("origin/feature"));
+      let refs = vec![ref_line("refs/tags/v1.0", tag_object, Tag, "2021-06-30T12:00:00Z")];
+      let mut summary = Summary::new(remote, status, refs, vec![]);
+      summary.for_each_ref[0].referred_object = Some(head.into());
+      summary
+    };
+    let pair = |sha: &str, refname: &str| git::RefPair { object_name: sha.into(), refname: refname.into(), peeled: None };
+
+    assert!(matches!(unpushed_tag(&summary(vec![pair(tag_object, "refs/tags/v1.0")])), CheckResult::Passed));
+    // the same tag name, pointing elsewhere on the remote
+    assert!(matches!(unpushed_tag(&summary(vec![pair(head, "refs/tags/v1.0")])), CheckResult::Failed));
+    // the same object, under another name
+    assert!(matches!(unpushed_tag(&summary(vec![pair(tag_object, "refs/tags/v1.1")])), CheckResult::Failed));
+    // abbreviated on the remote's side
+    assert!(matches!(unpushed_tag(&summary(vec![pair("f8f49343edaa", "refs/tags/v1.0")])), CheckResult::Passed));
+  }
+
+  #[test]
+  fn head_on_remote_abbreviated() {
+    let at = |commit: &str| {
+      let mut status = on_branch(Head::Branch("main".into()), Some("origin/main"));
+      status.branch.as_mut().unwrap().oid = Oid::Commit(commit.into());
+      let remote = vec![git::RefPair { object_name: "0a03ba3cfde6472cb7431958dd78ca2c0d65de74".into(), refname: "refs/heads/main".into(), peeled: None }];
+      Summary::new(remote, status, vec![], vec![])
+    };
+    assert!(matches!(head_on_remote(&at("0a03ba3cfde6472cb7431958dd78ca2c0d65de74")), CheckResult::Passed));
+    assert!(matches!(head_on_remote(&at("0a03ba3c")), CheckResult::Passed));
+    assert!(matches!(head_on_remote(&at("0a03ba")), CheckResult::Failed));
+    assert!(matches!(head_on_remote(&at("0a03ba3d")), CheckResult::Failed));
+  }
+
+  #[test]
+  fn group_membership() {
+    let local = STATUS | REFS;
+    assert!(local.intersects(REFS | REMOTE));
+    assert!(local.includes(REFS | REMOTE));
+    assert!(!(REFS | REMOTE).subset_of(local));
+    assert!(REFS.subset_of(local));
+    assert!(!local.intersects(REMOTE));
+    assert!(!datasource::EMPTY.intersects(local));
+    assert!(datasource::EMPTY.subset_of(local));
+    assert!(union(STATUS, REFS) == local);
+  }
+
+  #[test]
+  fn groups_are_single_distinct_sources() {
+    let all: Vec<_> = Group::all().collect();
+    assert_eq!(all.len(), 8);
+    for (i, &g) in all.iter().enumerate() {
+      assert!(g.subset_of(g) && g.intersects(g));
+      assert!(all.iter().skip(i + 1).all(|&other| !g.intersects(other)));
+    }
+    let every = all.iter().fold(datasource::EMPTY, |acc, &g| acc | g);
+    assert!(Check::all_checks().required_sources().subset_of(every));
+  }
+
+  #[test]
+  fn restricted_checks() {
+    let mut checks = Check::all_checks();
+    checks.restrict_to(STATUS);
+    assert!(checks.iter().all(|ch| ch.required_data.subset_of(STATUS)));
+    assert!(checks.iter().any(|ch| ch.tags.contains(&"push")));
+    assert!(!checks.iter().any(|ch| ch.tags.contains(&"merge")));
+  }
+
+  #[test]
+  fn ref_pattern_for_tag_checks() {
+    assert_eq!(Check::tagged_checks(vec!["tag", "stage"]).ref_pattern(), Some("refs/tags/"));
+    assert_eq!(Check::tagged_checks(vec!["tag", "stale_tag"]).ref_pattern(), None);
+    assert_eq!(Check::tagged_checks(vec!["unpushed_branch"]).ref_pattern(), None);
+  }
+
+  #[test]
+  fn totals_line() {
+    let summary = summary_of(Check::tagged_checks(vec!["local"]));
+    let totals = summary.totals();
+    assert_eq!(totals, Totals { checks: 7, passed: 3, failed: 4, warned: 0 });
+    assert_eq!(totals.to_string(), "checks=7 passed=3 failed=4 warned=0");
+  }
+
+  fn def(fail_when: &str) -> CheckDef {
+    CheckDef {
+      label: "few staged files".into(),
+      tags: vec!["few_staged".into()],
+      glyph: 'S',
+      status_group: 1,
+      required_data: vec!["status".into()],
+      fail_when: fail_when.into(),
+      severity: None,
+      weight: None,
+    }
+  }
+
+  #[test]
+  fn custom_threshold_check() {
+    let status = git::parse::status(include_str!("git/parse/testdata/status-triple-u")).unwrap();
+    let strict = Check::from_def(def("staged > 5")).unwrap();
+    let lax = Check::from_def(def("staged > 10 || conflicts > 1")).unwrap();
+    let summary = Summary::new(vec![], status, vec![], vec![&strict, &lax]);
+
+    let items = summary.items();
+    assert!(matches!(items[0].result, CheckResult::Failed));
+    assert!(matches!(items[1].result, CheckResult::Passed));
+    assert_eq!(summary.exit_status(), 1 << 1);
+    assert_eq!(strict.tags, &["few_staged"]);
+  }
+
+  #[test]
+  fn weighted_exit_status() {
+    let mut heavy = def("staged > 5");
+    heavy.weight = Some(200);
+    let heavy = Check::from_def(heavy).unwrap();
+    let status = git::parse::status(include_str!("git/parse/testdata/status-triple-u")).unwrap();
+    // commit (group 1) and tag (group 4) fail alongside the custom check
+    let mut checks = Check::tagged_checks(vec!["commit", "tag"]);
+    checks.push(&heavy);
+    let mut summary = Summary::new(vec![], status, vec![], checks);
+    assert_eq!(summary.exit_status(), 1 << 1 | 1 << 4);
+
+    summary.exit_by(ExitMode::Weight);
+    assert_eq!(summary.exit_status(), 202);
+
+    let mut heavier = def("staged > 5");
+    heavier.weight = Some(100);
+    let heavier = Check::from_def(heavier).unwrap();
+    summary.checks.push(&heavier);
+    assert_eq!(summary.exit_status(), 255);
+
+    summary.warn_for(vec![&heavy]);
+    assert_eq!(summary.exit_status(), 102);
+  }
+
+  #[test]
+  fn gated_exit_status() {
+    let mut outlier = def("staged > 5");
+    outlier.status_group = 5;
+    let outlier = Check::from_def(outlier).unwrap();
+    let status = git::parse::status(include_str!("git/parse/testdata/status-triple-u")).unwrap();
+    // commit (group 1) and tag (group 4) fail alongside the custom check
+    let mut checks = Check::tagged_checks(vec!["commit", "tag"]);
+    checks.push(&outlier);
+    let mut summary = Summary::new(vec![], status, vec![], checks);
+    assert_eq!(summary.exit_status(), 1 << 1 | 1 << 4 | 1 << 5);
+
+    summary.gate_on(Gate::Preserve);
+    assert_eq!(summary.exit_status(), 1 << 1);
+
+    summary.gate_on(Gate::Release);
+    assert_eq!(summary.exit_status(), 1 << 4);
+    summary.exit_by(ExitMode::Weight);
+    assert_eq!(summary.exit_status(), 1);
+
+    summary.gate_on(Gate::All);
+    assert_eq!(summary.exit_status(), 3);
+  }
+
+  #[test]
+  fn checks_sorted_by_group_then_label() {
+    let mut summary = summary_of(Check::tagged_checks(vec!["tag", "push", "commit", "merge", "stage"]));
+    let declared = summary.items().iter().map(|i| i.check.label).collect::<Vec<_>>();
+    assert_eq!(declared[..2], ["all commits pushed to remote", "all commits merged from remote"]);
+
+    summary.sort_checks();
+    let sorted = summary.items().iter().map(|i| (i.check.status_group, i.check.label)).collect::<Vec<_>>();
+    assert_eq!(sorted, vec![
+      (1, "no uncommited changes"),
+      (1, "no unstaged changes"),
+      (2, "all commits pushed to remote"),
+      (3, "all commits merged from remote"),
+      (4, "current commit is tagged"),
+    ]);
+  }
+
+  #[test]
+  fn custom_check_errors() {
+    assert!(Check::from_def(def("staged >")).is_err());
+    let mut bad_source = def("staged > 5");
+    bad_source.required_data = vec!["network".into()];
+    assert!(Check::from_def(bad_source).err().unwrap().contains("unknown data source 'network'"));
+    let mut clashing = def("staged > 5");
+    clashing.tags = vec!["few_staged".into(), "stage".into()];
+    assert_eq!(Check::from_def(clashing).err().unwrap(), "check 'few staged files': tag 'stage' is taken by check 'no unstaged changes'");
+    let mut local_primary = def("staged > 5");
+    local_primary.tags = vec!["local".into()];
+    assert!(Check::from_def(local_primary).is_err());
+    let mut everything = def("staged > 5");
+    everything.tags = vec!["all".into()];
+    assert!(Check::from_def(everything).is_err());
+    // group tags can be joined
+    let mut local = def("staged > 5");
+    local.tags = vec!["few_staged".into(), "local".into()];
+    assert!(Check::from_def(local).is_ok());
+  }
+
+  #[test]
+  fn custom_checks_clash_with_each_other() {
+    let first = Check::from_def(def("staged > 5")).unwrap();
+    let mut registered = Check::all_checks();
+    registered.push(&first);
+    let mut same = def("staged > 10");
+    same.label = "lots of staged files".into();
+    assert_eq!(Check::from_def_among(same, &registered).err().unwrap(),
+      "check 'lots of staged files': tag 'few_staged' is taken by check 'few staged files'");
+    let mut joining = def("staged > 10");
+    joining.tags = vec!["many_staged".into(), "few_staged".into()];
+    assert!(Check::from_def_among(joining, &registered).is_err());
+    let mut apart = def("staged > 10");
+    apart.tags = vec!["many_staged".into(), "local".into()];
+    assert!(Check::from_def_among(apart, &registered).is_ok());
+  }
+
+  #[test]
+  fn custom_checks_need_status() {
+    let mut unlisted = def("staged > 5");
+    unlisted.required_data = vec![];
+    assert!(Check::from_def(unlisted).unwrap().required_data == STATUS);
+    let mut more = def("staged > 5");
+    more.required_data = vec!["remote".into()];
+    assert!(Check::from_def(more).unwrap().required_data == STATUS | REMOTE);
+  }
+
+  #[test]
+  fn exit_status_below_signal_bit() {
+    let mut highest = def("staged == 0");
+    highest.status_group = MAX_STATUS_GROUP;
+    let highest = Check::from_def(highest).unwrap();
+    let mut checks = Check::all_checks();
+    checks.push(&highest);
+
+    let summary = summary_of(checks);
+    assert_eq!(summary.exit_status() & !0x7f, 0);
+    assert_ne!(summary.exit_status() & (1 << MAX_STATUS_GROUP), 0);
+
+    let mut beyond = def("staged == 0");
+    beyond.status_group = MAX_STATUS_GROUP + 1;
+    assert!(Check::from_def(beyond).is_err());
+  }
+
+  #[test]
+  fn commits_since_latest_tag() {
+    let since = |describe: &str| {
+      let mut summary = summary_of(Check::tagged_checks(vec!["since_tag"]));
+      summary.described = git::parse::describe(describe).unwrap();
+      let item = summary.items().remove(0);
+      (item.result, item.detail)
+    };
+
+    assert!(matches!(since("v1.0-5-gabc123\n"), (CheckResult::Bad(5), Some(Detail::Note(note))) if note == "since v1.0"));
+    assert!(matches!(since("v1.0-0-gabc123\n"), (CheckResult::Passed, None)));
+    assert!(matches!(since(""), (CheckResult::Passed, Some(Detail::Note(note))) if note == "no tags yet"));
+  }
+
+  #[test]
+  fn ignored_trees_summarized() {
+    let mut status = String::from("# branch.oid 0a03ba3cfde6472cb7431958dd78ca2c0d65de74\n# branch.head main\n");
+    for n in 0..4012 {
+      status.push_str(&format!("! node_modules/pkg{}/index.js\n", n));
+    }
+    status.push_str("! .env\n! build/app.log\n! target/\n! node_modules/.bin/tsc\n");
+    let mut summary = Summary::new(vec![], git::parse::status(&status).unwrap(), vec![], Check::tagged_checks(vec!["ignored"]));
+
+    let item = summary.items().remove(0);
+    assert!(matches!(item.result, CheckResult::Warn(Some(4016))));
+    let group = |path: &str, files| PathGroup { path: path.into(), files };
+    match item.detail {
+      Some(Detail::Ignored { groups, paths }) => {
+        assert_eq!(groups, vec![
+          group("node_modules/", 4013),
+          group(".env", 1),
+          group("build/app.log", 1),
+          group("target/", 1),
+        ]);
+        assert_eq!(paths.len(), 4016);
+        assert_eq!(paths[0], WorkPath::from("node_modules/pkg0/index.js"));
+      }
+      other => panic!("not ignored paths: {:?}", other),
+    }
+
+    summary.sort_paths(PathOrder::Path);
+    let json = serde_json::to_value(summary.report()).unwrap();
+    let ignored = &json["items"][0]["detail"]["ignored"];
+    assert_eq!(ignored["groups"][0], serde_json::json!({"path": ".env", "files": 1}));
+    assert_eq!(ignored["groups"][2], serde_json::json!({"path": "node_modules/", "files": 4013}));
+    assert_eq!(ignored["paths"][0], ".env");
+  }
+
+  #[test]
+  fn operation_in_progress() {
+    let mut summary = summary_of(Check::tagged_checks(vec!["in_progress"]));
+    assert!(summary.passed());
+    assert_eq!(summary.items()[0].detail, None);
+
+    summary.in_progress = vec![git::Operation::Rebase, git::Operation::Bisect];
+    let item = summary.items().remove(0);
+    assert!(matches!(item.result, CheckResult::Failed));
+    assert_eq!(item.detail, Some(Detail::Note("rebase, bisect in progress".into())));
+    assert_eq!(summary.exit_status(), 1 << 1);
+  }
+
+  #[test]
+  fn tracked_files_ignored() {
+    let mut summary = summary_of(Check::tagged_checks(vec!["ignore_conflict"]));
+    assert!(matches!(summary.items()[0].result, CheckResult::Passed));
+    assert!(summary.items()[0].detail.is_none());
+
+    summary.tracked_ignored = git::parse::ls_files("vendor/build.log\0config/local.yml\0").unwrap();
+    summary.sort_paths(PathOrder::Path);
+    let item = summary.items().remove(0);
+    assert!(matches!(item.result, CheckResult::Bad(2)));
+    assert_eq!(item.detail, Some(Detail::Paths(vec!["config/local.yml".into(), "vendor/build.log".into()])));
+  }
+
+  #[test]
+  fn porcelain_format() {
+    let summary = summary_of(Check::all_checks());
+    assert_eq!(summary.porcelain(), include_str!("testdata/porcelain-empty-status"));
+  }
+}
@@ -0,0 +1,187 @@
+//! Predicates for custom checks: integer expressions over the counts a
+//! `Summary` can report, with comparisons, `&&`, `||`, `!` and parentheses.
+//! As in C, comparisons produce 1 or 0, and any non-zero value is true.
+//!
+//!     untracked > 0 && unstaged == 0
+use nom::{
+  branch::alt,
+  bytes::complete::{tag, take_while1},
+  character::complete::{digit1, multispace0},
+  combinator::{map, map_res},
+  multi::many0,
+  sequence::{delimited, pair, preceded, tuple},
+  IResult,
+};
+
+/// The counts a predicate can refer to.
+pub const VARIABLES: &[&str] = &[
+  "untracked",
+  "ignored",
+  "staged",
+  "unstaged",
+  "conflicts",
+  "ahead",
+  "behind",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+  Num(i64),
+  Var(String),
+  Not(Box<Expr>),
+  Cmp(Box<Expr>, Op, Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Expr {
+  pub fn eval(&self, var: &dyn Fn(&str) -> i64) -> i64 {
+    use Expr::*;
+    match self {
+      Num(n) => *n,
+      Var(name) => var(name),
+      Not(e) => (e.eval(var) == 0) as i64,
+      And(l, r) => (l.eval(var) != 0 && r.eval(var) != 0) as i64,
+      Or(l, r) => (l.eval(var) != 0 || r.eval(var) != 0) as i64,
+      Cmp(l, op, r) => {
+        let (l, r) = (l.eval(var), r.eval(var));
+        (match op {
+          Op::Eq => l == r,
+          Op::Ne => l != r,
+          Op::Lt => l < r,
+          Op::Le => l <= r,
+          Op::Gt => l > r,
+          Op::Ge => l >= r,
+        }) as i64
+      }
+    }
+  }
+
+  fn unknown_variable(&self) -> Option<&str> {
+    use Expr::*;
+    match self {
+      Num(_) => None,
+      Var(name) if VARIABLES.contains(&name.as_str()) => None,
+      Var(name) => Some(name),
+      Not(e) => e.unknown_variable(),
+      And(l, r) | Or(l, r) | Cmp(l, _, r) => l.unknown_variable().or_else(|| r.unknown_variable()),
+    }
+  }
+}
+
+pub fn parse(input: &str) -> Result<Expr, String> {
+  match or_expr(input) {
+    Ok(("", e)) => match e.unknown_variable() {
+      None => Ok(e),
+      Some(name) => Err(format!("unknown count '{}' (known: {})", name, VARIABLES.join(", "))),
+    },
+    Ok((rest, _)) => Err(format!("couldn't parse expression at: {}", rest)),
+    Err(_) => Err(format!("couldn't parse expression: {}", input)),
+  }
+}
+
+fn ws<'a, O>(p: impl Fn(&'a str) -> IResult<&'a str, O>) -> impl Fn(&'a str) -> IResult<&'a str, O> {
+  delimited(multispace0, p, multispace0)
+}
+
+fn or_expr(input: &str) -> IResult<&str, Expr> {
+  let (i, first) = and_expr(input)?;
+  let (i, rest) = many0(preceded(ws(tag("||")), and_expr))(i)?;
+  Ok((i, rest.into_iter().fold(first, |l, r| Expr::Or(Box::new(l), Box::new(r)))))
+}
+
+fn and_expr(input: &str) -> IResult<&str, Expr> {
+  let (i, first) = unary(input)?;
+  let (i, rest) = many0(preceded(ws(tag("&&")), unary))(i)?;
+  Ok((i, rest.into_iter().fold(first, |l, r| Expr::And(Box::new(l), Box::new(r)))))
+}
+
+fn unary(input: &str) -> IResult<&str, Expr> {
+  alt((
+      map(preceded(ws(tag("!")), unary), |e| Expr::Not(Box::new(e))),
+      comparison,
+  ))(input)
+}
+
+fn comparison(input: &str) -> IResult<&str, Expr> {
+  let (i, left) = atom(input)?;
+  match pair(ws(op), atom)(i) {
+    Ok((i, (op, right))) => Ok((i, Expr::Cmp(Box::new(left), op, Box::new(right)))),
+    Err(_) => Ok((i, left)),
+  }
+}
+
+fn op(input: &str) -> IResult<&str, Op> {
+  alt((
+      map(tag("=="), |_| Op::Eq),
+      map(tag("!="), |_| Op::Ne),
+      map(tag("<="), |_| Op::Le),
+      map(tag(">="), |_| Op::Ge),
+      map(tag("<"), |_| Op::Lt),
+      map(tag(">"), |_| Op::Gt),
+  ))(input)
+}
+
+fn atom(input: &str) -> IResult<&str, Expr> {
+  ws(alt((
+      map_res(digit1, |n: &str| n.parse().map(Expr::Num)),
+      map(take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'), |v: &str| Expr::Var(v.into())),
+      map(tuple((tag("("), or_expr, tag(")"))), |(_, e, _)| e),
+  )))(input)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn counts(name: &str) -> i64 {
+    match name {
+      "untracked" => 3,
+      "unstaged" => 0,
+      _ => 1,
+    }
+  }
+
+  #[test]
+  fn parse_precedence() {
+    assert_eq!(
+      parse("untracked > 0 && unstaged == 0 || !ahead").unwrap(),
+      Expr::Or(
+        Box::new(Expr::And(
+            Box::new(Expr::Cmp(Box::new(Expr::Var("untracked".into())), Op::Gt, Box::new(Expr::Num(0)))),
+            Box::new(Expr::Cmp(Box::new(Expr::Var("unstaged".into())), Op::Eq, Box::new(Expr::Num(0)))),
+        )),
+        Box::new(Expr::Not(Box::new(Expr::Var("ahead".into())))),
+      )
+    );
+  }
+
+  #[test]
+  fn evaluation() {
+    assert_eq!(parse("untracked > 0 && unstaged == 0").unwrap().eval(&counts), 1);
+    assert_eq!(parse("untracked >= 4").unwrap().eval(&counts), 0);
+    assert_eq!(parse("!(staged < 2) || untracked != 3").unwrap().eval(&counts), 0);
+    assert_eq!(parse("untracked").unwrap().eval(&counts), 3);
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert!(parse("untracked >").is_err());
+    assert!(parse("untracked > 0 &&").is_err());
+    assert!(parse("(untracked > 0").is_err());
+    assert_eq!(
+      parse("dirty > 0").unwrap_err(),
+      "unknown count 'dirty' (known: untracked, ignored, staged, unstaged, conflicts, ahead, behind)"
+    );
+  }
+}
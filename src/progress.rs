@@ -0,0 +1,60 @@
+//! A spinner on stderr, so that waiting on a slow git command doesn't look
+//! like a hang.
+//!
+//! Timing makes this awkward to unit test. To check it by hand, point the
+//! remote somewhere slow and watch the spinner come and go:
+//!
+//!     git remote set-url origin https://10.255.255.1/repo.git
+//!     confit              # spins beside "contacting remote…", then clears
+//!     confit 2>/dev/null  # not a terminal: no spinner
+//!     confit --json       # no spinner
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Whether a spinner would be seen: stderr has to be a terminal.
+pub fn visible() -> bool {
+  atty::is(atty::Stream::Stderr)
+}
+
+/// Spins until dropped, then clears its line.
+pub struct Spinner {
+  done: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+  pub fn start(message: &'static str) -> Self {
+    let done = Arc::new(AtomicBool::new(false));
+    let spinning = done.clone();
+    let thread = thread::spawn(move || {
+      let stderr = std::io::stderr();
+      for frame in FRAMES.iter().cycle() {
+        if spinning.load(Ordering::Relaxed) {
+          break
+        }
+        let mut err = stderr.lock();
+        let _ = write!(err, "\r{} {}", frame, message);
+        let _ = err.flush();
+        drop(err);
+        thread::park_timeout(Duration::from_millis(100));
+      }
+      let _ = write!(stderr.lock(), "\r\x1b[K");
+    });
+    Spinner { done, thread: Some(thread) }
+  }
+}
+
+impl Drop for Spinner {
+  fn drop(&mut self) {
+    self.done.store(true, Ordering::Relaxed);
+    if let Some(thread) = self.thread.take() {
+      thread.thread().unpark();
+      let _ = thread.join();
+    }
+  }
+}
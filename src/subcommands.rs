@@ -22,10 +22,169 @@ pub(crate) mod write_templates {
     }
 
     for (name, body) in &*TEMPLATES {
-      let tpath = dir.join(name);
+      let tpath = dir.join(name).with_extension("txt");
       println!("{:?}", tpath);
       let mut tfile = File::create(tpath).unwrap_or_else(&error_status(1));
       tfile.write(body.as_bytes()).unwrap_or_else(&error_status(1));
     }
   }
 }
+
+pub(crate) mod schema {
+  use clap::{App, SubCommand, ArgMatches};
+  use crate::preserves::Report;
+
+  pub(crate) fn def() -> App<'static, 'static> {
+    SubCommand::with_name("schema")
+      .about("Emits a JSON Schema describing the --json output")
+  }
+
+  pub(crate) fn run(_: &ArgMatches) {
+    println!("{}", document())
+  }
+
+  pub(crate) fn document() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(Report)).expect("schema serializes")
+  }
+}
+
+pub(crate) mod doctor {
+  use clap::{App, SubCommand, ArgMatches};
+  use crate::doctor;
+  use crate::git::exec::System;
+
+  pub(crate) fn def() -> App<'static, 'static> {
+    SubCommand::with_name("doctor")
+      .about("Checks that git, the work tree and its remote are set up for confit to run meaningfully")
+  }
+
+  pub(crate) fn run(_: &ArgMatches) {
+    let probes = doctor::diagnose(&System);
+    for probe in &probes {
+      println!("{}", probe)
+    }
+    if doctor::failed(&probes) {
+      std::process::exit(1)
+    }
+  }
+}
+
+pub(crate) mod check_templates {
+  use clap::{App, SubCommand, Arg, ArgMatches};
+  use std::path::Path;
+  use tera::Template;
+  use crate::{load_templates, template_error, template_files};
+
+  pub(crate) fn def() -> App<'static, 'static> {
+    SubCommand::with_name("check-templates")
+      .about("Parses each template in a directory for --template, reporting any errors, without running git")
+      .arg(Arg::with_name("directory")
+        .required(true))
+  }
+
+  pub(crate) fn run(args: &ArgMatches) {
+    let dirname = args.value_of("directory").expect("directory is required");
+    let dir = Path::new(dirname);
+    if !dir.is_dir() {
+      println!("{} is not a directory!", dirname);
+      std::process::exit(1)
+    }
+
+    let results = check(dir);
+    for (name, result) in &results {
+      match result {
+        Ok(()) => println!("ok     {}", name),
+        Err(e) => print!("error  {}", e),
+      }
+    }
+    if results.iter().any(|(_, result)| result.is_err()) {
+      std::process::exit(1)
+    }
+  }
+
+  /// Parses every template in the directory, as --template would load it,
+  /// then (if each parses) all of them together, which resolves imports and
+  /// inheritance between them.
+  pub(crate) fn check(dir: &Path) -> Vec<(String, Result<(), String>)> {
+    let mut results: Vec<_> = template_files(dir).into_iter().map(|(name, path)| {
+      let result = std::fs::read_to_string(&path)
+        .map_err(|e| format!("couldn't read {}: {}\n", name, e))
+        .and_then(|body| {
+          Template::new(&name, Some(path.to_string_lossy().into_owned()), &body)
+            .map(|_| ())
+            .map_err(|e| template_error(&name, &e))
+        });
+      (name, result)
+    }).collect();
+
+    if results.iter().all(|(_, result)| result.is_ok()) {
+      if let Err(e) = load_templates(dir) {
+        results.push(("(all together)".into(), Err(e)))
+      }
+    }
+    results
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_template() {
+      let dir = std::env::temp_dir().join(format!("confit-check-templates-{}", std::process::id()));
+      std::fs::create_dir_all(&dir).unwrap();
+      std::fs::write(dir.join("good.txt"), "{% for item in items %}{{ item.glyph }}{% endfor %}").unwrap();
+      std::fs::write(dir.join("broken.txt"), "{{ item.check.label }\n").unwrap();
+      std::fs::write(dir.join("notes.md"), "{{ not a template").unwrap();
+
+      let results = check(&dir);
+      std::fs::remove_dir_all(&dir).unwrap();
+
+      assert_eq!(results.len(), 2);
+      assert_eq!(results[0].0, "broken");
+      let e = results[0].1.as_ref().unwrap_err();
+      assert!(e.starts_with("error in template 'broken':\n"), "{}", e);
+      assert!(e.contains("1:"), "{}", e);
+      assert_eq!(results[1], ("good".to_string(), Ok(())));
+    }
+  }
+}
+
+pub(crate) mod completions {
+  use clap::{App, SubCommand, Arg, ArgMatches, Shell};
+  use std::io::Write;
+  use crate::completion_app;
+
+  pub(crate) fn def() -> App<'static, 'static> {
+    SubCommand::with_name("completions")
+      .about("Writes a shell completion script to stdout, completing check tags and formats as well as options")
+      .arg(Arg::with_name("shell")
+        .required(true)
+        .possible_values(&Shell::variants()))
+  }
+
+  pub(crate) fn run(args: &ArgMatches) {
+    let shell = args.value_of("shell").expect("shell is required").parse().expect("validated");
+    generate(shell, &mut std::io::stdout())
+  }
+
+  pub(crate) fn generate(shell: Shell, out: &mut impl Write) {
+    completion_app().gen_completions_to("confit", shell, out)
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_completions() {
+      let mut out = Vec::new();
+      generate(Shell::Bash, &mut out);
+      let script = String::from_utf8(out).unwrap();
+      assert!(script.contains("--checks"));
+      assert!(script.contains("track_files"));
+      assert!(script.contains("markdown"));
+      assert!(script.contains("completions"));
+    }
+  }
+}